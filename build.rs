@@ -0,0 +1,28 @@
+use std::{env, fs, path::PathBuf};
+
+/// writes the C header of the API in src/ffi.rs and the plugin API in src/plugin.rs to
+/// `$OUT_DIR/stacky.h`, with `STACKY_UPDATE_HEADER` set it also updates the checked-in
+/// include/stacky.h
+fn main() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=src/plugin.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=STACKY_UPDATE_HEADER");
+
+    let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    let header = out.join("stacky.h");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(dir.join("src/ffi.rs"))
+        .with_src(dir.join("src/plugin.rs"))
+        .generate()
+        .expect("the C API in src/ffi.rs cannot be turned into a header")
+        .write_to_file(&header);
+
+    // build scripts only write to OUT_DIR unless they are asked to
+    if env::var_os("STACKY_UPDATE_HEADER").is_some() {
+        fs::copy(&header, dir.join("include/stacky.h")).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+#ifndef STACKY_H
+#define STACKY_H
+
+/* Generated from src/ffi.rs and src/plugin.rs by build.rs, do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+struct StackyPluginApi;
+struct StackyRegistrar;
+
+/*
+ a plugin exports both, the init function is only called if the versions match
+ */
+uint32_t stacky_plugin_abi_version(void);
+int stacky_plugin_init(const struct StackyPluginApi *api, struct StackyRegistrar *registrar);
+
+/*
+ the version of the plugin interface, a plugin built for another version is not loaded
+ */
+#define STACKY_PLUGIN_ABI_VERSION 1
+
+/*
+ an engine for C programs, with what the last call left for the caller
+ */
+typedef struct StackyEngine StackyEngine;
+
+/*
+ collects the words a plugin registers
+ */
+typedef struct StackyRegistrar StackyRegistrar;
+
+/*
+ the stack of a running plugin word
+ */
+typedef struct StackyStack StackyStack;
+
+/*
+ a word of a plugin, returns 0 if it succeeded and anything else if it failed
+ */
+typedef int (*StackyWordFn)(struct StackyStack *stack);
+
+/*
+ the functions a plugin uses, passed to its init function
+ */
+typedef struct StackyPluginApi {
+  /*
+   the ABI version of the loading stacky
+   */
+  uint32_t abi_version;
+  /*
+   adds a word that is called with at least `arity` items on the stack, returns 0 if it
+   succeeded and -1 if the name is no UTF-8
+   */
+  int (*register_word)(struct StackyRegistrar *registrar,
+                       const char *name,
+                       size_t arity,
+                       StackyWordFn word);
+  void (*push_int)(struct StackyStack *stack, int64_t value);
+  /*
+   returns 0 if it succeeded and -1 if the top item is no number
+   */
+  int (*pop_int)(struct StackyStack *stack, int64_t *value);
+  /*
+   pushes a copy, returns 0 if it succeeded and -1 if it is no UTF-8
+   */
+  int (*push_string)(struct StackyStack *stack, const char *value);
+  /*
+   NULL if the top item is no string, free it with `string_free`
+   */
+  char *(*pop_string)(struct StackyStack *stack);
+  void (*string_free)(char *value);
+  /*
+   sets the message of the error the word fails with when it returns
+   */
+  void (*fail)(struct StackyStack *stack, const char *message);
+} StackyPluginApi;
+
+/*
+ creates an engine that knows the words of the prelude, free it with `stacky_engine_free`, NULL
+ if it cannot be created
+ */
+struct StackyEngine *stacky_engine_new(void);
+
+/*
+ frees an engine, NULL is ignored
+
+ # Safety
+
+ `engine` has to be null or come from `stacky_engine_new` and must not be used afterwards
+ */
+void stacky_engine_free(struct StackyEngine *engine);
+
+/*
+ runs source code, returns 0 if it succeeded and -1 if it failed
+
+ # Safety
+
+ `engine` has to come from `stacky_engine_new` and `source` has to be a NUL terminated string
+ */
+int stacky_eval(struct StackyEngine *engine, const char *source);
+
+/*
+ what the last `stacky_eval` printed, the string belongs to the engine and lives until the
+ next eval
+
+ # Safety
+
+ `engine` has to come from `stacky_engine_new`
+ */
+const char *stacky_output(const struct StackyEngine *engine);
+
+/*
+ the message of the error of the last call, NULL if it succeeded, the string belongs to the
+ engine and lives until the next call
+
+ # Safety
+
+ `engine` has to come from `stacky_engine_new`
+ */
+const char *stacky_last_error(const struct StackyEngine *engine);
+
+/*
+ pushes a number
+
+ # Safety
+
+ `engine` has to come from `stacky_engine_new`
+ */
+void stacky_push_int(struct StackyEngine *engine, int64_t value);
+
+/*
+ pops a number into `value`, returns 0 if it succeeded and -1 if the top item is no number
+
+ # Safety
+
+ `engine` has to come from `stacky_engine_new` and `value` has to point to an int64_t
+ */
+int stacky_pop_int(struct StackyEngine *engine, int64_t *value);
+
+/*
+ pushes a copy of a string, returns 0 if it succeeded and -1 if it is no UTF-8
+
+ # Safety
+
+ `engine` has to come from `stacky_engine_new` and `value` has to be a NUL terminated string
+ */
+int stacky_push_string(struct StackyEngine *engine, const char *value);
+
+/*
+ pops a string, NULL if the top item is no string, free it with `stacky_string_free`
+
+ # Safety
+
+ `engine` has to come from `stacky_engine_new`
+ */
+char *stacky_pop_string(struct StackyEngine *engine);
+
+/*
+ frees a string returned by `stacky_pop_string`, NULL is ignored
+
+ # Safety
+
+ `value` has to be null or come from `stacky_pop_string` and must not be used afterwards
+ */
+void stacky_string_free(char *value);
+
+#endif  /* STACKY_H */
@@ -0,0 +1,591 @@
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde::{
+    de::{
+        self,
+        value::{MapDeserializer, SeqDeserializer},
+        DeserializeOwned, IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::{self, Serialize},
+};
+
+use crate::{engine::Value, error::StackyError};
+
+// the bridge maps Rust values to values like this:
+// - booleans are the numbers 1 and 0, like the results of comparisons
+// - integers are numbers, or big integers if they do not fit into 64 bits
+// - chars and strings are strings, bytes are arrays of numbers
+// - None and () are the empty array, Some(x) is an array holding x, so Some(()) is no None
+// - sequences, tuples and tuple structs are arrays, maps and structs are maps
+// - unit variants are their name, other variants are a map from their name to their content
+
+/// turns a Rust value into a value that can be pushed
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, StackyError> {
+    value.serialize(Serializer)
+}
+
+/// turns a popped value into a Rust value
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, StackyError> {
+    T::deserialize(Deserializer { value })
+}
+
+impl ser::Error for StackyError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        StackyError::runtime(message.to_string())
+    }
+}
+
+impl de::Error for StackyError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        StackyError::runtime(message.to_string())
+    }
+}
+
+/// the value of an integer, a big integer if it does not fit into 64 bits
+fn integer(value: impl Into<BigInt>) -> Value {
+    let value = value.into();
+    match value.to_i64() {
+        Some(x) => Value::Number(x),
+        None => Value::BigInt(value),
+    }
+}
+
+/// the map of a variant that is not a unit variant
+fn variant(name: &str, content: Value) -> Value {
+    Value::Map(vec![(Value::from(name), content)])
+}
+
+/// serializes Rust values into values
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = StackyError;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, StackyError> {
+        Ok(Value::Number(v as i64))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, StackyError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, StackyError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, StackyError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, StackyError> {
+        Ok(Value::Number(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, StackyError> {
+        Ok(integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, StackyError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, StackyError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, StackyError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, StackyError> {
+        Ok(integer(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, StackyError> {
+        Ok(integer(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, StackyError> {
+        Ok(Value::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, StackyError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, StackyError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, StackyError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, StackyError> {
+        Ok(Value::Array(
+            v.iter().map(|byte| Value::Number(*byte as i64)).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Value, StackyError> {
+        Ok(Value::Array(vec![]))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, StackyError> {
+        Ok(Value::Array(vec![value.serialize(self)?]))
+    }
+
+    fn serialize_unit(self) -> Result<Value, StackyError> {
+        Ok(Value::Array(vec![]))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, StackyError> {
+        Ok(Value::Array(vec![]))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, StackyError> {
+        Ok(Value::from(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, StackyError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Value, StackyError> {
+        Ok(variant(name, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, StackyError> {
+        Ok(SerializeArray {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, StackyError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, StackyError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, StackyError> {
+        Ok(SerializeArray {
+            variant: Some(name),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeMap, StackyError> {
+        Ok(SerializeMap {
+            variant: None,
+            pairs: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeMap, StackyError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<SerializeMap, StackyError> {
+        Ok(SerializeMap {
+            variant: Some(name),
+            pairs: Vec::with_capacity(len),
+            key: None,
+        })
+    }
+}
+
+/// collects the items of an array, `variant` is the name of a tuple variant
+pub struct SerializeArray {
+    variant: Option<&'static str>,
+    items: Vec<Value>,
+}
+
+impl SerializeArray {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StackyError> {
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        let array = Value::Array(self.items);
+        Ok(match self.variant {
+            Some(name) => variant(name, array),
+            None => array,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Value;
+    type Error = StackyError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StackyError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        SerializeArray::end(self)
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Value;
+    type Error = StackyError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StackyError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        SerializeArray::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Value;
+    type Error = StackyError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StackyError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        SerializeArray::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = Value;
+    type Error = StackyError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StackyError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        SerializeArray::end(self)
+    }
+}
+
+/// collects the pairs of a map, `variant` is the name of a struct variant
+pub struct SerializeMap {
+    variant: Option<&'static str>,
+    pairs: Vec<(Value, Value)>,
+    /// the key whose value comes next
+    key: Option<Value>,
+}
+
+impl SerializeMap {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: Value, value: &T) -> Result<(), StackyError> {
+        self.pairs.push((key, value.serialize(Serializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        let map = Value::Map(self.pairs);
+        Ok(match self.variant {
+            Some(name) => variant(name, map),
+            None => map,
+        })
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = StackyError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), StackyError> {
+        self.key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StackyError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| StackyError::runtime("A map value was serialized before its key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = StackyError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), StackyError> {
+        self.insert(Value::from(key), value)
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+    type Ok = Value;
+    type Error = StackyError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), StackyError> {
+        self.insert(Value::from(key), value)
+    }
+
+    fn end(self) -> Result<Value, StackyError> {
+        SerializeMap::end(self)
+    }
+}
+
+/// deserializes Rust values from a value
+pub struct Deserializer {
+    value: Value,
+}
+
+impl IntoDeserializer<'_, StackyError> for Value {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Deserializer {
+        Deserializer { value: self }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = StackyError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StackyError> {
+        match self.value {
+            Value::Number(x) => visitor.visit_i64(x),
+            // big integers hold numbers that do not fit into an i64
+            Value::BigInt(x) => {
+                if let Some(x) = x.to_u64() {
+                    visitor.visit_u64(x)
+                } else if let Some(x) = x.to_u128() {
+                    visitor.visit_u128(x)
+                } else if let Some(x) = x.to_i128() {
+                    visitor.visit_i128(x)
+                } else {
+                    Err(StackyError::runtime(format!(
+                        "Number {} does not fit into 128 bits",
+                        x
+                    )))
+                }
+            }
+            Value::Float(x) => visitor.visit_f64(x),
+            Value::String(x) => visitor.visit_string(x),
+            Value::Array(items) => {
+                let mut items = SeqDeserializer::new(items.into_iter());
+                let result = visitor.visit_seq(&mut items)?;
+                items.end()?;
+                Ok(result)
+            }
+            Value::Map(pairs) => {
+                let mut pairs = MapDeserializer::new(pairs.into_iter());
+                let result = visitor.visit_map(&mut pairs)?;
+                pairs.end()?;
+                Ok(result)
+            }
+        }
+    }
+
+    /// 0 is false and every other number true
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StackyError> {
+        match self.value {
+            Value::Number(x) => visitor.visit_bool(x != 0),
+            value => Err(StackyError::runtime(format!(
+                "Expected a flag, got {}",
+                value
+            ))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StackyError> {
+        match self.value {
+            Value::Array(mut items) if items.len() <= 1 => match items.pop() {
+                Some(value) => visitor.visit_some(Deserializer { value }),
+                None => visitor.visit_none(),
+            },
+            value => Err(StackyError::runtime(format!(
+                "Expected an empty array or an array of one item, got {}",
+                value
+            ))),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StackyError> {
+        match self.value {
+            Value::Array(items) if items.is_empty() => visitor.visit_unit(),
+            value => Err(StackyError::runtime(format!(
+                "Expected an empty array, got {}",
+                value
+            ))),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, StackyError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, StackyError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, StackyError> {
+        match self.value {
+            Value::String(name) => visitor.visit_enum(Enum {
+                name,
+                content: None,
+            }),
+            Value::Map(pairs) if pairs.len() == 1 => match pairs.into_iter().next() {
+                Some((Value::String(name), content)) => visitor.visit_enum(Enum {
+                    name,
+                    content: Some(content),
+                }),
+                _ => Err(StackyError::runtime(
+                    "Expected the name of a variant as key",
+                )),
+            },
+            value => Err(StackyError::runtime(format!(
+                "Expected the name of a variant or a map with one, got {}",
+                value
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// a variant by its name, unit variants have no content
+struct Enum {
+    name: String,
+    content: Option<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for Enum {
+    type Error = StackyError;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), StackyError> {
+        let name = seed.deserialize(Value::from(self.name.as_str()).into_deserializer())?;
+        Ok((name, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Enum {
+    type Error = StackyError;
+
+    fn unit_variant(self) -> Result<(), StackyError> {
+        match self.content {
+            None => Ok(()),
+            Some(content) => de::Deserialize::deserialize(content.into_deserializer()),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, StackyError> {
+        seed.deserialize(self.content()?.into_deserializer())
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, StackyError> {
+        de::Deserializer::deserialize_seq(self.content()?.into_deserializer(), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, StackyError> {
+        de::Deserializer::deserialize_map(self.content()?.into_deserializer(), visitor)
+    }
+}
+
+impl Enum {
+    fn content(self) -> Result<Value, StackyError> {
+        self.content
+            .ok_or_else(|| StackyError::runtime(format!("Variant {} needs content", self.name)))
+    }
+}
@@ -0,0 +1,707 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    error::StackyError,
+    lexer::Token,
+    parser::{Ast, Node, StackEffect},
+};
+
+/// the kind of entity a stack slot holds
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Kind {
+    Number,
+    Float,
+    String,
+    Pointer,
+    Function,
+    File,
+    Array,
+    Map,
+}
+
+impl Kind {
+    /// the kind of an item in a stack effect comment, like `s:string`
+    fn annotation(item: &str) -> Result<Option<Kind>, StackyError> {
+        let Some((_, kind)) = item.split_once(':') else {
+            return Ok(None);
+        };
+        let kind = match kind {
+            "number" => Kind::Number,
+            "float" => Kind::Float,
+            "string" => Kind::String,
+            "pointer" => Kind::Pointer,
+            "function" => Kind::Function,
+            "file" => Kind::File,
+            "array" => Kind::Array,
+            "map" => Kind::Map,
+            _ => {
+                return Err(StackyError::Check(format!(
+                    "Unknown type {} of {}",
+                    kind, item
+                )))
+            }
+        };
+        Ok(Some(kind))
+    }
+
+    /// the name with its article, like `a number` or `an array`
+    fn article(&self) -> String {
+        match self {
+            Kind::Array => format!("an {}", self),
+            _ => format!("a {}", self),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Kind::Number => "number",
+            Kind::Float => "float",
+            Kind::String => "string",
+            Kind::Pointer => "pointer",
+            Kind::Function => "function",
+            Kind::File => "file",
+            Kind::Array => "array",
+            Kind::Map => "map",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// what is known about a stack slot before running
+#[derive(Debug, PartialEq, Clone)]
+enum Slot {
+    Kind(Kind),
+    Any,
+    /// the nth item that was on the stack before the code ran, 0 is the top
+    Input(usize),
+}
+
+/// the items a word takes from the stack and the items it leaves
+#[derive(Debug, Clone)]
+struct Effect {
+    /// the kinds the inputs must have, the first input is the top of the stack
+    inputs: Vec<Option<Kind>>,
+    /// the items left on the stack from bottom to top
+    outputs: Vec<Slot>,
+}
+
+impl Effect {
+    /// an effect that takes `inputs` items of any kind and leaves the inputs listed in `outputs`
+    fn shuffle(inputs: usize, outputs: &[usize]) -> Self {
+        Self {
+            inputs: vec![None; inputs],
+            outputs: outputs.iter().map(|input| Slot::Input(*input)).collect(),
+        }
+    }
+
+    fn typed(inputs: &[Option<Kind>], outputs: &[Slot]) -> Self {
+        Self {
+            inputs: inputs.to_vec(),
+            outputs: outputs.to_vec(),
+        }
+    }
+}
+
+/// the stack while a piece of code is checked
+#[derive(Debug, Clone)]
+struct State {
+    /// the items pushed by the code, from bottom to top
+    stack: Vec<Slot>,
+    /// the kinds the inputs must have, longer than `taken` if a stack effect declared more inputs
+    inputs: Vec<Option<Kind>>,
+    /// how many items the code took from the stack it started with
+    taken: usize,
+    /// false once the code did something with an unknown effect, the stack then only holds the
+    /// items pushed since
+    exact: bool,
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            stack: vec![],
+            inputs: vec![],
+            taken: 0,
+            exact: true,
+        }
+    }
+
+    /// how much the stack grew
+    fn depth(&self) -> i64 {
+        self.stack.len() as i64 - self.taken as i64
+    }
+
+    fn pop(&mut self) -> Slot {
+        if let Some(slot) = self.stack.pop() {
+            return slot;
+        }
+        let input = self.taken;
+        self.taken += 1;
+        if self.inputs.len() < self.taken {
+            self.inputs.push(None);
+        }
+        Slot::Input(input)
+    }
+
+    fn kind(&self, slot: &Slot) -> Option<Kind> {
+        match slot {
+            Slot::Kind(kind) => Some(*kind),
+            Slot::Any => None,
+            Slot::Input(input) => self.inputs[*input],
+        }
+    }
+
+    /// the kind of the item on top of the stack, if it is known
+    fn top_kind(&self) -> Option<Kind> {
+        match self.stack.last() {
+            Some(slot) => self.kind(slot),
+            None => self.inputs.get(self.taken).copied().flatten(),
+        }
+    }
+
+    /// fails if the slot holds another kind, an input of unknown kind is required to have it
+    fn require(&mut self, slot: &Slot, kind: Kind, word: &str) -> Result<(), StackyError> {
+        match (self.kind(slot), slot) {
+            (Some(actual), _) if actual != kind => Err(StackyError::Check(format!(
+                "{} expects {}, got {}",
+                word,
+                kind.article(),
+                actual.article()
+            ))),
+            (None, Slot::Input(input)) => {
+                self.inputs[*input] = Some(kind);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// takes inputs up to `count` without touching them, they are left at the bottom of the stack
+    fn take_inputs(&mut self, count: usize) {
+        for input in self.taken..count {
+            self.stack.insert(0, Slot::Input(input));
+        }
+        if self.inputs.len() < count {
+            self.inputs.resize(count, None);
+        }
+        self.taken = self.taken.max(count);
+    }
+
+    /// drops what is known about the stack after an effect that is not known
+    fn forget(&mut self) {
+        self.stack.clear();
+        self.exact = false;
+    }
+
+    fn apply(&mut self, effect: &Effect, word: &str) -> Result<(), StackyError> {
+        let inputs: Vec<Slot> = effect.inputs.iter().map(|_| self.pop()).collect();
+        for (slot, kind) in inputs.iter().zip(&effect.inputs) {
+            if let Some(kind) = kind {
+                self.require(slot, *kind, word)?;
+            }
+        }
+        for output in &effect.outputs {
+            self.stack.push(match output {
+                Slot::Input(input) => inputs[*input].clone(),
+                slot => slot.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// the state after one of two pieces of code ran that both started from the same state
+fn merge(mut a: State, mut b: State) -> State {
+    let taken = a.taken.max(b.taken);
+    a.take_inputs(taken);
+    b.take_inputs(taken);
+    let length = a.inputs.len().max(b.inputs.len());
+    a.inputs.resize(length, None);
+    b.inputs.resize(length, None);
+
+    let inputs = a
+        .inputs
+        .iter()
+        .zip(&b.inputs)
+        .map(|(a, b)| a.or(*b))
+        .collect();
+    let stack = a
+        .stack
+        .into_iter()
+        .zip(b.stack)
+        .map(|(a, b)| if a == b { a } else { Slot::Any })
+        .collect();
+    State {
+        stack,
+        inputs,
+        taken,
+        exact: a.exact && b.exact,
+    }
+}
+
+/// infers the stack effect of words and the kinds of the items on the stack before the program
+/// runs, code with an effect that depends on values on the stack is not checked
+pub(crate) struct Checker {
+    /// the effects of the words and variables defined so far, None if a word's effect is unknown
+    effects: HashMap<String, Option<Effect>>,
+}
+
+impl Checker {
+    pub(crate) fn new() -> Self {
+        Self {
+            effects: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn check(&mut self, ast: &Ast) -> Result<(), StackyError> {
+        self.node(ast, &mut State::new(), None)
+    }
+
+    /// checks a single node, `literal` is the number the previous node pushed if it was a literal
+    fn node(
+        &mut self,
+        ast: &Ast,
+        state: &mut State,
+        literal: Option<usize>,
+    ) -> Result<(), StackyError> {
+        match ast {
+            Ast::Number(_) | Ast::BigNumber(_) | Ast::LoopVariable(_) => {
+                state.stack.push(Slot::Kind(Kind::Number))
+            }
+            Ast::Float(_) => state.stack.push(Slot::Kind(Kind::Float)),
+            Ast::StringLiteral(_) => state.stack.push(Slot::Kind(Kind::String)),
+            Ast::GetVariable(_) => state.stack.push(Slot::Any),
+            Ast::SetVariable(name) => {
+                state.pop();
+                let variable = Effect::typed(&[], &[Slot::Any]);
+                self.effects.insert(name.clone(), Some(variable));
+            }
+            Ast::FunctionCall(name) if name == "BASE" => state.stack.push(Slot::Kind(Kind::Number)),
+            Ast::FunctionCall(name) => match self.effects.get(name) {
+                Some(Some(effect)) => state.apply(&effect.clone(), name)?,
+                _ => state.forget(),
+            },
+            Ast::Operation(op) => operation(op, state, literal)?,
+            Ast::Pragma(_) => {}
+            Ast::Expressions(nodes) | Ast::Include { body: nodes, .. } => {
+                self.sequence(nodes, state)?
+            }
+            Ast::WordDefinition { name, effect, body } => {
+                // a word that calls itself has an unknown effect while it is checked
+                self.effects.insert(name.clone(), None);
+                let effect = self.word(name, effect.as_ref(), body)?;
+                self.effects.insert(name.clone(), effect);
+            }
+            Ast::If { if_body, else_body } => {
+                let condition = state.pop();
+                state.require(&condition, Kind::Number, "IF")?;
+
+                let mut a = State {
+                    exact: true,
+                    ..state.clone()
+                };
+                self.sequence(if_body, &mut a)?;
+                let mut b = State {
+                    exact: true,
+                    ..state.clone()
+                };
+                self.sequence(else_body, &mut b)?;
+                if !(a.exact && b.exact) {
+                    state.forget();
+                    return Ok(());
+                }
+                if a.depth() != b.depth() {
+                    return Err(StackyError::Check(format!(
+                        "IF branches change the stack depth differently: by {} and by {}",
+                        a.depth() - state.depth(),
+                        b.depth() - state.depth()
+                    )));
+                }
+                *state = State {
+                    exact: state.exact,
+                    ..merge(a, b)
+                };
+            }
+            Ast::Loop { body } => {
+                let index = state.pop();
+                state.require(&index, Kind::Number, "DO")?;
+                let limit = state.pop();
+                state.require(&limit, Kind::Number, "DO")?;
+
+                let mut repeated = State {
+                    exact: true,
+                    ..state.clone()
+                };
+                self.sequence(body, &mut repeated)?;
+                if !repeated.exact {
+                    state.forget();
+                    return Ok(());
+                }
+                if repeated.depth() != state.depth() {
+                    return Err(StackyError::Check(format!(
+                        "LOOP body changes the stack depth by {}",
+                        repeated.depth() - state.depth()
+                    )));
+                }
+                // the body may also run zero times
+                *state = State {
+                    exact: state.exact,
+                    ..merge(state.clone(), repeated)
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn sequence(&mut self, nodes: &[Node], state: &mut State) -> Result<(), StackyError> {
+        let mut literal = None;
+        for node in nodes {
+            self.node(&node.ast, state, literal)
+                .map_err(|e| e.at(node.span.clone()))?;
+            literal = match &node.ast {
+                Ast::Number(x) => usize::try_from(*x).ok(),
+                _ => None,
+            };
+        }
+        Ok(())
+    }
+
+    /// checks the body of a word against its declared effect and returns the effect for callers
+    fn word(
+        &mut self,
+        name: &str,
+        declared: Option<&StackEffect>,
+        body: &[Node],
+    ) -> Result<Option<Effect>, StackyError> {
+        let mut state = State::new();
+        let mut outputs = vec![];
+        if let Some(declared) = declared {
+            for item in declared.inputs.iter().rev() {
+                state.inputs.push(Kind::annotation(item)?);
+            }
+            for item in &declared.outputs {
+                outputs.push(Kind::annotation(item)?);
+            }
+        }
+        self.sequence(body, &mut state)?;
+
+        let Some(declared) = declared else {
+            return Ok(state.exact.then_some(Effect {
+                inputs: state.inputs,
+                outputs: state.stack,
+            }));
+        };
+        if !state.exact {
+            // callers rely on the declaration when the body can not be checked
+            state.inputs.truncate(declared.inputs.len());
+            let outputs = outputs
+                .into_iter()
+                .map(|kind| kind.map_or(Slot::Any, Slot::Kind))
+                .collect();
+            return Ok(Some(Effect {
+                inputs: state.inputs,
+                outputs,
+            }));
+        }
+
+        // a word may declare items it passes through without touching them
+        let declared_depth = declared.outputs.len() as i64 - declared.inputs.len() as i64;
+        if state.depth() != declared_depth || state.taken > declared.inputs.len() {
+            return Err(StackyError::Check(format!(
+                "{} takes {} and leaves {} items, but is declared as {}",
+                name,
+                state.taken,
+                state.stack.len(),
+                declared
+            )));
+        }
+        state.take_inputs(declared.inputs.len());
+
+        for (index, kind) in outputs.into_iter().enumerate() {
+            let Some(kind) = kind else {
+                continue;
+            };
+            match state.kind(&state.stack[index]) {
+                Some(actual) if actual != kind => {
+                    return Err(StackyError::Check(format!(
+                        "{} leaves {} as {}, but it is declared as {}",
+                        name,
+                        actual.article(),
+                        declared.outputs[index],
+                        kind.article()
+                    )))
+                }
+                Some(_) => {}
+                None => state.stack[index] = Slot::Kind(kind),
+            }
+        }
+        Ok(Some(Effect {
+            inputs: state.inputs,
+            outputs: state.stack,
+        }))
+    }
+}
+
+/// the largest count before PICK, ROLL, >ARRAY, >MAP and PUTS that is checked item by item when
+/// the stack is not known to be as deep, larger counts are checked when the code runs
+const MAX_COUNT: usize = 64;
+
+/// checks a native word, `literal` is the number pushed right before it if there is one
+fn operation(op: &Token, state: &mut State, literal: Option<usize>) -> Result<(), StackyError> {
+    use Kind::{Float, Number};
+    let literal = literal.filter(|n| *n <= MAX_COUNT.max(state.stack.len()));
+    let (word, effect) = match op {
+        Token::Add | Token::Sub | Token::Mul | Token::Div | Token::Percent => {
+            return arithmetic(op, state)
+        }
+        Token::Lt | Token::Gt | Token::Lte | Token::Gte => return comparison(op, state),
+        Token::Puts => return puts(state, literal),
+        Token::IntToFloat => ("S>F", Effect::typed(&[Some(Number)], &[Slot::Kind(Float)])),
+        Token::FloatToInt => ("F>S", Effect::typed(&[Some(Float)], &[Slot::Kind(Number)])),
+        Token::FRound => (
+            "FROUND",
+            Effect::typed(&[Some(Float)], &[Slot::Kind(Float)]),
+        ),
+        Token::FFloor => ("FLOOR", Effect::typed(&[Some(Float)], &[Slot::Kind(Float)])),
+        Token::FTrunc => (
+            "FTRUNC",
+            Effect::typed(&[Some(Float)], &[Slot::Kind(Float)]),
+        ),
+        Token::Key => ("KEY", Effect::typed(&[], &[Slot::Kind(Number)])),
+        Token::Accept => (
+            "ACCEPT",
+            Effect::typed(&[Some(Number)], &[Slot::Kind(Kind::String)]),
+        ),
+        // READ-LINE reads a line of a file if a file is on top of the stack and of the input
+        // otherwise, which is only known if the kind of the top item is
+        Token::ReadLine => match state.top_kind() {
+            Some(Kind::File) => (
+                "READ-LINE",
+                Effect::typed(
+                    &[Some(Kind::File)],
+                    &[
+                        Slot::Kind(Kind::String),
+                        Slot::Kind(Number),
+                        Slot::Kind(Number),
+                    ],
+                ),
+            ),
+            Some(_) => (
+                "READ-LINE",
+                Effect::typed(&[], &[Slot::Kind(Kind::String), Slot::Kind(Number)]),
+            ),
+            None => {
+                state.forget();
+                return Ok(());
+            }
+        },
+        Token::ReadOnly | Token::WriteOnly | Token::ReadWrite => {
+            ("", Effect::typed(&[], &[Slot::Kind(Number)]))
+        }
+        Token::OpenFile | Token::CreateFile => {
+            let word = if *op == Token::OpenFile {
+                "OPEN-FILE"
+            } else {
+                "CREATE-FILE"
+            };
+            let inputs = [Some(Number), Some(Kind::String)];
+            let outputs = [Slot::Kind(Kind::File), Slot::Kind(Number)];
+            (word, Effect::typed(&inputs, &outputs))
+        }
+        Token::ReadFile => (
+            "READ-FILE",
+            Effect::typed(
+                &[Some(Kind::File), Some(Number)],
+                &[Slot::Kind(Kind::String), Slot::Kind(Number)],
+            ),
+        ),
+        Token::WriteFile => (
+            "WRITE-FILE",
+            Effect::typed(
+                &[Some(Kind::File), Some(Kind::String)],
+                &[Slot::Kind(Number)],
+            ),
+        ),
+        Token::CloseFile => (
+            "CLOSE-FILE",
+            Effect::typed(&[Some(Kind::File)], &[Slot::Kind(Number)]),
+        ),
+        Token::FileSize => (
+            "FILE-SIZE",
+            Effect::typed(
+                &[Some(Kind::File)],
+                &[Slot::Kind(Number), Slot::Kind(Number)],
+            ),
+        ),
+        Token::DeleteFile => (
+            "DELETE-FILE",
+            Effect::typed(&[Some(Kind::String)], &[Slot::Kind(Number)]),
+        ),
+        Token::Plugin => ("PLUGIN", Effect::typed(&[Some(Kind::String)], &[])),
+        Token::NumberIn => (
+            "NUMBER-IN",
+            Effect::typed(&[], &[Slot::Kind(Number), Slot::Kind(Number)]),
+        ),
+        // >ARRAY and >MAP take as many items or pairs as the number on top of the stack says
+        Token::ToArray | Token::ToMap => {
+            let (word, kind, size) = if *op == Token::ToArray {
+                (">ARRAY", Kind::Array, 1)
+            } else {
+                (">MAP", Kind::Map, 2)
+            };
+            let Some(items) = literal.and_then(|n| n.checked_mul(size)) else {
+                let count = state.pop();
+                state.require(&count, Number, word)?;
+                state.forget();
+                return Ok(());
+            };
+            let mut inputs = vec![None; items + 1];
+            inputs[0] = Some(Number);
+            (word, Effect::typed(&inputs, &[Slot::Kind(kind)]))
+        }
+        // ARRAY> and MAP> leave as many items as there are in the array or map
+        Token::FromArray | Token::FromMap => {
+            let (word, kind) = if *op == Token::FromArray {
+                ("ARRAY>", Kind::Array)
+            } else {
+                ("MAP>", Kind::Map)
+            };
+            let container = state.pop();
+            state.require(&container, kind, word)?;
+            state.forget();
+            return Ok(());
+        }
+        // GET takes a key of a map or an index of an array
+        Token::Get => ("", Effect::typed(&[None, None], &[Slot::Any])),
+        // PICK and ROLL take as many items as the number on top of the stack says
+        Token::Pick | Token::Roll => {
+            let word = if *op == Token::Pick { "PICK" } else { "ROLL" };
+            let Some(n) = literal else {
+                let index = state.pop();
+                state.require(&index, Number, word)?;
+                state.forget();
+                return Ok(());
+            };
+            let mut outputs: Vec<usize> = (1..=n + 1).rev().collect();
+            if *op == Token::Roll {
+                outputs.remove(0);
+            }
+            outputs.push(n + 1);
+            let mut effect = Effect::shuffle(n + 2, &outputs);
+            effect.inputs[0] = Some(Number);
+            (word, effect)
+        }
+        // the rest take items of any kind
+        Token::Eq => ("=", Effect::typed(&[None, None], &[Slot::Kind(Number)])),
+        Token::Emit | Token::Drop | Token::ToR => ("", Effect::shuffle(1, &[])),
+        Token::Dup => ("", Effect::shuffle(1, &[0, 0])),
+        // SWAP puts the items back in the order they were
+        Token::Swap => ("", Effect::shuffle(2, &[1, 0])),
+        Token::Hex | Token::Decimal | Token::Binary => ("", Effect::shuffle(0, &[])),
+        Token::Over => ("", Effect::shuffle(2, &[1, 0, 1])),
+        Token::Rot => ("", Effect::shuffle(3, &[1, 0, 2])),
+        Token::MinusRot => ("", Effect::shuffle(3, &[0, 2, 1])),
+        Token::Nip => ("", Effect::shuffle(2, &[0])),
+        Token::Tuck => ("", Effect::shuffle(2, &[0, 1, 0])),
+        Token::TwoDup => ("", Effect::shuffle(2, &[1, 0, 1, 0])),
+        Token::TwoDrop | Token::TwoToR => ("", Effect::shuffle(2, &[])),
+        Token::TwoSwap => ("", Effect::shuffle(4, &[1, 0, 3, 2])),
+        Token::TwoOver => ("", Effect::shuffle(4, &[3, 2, 1, 0, 3, 2])),
+        Token::Depth => ("", Effect::typed(&[], &[Slot::Kind(Number)])),
+        Token::FromR | Token::RFetch => ("", Effect::typed(&[], &[Slot::Any])),
+        Token::TwoFromR => ("", Effect::typed(&[], &[Slot::Any, Slot::Any])),
+        _ => {
+            state.forget();
+            return Ok(());
+        }
+    };
+    state.apply(&effect, word)
+}
+
+/// + - * / % take two numbers or floats, + also joins two strings
+fn arithmetic(op: &Token, state: &mut State) -> Result<(), StackyError> {
+    let word = match op {
+        Token::Add => "+",
+        Token::Sub => "-",
+        Token::Mul => "*",
+        Token::Div => "/",
+        _ => "%",
+    };
+    let a = state.pop();
+    let b = state.pop();
+    let result = match (state.kind(&b), state.kind(&a)) {
+        (Some(Kind::String), Some(Kind::String)) if *op == Token::Add => Slot::Kind(Kind::String),
+        (Some(Kind::String), None) if *op == Token::Add => {
+            state.require(&a, Kind::String, word)?;
+            Slot::Kind(Kind::String)
+        }
+        (None, Some(Kind::String)) if *op == Token::Add => {
+            state.require(&b, Kind::String, word)?;
+            Slot::Kind(Kind::String)
+        }
+        (Some(Kind::Number), Some(Kind::Number)) => Slot::Kind(Kind::Number),
+        (b, a) => {
+            numeric(word, b, a)?;
+            if b == Some(Kind::Float) || a == Some(Kind::Float) {
+                Slot::Kind(Kind::Float)
+            } else {
+                Slot::Any
+            }
+        }
+    };
+    state.stack.push(result);
+    Ok(())
+}
+
+/// < > <= >= compare two numbers or floats
+fn comparison(op: &Token, state: &mut State) -> Result<(), StackyError> {
+    let word = match op {
+        Token::Lt => "<",
+        Token::Gt => ">",
+        Token::Lte => "<=",
+        _ => ">=",
+    };
+    let a = state.pop();
+    let b = state.pop();
+    numeric(word, state.kind(&b), state.kind(&a))?;
+    state.stack.push(Slot::Kind(Kind::Number));
+    Ok(())
+}
+
+fn numeric(word: &str, b: Option<Kind>, a: Option<Kind>) -> Result<(), StackyError> {
+    let is_numeric = |kind| matches!(kind, None | Some(Kind::Number | Kind::Float));
+    if is_numeric(b) && is_numeric(a) {
+        return Ok(());
+    }
+    let name = |kind: Option<Kind>| kind.map_or("value".to_string(), |kind| kind.to_string());
+    Err(StackyError::Check(format!(
+        "{} cannot take a {} and a {}",
+        word,
+        name(b),
+        name(a)
+    )))
+}
+
+/// PUTS prints a string, or takes a number n and prints the n characters below it
+fn puts(state: &mut State, literal: Option<usize>) -> Result<(), StackyError> {
+    let top = state.pop();
+    match (state.kind(&top), literal) {
+        (Some(Kind::String), _) => {}
+        (_, Some(n)) => {
+            for _ in 0..n {
+                let c = state.pop();
+                state.require(&c, Kind::Number, "PUTS")?;
+            }
+        }
+        (None | Some(Kind::Number), None) => state.forget(),
+        (Some(kind), None) => {
+            return Err(StackyError::Check(format!(
+                "PUTS expects a string or a number, got a {}",
+                kind
+            )))
+        }
+    }
+    Ok(())
+}
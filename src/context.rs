@@ -1,11 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::{parser::Ast, stack_machine::Entity};
+use crate::{error::StackyError, parser::Node, stack_machine::Entity};
 
 #[derive(Debug, Clone)]
 pub(crate) enum Variable {
-    Function {body: Vec<Ast>},
-    Variable (Entity),
+    Function {
+        body: Vec<Node>,
+    },
+    /// a word of the host program, registered with the interpreter
+    Native,
+    Value(Entity),
 }
 
 #[derive(Debug, Clone)]
@@ -17,17 +21,17 @@ pub(crate) struct Context {
 impl Context {
     pub(crate) fn new(parent: Option<Box<Context>>) -> Self {
         Self {
-            parent: parent,
+            parent,
             variables: HashMap::new(),
         }
     }
 
-    pub(crate) fn get(&self, name: String) -> Variable {
+    pub(crate) fn get(&self, name: String) -> Result<Variable, StackyError> {
         match self.variables.get(&name) {
-            Some(var) => var.clone(),
+            Some(var) => Ok(var.clone()),
             None => match &self.parent {
                 Some(parent) => parent.get(name),
-                None => panic!("Word not found"),
+                None => Err(StackyError::runtime("Word not found")),
             },
         }
     }
@@ -35,4 +39,27 @@ impl Context {
     pub(crate) fn set(&mut self, name: String, var: Variable) {
         self.variables.insert(name, var);
     }
+
+    /// the variables visible from this context with their values, sorted by name
+    pub(crate) fn variables(&self) -> Vec<(String, Entity)> {
+        let mut names: Vec<String> = self.names().into_iter().collect();
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| match self.get(name.clone()) {
+                Ok(Variable::Value(value)) => Some((name, value)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// names of all words and variables visible from this context
+    pub(crate) fn names(&self) -> HashSet<String> {
+        let mut names = match &self.parent {
+            Some(parent) => parent.names(),
+            None => HashSet::new(),
+        };
+        names.extend(self.variables.keys().cloned());
+        names
+    }
 }
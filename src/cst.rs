@@ -0,0 +1,390 @@
+use std::iter::Peekable;
+
+use crate::{
+    error::StackyError,
+    lexer::{is_native, Lexeme, LexemeKind, Token},
+    parser::{atom, Ast, Node, Pragma},
+};
+
+/// what a node of the concrete syntax tree stands for
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum SyntaxKind {
+    /// a whole source, the last child is the EOF token
+    Program,
+    /// `: name ( effect ) body ;`
+    WordDefinition,
+    /// `IF body ELSE body THEN`
+    If,
+    /// `DO body LOOP`
+    Loop,
+    /// `-> name`
+    SetVariable,
+    /// `@ name`
+    GetVariable,
+    /// `PRAGMA key value`
+    Pragma,
+    /// `INCLUDE path` or `REQUIRE path`
+    Include,
+}
+
+/// the concrete syntax tree keeps every token, whitespace and comment with its span, so its text
+/// is the source it was built from
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Cst {
+    Leaf(Lexeme),
+    Node {
+        kind: SyntaxKind,
+        children: Vec<Cst>,
+    },
+}
+
+impl Cst {
+    /// builds the tree of a lossless token stream, which never fails: words, IFs and DOs that are
+    /// not closed end with the input and the errors are left to [`Cst::lower`]
+    pub(crate) fn build(lexemes: Vec<Lexeme>) -> Cst {
+        let mut lexemes = lexemes.into_iter().peekable();
+        let mut children = sequence(&mut lexemes, &[]);
+        children.extend(lexemes.map(Cst::Leaf));
+        Cst::Node {
+            kind: SyntaxKind::Program,
+            children,
+        }
+    }
+
+    /// the source the tree was built from
+    pub(crate) fn text(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text);
+        text
+    }
+
+    fn write(&self, text: &mut String) {
+        match self {
+            Cst::Leaf(lexeme) => text.push_str(&lexeme.text),
+            Cst::Node { children, .. } => children.iter().for_each(|child| child.write(text)),
+        }
+    }
+
+    /// the first token of the node, none if it only holds whitespace and comments
+    pub(crate) fn first_token(&self) -> Option<&Lexeme> {
+        match self {
+            Cst::Leaf(lexeme) => is_token(lexeme).then_some(lexeme),
+            Cst::Node { children, .. } => children.iter().find_map(Cst::first_token),
+        }
+    }
+
+    /// the last token of the node, none if it only holds whitespace and comments
+    pub(crate) fn last_token(&self) -> Option<&Lexeme> {
+        match self {
+            Cst::Leaf(lexeme) => is_token(lexeme).then_some(lexeme),
+            Cst::Node { children, .. } => children.iter().rev().find_map(Cst::last_token),
+        }
+    }
+
+    /// the token of a leaf, none for whitespace, comments and nodes
+    pub(crate) fn token(&self) -> Option<&Token> {
+        match self {
+            Cst::Leaf(Lexeme {
+                kind: LexemeKind::Token(token),
+                ..
+            }) => Some(token),
+            _ => None,
+        }
+    }
+
+    /// lowers the tree of a program to the ast that runs, the syntax errors are raised here
+    pub(crate) fn lower(&self) -> Result<Ast, StackyError> {
+        self.lower_program(false)
+    }
+
+    /// like [`Cst::lower`], but definitions of words that are implemented natively are dropped
+    /// instead of replacing the native word
+    pub(crate) fn lower_preferring_native(&self) -> Result<Ast, StackyError> {
+        self.lower_program(true)
+    }
+
+    fn lower_program(&self, prefer_native: bool) -> Result<Ast, StackyError> {
+        let program = match self {
+            Cst::Node {
+                kind: SyntaxKind::Program,
+                children,
+            } => children.split_last().and_then(|(eof, children)| {
+                let eof = eof.first_token()?;
+                (eof.kind == LexemeKind::Token(Token::EOF)).then_some((eof, children))
+            }),
+            _ => None,
+        };
+        let Some((eof, children)) = program else {
+            return Err(StackyError::runtime(
+                "Only a whole program that ends with EOF can be lowered",
+            ));
+        };
+        let lowering = Lowering { eof, prefer_native };
+        let nodes = lowering.sequence(&significant(children), eof, 0)?;
+        Ok(Ast::Expressions(nodes))
+    }
+}
+
+fn is_token(lexeme: &Lexeme) -> bool {
+    matches!(lexeme.kind, LexemeKind::Token(_))
+}
+
+/// the children that are no whitespace or comments
+fn significant(children: &[Cst]) -> Vec<&Cst> {
+    children
+        .iter()
+        .filter(|child| child.first_token().is_some())
+        .collect()
+}
+
+type Lexemes = Peekable<std::vec::IntoIter<Lexeme>>;
+
+/// reads nodes up to a token in `closers` or EOF, which are left for the caller
+fn sequence(lexemes: &mut Lexemes, closers: &[Token]) -> Vec<Cst> {
+    let mut children = vec![];
+    while let Some(lexeme) = lexemes.peek() {
+        match &lexeme.kind {
+            LexemeKind::Token(token) if *token == Token::EOF || closers.contains(token) => break,
+            LexemeKind::Token(_) => children.push(node(lexemes)),
+            _ => children.push(Cst::Leaf(lexemes.next().unwrap())),
+        }
+    }
+    children
+}
+
+/// reads the node that starts with the next token
+fn node(lexemes: &mut Lexemes) -> Cst {
+    let lexeme = lexemes.next().unwrap();
+    let LexemeKind::Token(token) = &lexeme.kind else {
+        return Cst::Leaf(lexeme);
+    };
+    let token = token.clone();
+    let is_identifier = |token: &Token| matches!(token, Token::Identifier(_));
+    let mut children = vec![Cst::Leaf(lexeme)];
+    let kind = match token {
+        Token::Colon => {
+            if operand(lexemes, &mut children, is_identifier) {
+                operand(lexemes, &mut children, |token| {
+                    matches!(token, Token::StackEffect(_))
+                });
+            }
+            children.extend(sequence(lexemes, &[Token::SemiColon]));
+            operand(lexemes, &mut children, |token| *token == Token::SemiColon);
+            SyntaxKind::WordDefinition
+        }
+        Token::If => {
+            children.extend(sequence(lexemes, &[Token::Else, Token::Then]));
+            if operand(lexemes, &mut children, |token| *token == Token::Else) {
+                children.extend(sequence(lexemes, &[Token::Then]));
+            }
+            operand(lexemes, &mut children, |token| *token == Token::Then);
+            SyntaxKind::If
+        }
+        Token::Do => {
+            children.extend(sequence(lexemes, &[Token::Loop]));
+            operand(lexemes, &mut children, |token| *token == Token::Loop);
+            SyntaxKind::Loop
+        }
+        Token::Arrow | Token::At => {
+            operand(lexemes, &mut children, is_identifier);
+            if token == Token::Arrow {
+                SyntaxKind::SetVariable
+            } else {
+                SyntaxKind::GetVariable
+            }
+        }
+        Token::Pragma => {
+            if operand(lexemes, &mut children, is_identifier) {
+                operand(lexemes, &mut children, is_identifier);
+            }
+            SyntaxKind::Pragma
+        }
+        Token::Include | Token::Require => {
+            operand(lexemes, &mut children, |token| {
+                matches!(token, Token::StringLiteral(_))
+            });
+            SyntaxKind::Include
+        }
+        _ => return children.pop().unwrap(),
+    };
+    Cst::Node { kind, children }
+}
+
+/// takes the whitespace and comments up to the next token and the token if `accept` does
+fn operand(
+    lexemes: &mut Lexemes,
+    children: &mut Vec<Cst>,
+    accept: impl Fn(&Token) -> bool,
+) -> bool {
+    while let Some(lexeme) = lexemes.peek() {
+        match &lexeme.kind {
+            LexemeKind::Token(token) if *token != Token::EOF && accept(token) => {
+                children.push(Cst::Leaf(lexemes.next().unwrap()));
+                return true;
+            }
+            LexemeKind::Token(_) => return false,
+            _ => children.push(Cst::Leaf(lexemes.next().unwrap())),
+        }
+    }
+    false
+}
+
+/// what lowering a program needs besides the nodes
+struct Lowering<'a> {
+    eof: &'a Lexeme,
+    /// drops definitions of words that are implemented natively
+    prefer_native: bool,
+}
+
+impl Lowering<'_> {
+    /// lowers nodes that are followed by `after`, the token looked at past them
+    fn sequence(
+        &self,
+        children: &[&Cst],
+        after: &Lexeme,
+        depth: usize,
+    ) -> Result<Vec<Node>, StackyError> {
+        let mut nodes = vec![];
+        for (index, child) in children.iter().enumerate() {
+            let next = match children.get(index + 1) {
+                Some(next) => next.first_token().unwrap(),
+                None => after,
+            };
+            nodes.push(self.node(child, next, depth)?);
+        }
+        Ok(nodes)
+    }
+
+    /// lowers a node, `next` is the token after it
+    fn node(&self, cst: &Cst, next: &Lexeme, depth: usize) -> Result<Node, StackyError> {
+        let eof = self.eof;
+        let first = cst.first_token().unwrap();
+        let span = first.span.clone();
+        let Cst::Node { kind, children } = cst else {
+            let LexemeKind::Token(token) = &first.kind else {
+                unreachable!("whitespace and comments are not lowered")
+            };
+            let ast = atom(token.clone(), &span)?;
+            return Ok(Node { ast, span });
+        };
+        let children = significant(children);
+        // the token after the first one, which is looked at next
+        let second = children
+            .get(1)
+            .map_or(next, |child| child.first_token().unwrap());
+        let syntax = |message: &str, at: &Lexeme| StackyError::syntax(message).at(at.span.clone());
+
+        let ast = match kind {
+            SyntaxKind::WordDefinition => {
+                let Some(Token::Identifier(name)) = children.get(1).and_then(|child| child.token())
+                else {
+                    return Err(syntax("Expected identifier", second));
+                };
+                let mut body = &children[2..];
+                let mut effect = None;
+                if let Some(Token::StackEffect(comment)) =
+                    body.first().and_then(|child| child.token())
+                {
+                    let at = body[0].first_token().unwrap();
+                    effect = Some(comment.parse().map_err(|e: String| syntax(&e, at))?);
+                    body = &body[1..];
+                }
+                let (body, close) = closed(body, &Token::SemiColon, eof);
+                let body = self.sequence(body, close, depth + 1)?;
+                if close.kind == LexemeKind::Token(Token::EOF) {
+                    return Err(syntax("Unexpected end of input", eof));
+                }
+                if self.prefer_native && is_native(name) {
+                    Ast::Expressions(vec![])
+                } else {
+                    Ast::WordDefinition {
+                        name: name.clone(),
+                        effect,
+                        body,
+                    }
+                }
+            }
+            SyntaxKind::If => {
+                let (body, close) = closed(&children[1..], &Token::Then, eof);
+                let middle = body
+                    .iter()
+                    .position(|child| child.token() == Some(&Token::Else));
+                let (if_body, else_body) = match middle {
+                    Some(middle) => (&body[..middle], &body[middle + 1..]),
+                    None => (body, &[][..]),
+                };
+                let after_if = match middle {
+                    Some(middle) => body[middle].first_token().unwrap(),
+                    None => close,
+                };
+                let if_body = self.sequence(if_body, after_if, depth + 1)?;
+                let else_body = self.sequence(else_body, close, depth + 1)?;
+                if close.kind == LexemeKind::Token(Token::EOF) {
+                    return Err(syntax("Unexpected end of input", eof));
+                }
+                Ast::If { if_body, else_body }
+            }
+            SyntaxKind::Loop => {
+                let (body, close) = closed(&children[1..], &Token::Loop, eof);
+                let body = self.sequence(body, close, depth + 1)?;
+                if close.kind == LexemeKind::Token(Token::EOF) {
+                    return Err(syntax("Unexpected end of input", eof));
+                }
+                Ast::Loop { body }
+            }
+            SyntaxKind::SetVariable | SyntaxKind::GetVariable => {
+                let is_set = *kind == SyntaxKind::SetVariable;
+                match children.get(1).and_then(|child| child.token()) {
+                    Some(Token::Identifier(name)) if is_set => Ast::SetVariable(name.clone()),
+                    Some(Token::Identifier(name)) => Ast::GetVariable(name.clone()),
+                    _ if is_set => return Err(syntax("Expected identifier after ->", second)),
+                    _ => return Err(syntax("Expected identifier after @", second)),
+                }
+            }
+            SyntaxKind::Pragma => {
+                if depth > 0 {
+                    return Err(syntax(
+                        "PRAGMA is only allowed at the top level of a file",
+                        second,
+                    ));
+                }
+                let key = children.get(1).and_then(|child| child.token());
+                let value = children.get(2).and_then(|child| child.token());
+                match (key, value) {
+                    (Some(Token::Identifier(key)), Some(Token::Identifier(value)))
+                        if key == "OVERFLOW" =>
+                    {
+                        match value.parse() {
+                            Ok(policy) => Ast::Pragma(Pragma::Overflow(policy)),
+                            Err(e) => return Err(syntax(&e, next)),
+                        }
+                    }
+                    _ => {
+                        let LexemeKind::Token(key) = &second.kind else {
+                            unreachable!("nodes start with a token")
+                        };
+                        return Err(syntax(&format!("Unknown pragma {:?}", key), second));
+                    }
+                }
+            }
+            SyntaxKind::Include => match children.get(1).and_then(|child| child.token()) {
+                Some(Token::StringLiteral(path)) => Ast::Include {
+                    path: path.clone(),
+                    once: first.kind == LexemeKind::Token(Token::Require),
+                    body: vec![],
+                },
+                _ => return Err(syntax("Expected a path after INCLUDE or REQUIRE", second)),
+            },
+            SyntaxKind::Program => unreachable!("programs are only lowered as a whole"),
+        };
+        Ok(Node { ast, span })
+    }
+}
+
+/// splits the closing token off a body, EOF closes a body that is not closed
+fn closed<'a>(body: &'a [&'a Cst], close: &Token, eof: &'a Lexeme) -> (&'a [&'a Cst], &'a Lexeme) {
+    match body.split_last() {
+        Some((last, body)) if last.token() == Some(close) => (body, last.first_token().unwrap()),
+        _ => (body, eof),
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    error::StackyError,
+    interpreter::{Event, Hook},
+    parser::Ast,
+    span::Lines,
+};
+
+const HELP: &str = "\
+s, step             run the next node, stepping into words
+n, next             run the next node, stepping over words
+o, out              run until the current word returns
+c, continue         run until a breakpoint
+b, break [where]    stop at a word, a line or a file:line, without where list the breakpoints
+d, delete where     remove a breakpoint
+p, print            show the stacks and variables again
+q, quit             stop the program
+h, help             show this help";
+
+/// a place the program stops at when it runs into it
+#[derive(Debug, PartialEq, Clone)]
+enum Breakpoint {
+    /// before the word is called
+    Word(String),
+    /// before the first node of the line runs, in any file
+    Line(usize),
+    /// before the first node of the line in a file runs
+    FileLine(String, usize),
+}
+
+impl Breakpoint {
+    fn parse(text: &str) -> Breakpoint {
+        if let Ok(line) = text.parse() {
+            return Breakpoint::Line(line);
+        }
+        match text
+            .rsplit_once(':')
+            .map(|(file, line)| (file, line.parse()))
+        {
+            Some((file, Ok(line))) if !file.is_empty() => {
+                Breakpoint::FileLine(file.to_string(), line)
+            }
+            _ => Breakpoint::Word(text.to_string()),
+        }
+    }
+
+    /// if the breakpoint is hit at the node, `new_line` tells if the node starts a line
+    fn is_hit(&self, event: &Event, new_line: bool) -> bool {
+        let span = &event.node.span;
+        match self {
+            Breakpoint::Word(name) => {
+                matches!(&event.node.ast, Ast::FunctionCall(call) if call == name)
+            }
+            Breakpoint::Line(line) => new_line && span.line == *line,
+            Breakpoint::FileLine(file, line) => {
+                new_line && span.line == *line && span.file.ends_with(file.as_str())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Word(name) => write!(f, "{}", name),
+            Breakpoint::Line(line) => write!(f, "{}", line),
+            Breakpoint::FileLine(file, line) => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+/// how far the program runs before it stops again
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    /// stop at the next node
+    Step,
+    /// stop at the next node not deeper in words than this
+    Next(usize),
+    /// stop at the next node less deep in words than this
+    Out(usize),
+    /// only stop at breakpoints
+    Continue,
+    /// never stop again, the commands ran out
+    Detached,
+}
+
+/// stops a running program at breakpoints and reads commands to step through it
+pub struct Debugger<R, W> {
+    commands: R,
+    output: W,
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    /// to stop at line breakpoints only once each time the program runs into a line
+    lines: Lines,
+    /// the last command that resumed the program, an empty line repeats it
+    last: String,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    /// a debugger that stops before the first node
+    pub fn new(commands: R, output: W) -> Self {
+        Self {
+            commands,
+            output,
+            breakpoints: vec![],
+            mode: Mode::Step,
+            lines: Lines::default(),
+            last: "s".to_string(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, text: &str) {
+        self.breakpoints.push(Breakpoint::parse(text));
+    }
+
+    fn should_stop(&self, event: &Event, new_line: bool) -> bool {
+        let depth = event.calls.len();
+        match self.mode {
+            Mode::Detached => return false,
+            Mode::Step => return true,
+            Mode::Next(d) if depth <= d => return true,
+            Mode::Out(d) if depth < d => return true,
+            _ => {}
+        }
+        self.breakpoints
+            .iter()
+            .any(|breakpoint| breakpoint.is_hit(event, new_line))
+    }
+
+    fn show(&mut self, event: &Event) -> Result<(), StackyError> {
+        write!(self.output, "{} {}", event.node.span, event.node.ast)?;
+        if let Some(word) = event.calls.last() {
+            write!(self.output, " in {}", word)?;
+        }
+        write!(self.output, "\n  stack:")?;
+        for item in event.stack {
+            write!(self.output, " {}", item)?;
+        }
+        write!(self.output, "\n  loops:")?;
+        // the loop stack holds the limit and then the index of each loop, the innermost is I
+        let names = ["I", "J", "K", "L", "M"];
+        for (name, pair) in names.iter().zip(event.loop_stack.rchunks(2)) {
+            if let [limit, index] = pair {
+                write!(self.output, " {}={}/{}", name, index, limit)?;
+            }
+        }
+        write!(self.output, "\n  variables:")?;
+        for (name, value) in event.context.variables() {
+            write!(self.output, " {}={}", name, value)?;
+        }
+        writeln!(self.output)?;
+        Ok(())
+    }
+
+    /// reads commands until one resumes the program
+    fn prompt(&mut self, event: &Event) -> Result<(), StackyError> {
+        self.show(event)?;
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.commands.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                self.mode = Mode::Detached;
+                return Ok(());
+            }
+            let mut line = line.trim().to_string();
+            if line.is_empty() {
+                line = self.last.clone();
+            }
+            let (command, argument) = match line.split_once(char::is_whitespace) {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (line.as_str(), ""),
+            };
+            let depth = event.calls.len();
+            let mode = match command {
+                "s" | "step" => Mode::Step,
+                "n" | "next" => Mode::Next(depth),
+                "o" | "out" => Mode::Out(depth),
+                "c" | "continue" => Mode::Continue,
+                "b" | "break" if argument.is_empty() => {
+                    for breakpoint in &self.breakpoints {
+                        writeln!(self.output, "{}", breakpoint)?;
+                    }
+                    continue;
+                }
+                "b" | "break" => {
+                    self.add_breakpoint(argument);
+                    continue;
+                }
+                "d" | "delete" => {
+                    let breakpoint = Breakpoint::parse(argument);
+                    let count = self.breakpoints.len();
+                    self.breakpoints.retain(|b| *b != breakpoint);
+                    if self.breakpoints.len() == count {
+                        writeln!(self.output, "No breakpoint at {}", argument)?;
+                    }
+                    continue;
+                }
+                "p" | "print" => {
+                    self.show(event)?;
+                    continue;
+                }
+                "q" | "quit" => return Err(StackyError::runtime("Stopped by the debugger")),
+                "h" | "help" => {
+                    writeln!(self.output, "{}", HELP)?;
+                    continue;
+                }
+                _ => {
+                    writeln!(self.output, "Unknown command {}, try help", command)?;
+                    continue;
+                }
+            };
+            self.mode = mode;
+            self.last = line;
+            return Ok(());
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn before(&mut self, event: &Event) -> Result<(), StackyError> {
+        let new_line = self.lines.starts_line(&event.node.span, event.calls.len());
+        if self.should_stop(event, new_line) {
+            self.prompt(event)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use num_bigint::BigInt;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::StackyError,
+    interpreter::Hook,
+    limits::{CancelHandle, Limits},
+    native::{NativeFn, NativeWord, Stack},
+    parser::Ast,
+    stack_machine::{Entity, OverflowPolicy},
+    stacky::Stacky,
+};
+
+/// an item a host program puts on or takes from the stack
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum Value {
+    Number(i64),
+    /// a number that does not fit into 64 bits, only pushed with the bigint overflow policy
+    BigInt(BigInt),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// keys and their values, in the order they were added
+    Map(Vec<(Value, Value)>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&entity(self.clone()), f)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(value: BigInt) -> Self {
+        Value::BigInt(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+pub(crate) fn entity(value: Value) -> Entity {
+    match value {
+        Value::Number(x) => Entity::Number(x),
+        Value::BigInt(x) => Entity::BigInt(x),
+        Value::Float(x) => Entity::Float(x),
+        Value::String(x) => Entity::String(x),
+        Value::Array(items) => Entity::Array(items.into_iter().map(entity).collect()),
+        Value::Map(pairs) => Entity::Map(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (entity(key), entity(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// the value of an entity, pointers, function pointers and file handles have none and neither
+/// have arrays and maps holding them
+pub(crate) fn value(entity: &Entity) -> Option<Value> {
+    match entity {
+        Entity::Number(x) => Some(Value::Number(*x)),
+        Entity::BigInt(x) => Some(Value::BigInt(x.clone())),
+        Entity::Float(x) => Some(Value::Float(*x)),
+        Entity::String(x) => Some(Value::String(x.clone())),
+        Entity::Array(items) => items
+            .iter()
+            .map(value)
+            .collect::<Option<_>>()
+            .map(Value::Array),
+        Entity::Map(pairs) => pairs
+            .iter()
+            .map(|(key, item)| Some((value(key)?, value(item)?)))
+            .collect::<Option<_>>()
+            .map(Value::Map),
+        Entity::Pointer(_) | Entity::Function(_) | Entity::File(_) => None,
+    }
+}
+
+/// a loaded and checked program, ready to run
+pub struct Program {
+    ast: Ast,
+}
+
+/// shows the syntax tree of the program
+impl fmt::Debug for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.ast, f)
+    }
+}
+
+/// runs stacky code, the stack, words and variables are kept between calls
+pub struct Engine {
+    stacky: Stacky,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// an engine that knows the words of the prelude
+    pub fn new() -> Self {
+        Self {
+            stacky: Stacky::new(),
+        }
+    }
+
+    /// an engine that only knows the native words
+    pub fn without_prelude() -> Self {
+        Self {
+            stacky: Stacky::without_prelude(),
+        }
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.stacky.set_overflow_policy(policy);
+    }
+
+    /// turns the stack effect checks that run before code off or on, they are on by default
+    pub fn set_check(&mut self, check: bool) {
+        self.stacky.set_check(check);
+    }
+
+    /// sets how much files are optimized by [`Engine::optimize`], from 0 for not at all to 2
+    pub fn set_opt_level(&mut self, level: u8) {
+        self.stacky.set_opt_level(level);
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.stacky.set_limits(limits);
+    }
+
+    /// allows or denies the file words access to the file system, it is allowed by default
+    pub fn set_file_access(&mut self, allowed: bool) {
+        self.stacky.set_file_access(allowed);
+    }
+
+    /// adds a directory that INCLUDE and REQUIRE search after the directory of the including file
+    pub fn add_search_path(&mut self, dir: impl Into<PathBuf>) {
+        self.stacky.add_search_path(dir);
+    }
+
+    /// a handle to stop runs from another thread
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.stacky.cancel_handle()
+    }
+
+    /// runs source code and returns what it printed, the code reads no input
+    pub fn eval(&mut self, source: &str) -> Result<String, StackyError> {
+        let mut output = vec![];
+        self.eval_with(source, &mut io::empty(), &mut output)?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// runs source code that reads from `input` and prints to `output`
+    pub fn eval_with(
+        &mut self,
+        source: &str,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<(), StackyError> {
+        self.stacky.eval_with_input(source, input, output)
+    }
+
+    /// the tokens of source code, for debugging the lexer
+    pub fn tokens(&mut self, source: &str) -> Result<String, StackyError> {
+        let tokens = self.stacky.try_lex(source)?;
+        Ok(format!("{:#?}", tokens))
+    }
+
+    /// lexes, parses and checks a file together with the files it includes
+    pub fn load_file(&mut self, path: &Path) -> Result<Program, StackyError> {
+        let ast = self.stacky.load_file(path)?;
+        self.stacky.check(&ast)?;
+        Ok(Program { ast })
+    }
+
+    /// optimizes a whole program, words it does not call are removed so no code may follow it
+    pub fn optimize(&self, program: Program) -> Program {
+        Program {
+            ast: self.stacky.optimize(program.ast),
+        }
+    }
+
+    pub fn run(
+        &mut self,
+        program: &Program,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<(), StackyError> {
+        self.stacky.try_run_with_input(&program.ast, input, output)
+    }
+
+    /// runs a program and lets the hook watch every node
+    pub fn run_with(
+        &mut self,
+        program: &Program,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+        hook: &mut dyn Hook,
+    ) -> Result<(), StackyError> {
+        self.stacky.try_run_with(&program.ast, input, output, hook)
+    }
+
+    /// defines a word that runs Rust code, it replaces a word with the same name
+    pub fn register(&mut self, name: &str, word: impl NativeWord + Send + 'static) {
+        self.stacky.register(name, Box::new(word));
+    }
+
+    /// defines a word that runs a closure, which is called with at least `arity` items on the
+    /// stack
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl FnMut(&mut Stack) -> Result<(), StackyError> + Send + 'static,
+    ) {
+        self.register(name, NativeFn { arity, function });
+    }
+
+    /// loads a shared library of native words, see [`crate::StackyPluginApi`]
+    pub fn load_plugin(&mut self, path: impl AsRef<Path>) -> Result<(), StackyError> {
+        self.stacky.load_plugin(path.as_ref())
+    }
+
+    /// runs a word or pushes the value of a variable and returns what it printed
+    pub fn call(&mut self, word: &str) -> Result<String, StackyError> {
+        let mut output = vec![];
+        self.stacky.call(word, &mut io::empty(), &mut output)?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    pub fn push(&mut self, value: impl Into<Value>) {
+        self.data().push(value);
+    }
+
+    /// takes the item on top of the stack, items without a value are left on the stack
+    pub fn pop(&mut self) -> Result<Value, StackyError> {
+        self.data().pop()
+    }
+
+    /// takes a number, any other item is left on the stack
+    pub fn pop_number(&mut self) -> Result<i64, StackyError> {
+        self.data().pop_number()
+    }
+
+    pub fn pop_float(&mut self) -> Result<f64, StackyError> {
+        self.data().pop_float()
+    }
+
+    pub fn pop_string(&mut self) -> Result<String, StackyError> {
+        self.data().pop_string()
+    }
+
+    /// pushes a Rust value, like a struct as a map
+    pub fn push_serialized<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StackyError> {
+        self.data().push_serialized(value)
+    }
+
+    /// takes the item on top of the stack as a Rust value, it is left on the stack if it does not fit
+    pub fn pop_deserialized<T: DeserializeOwned>(&mut self) -> Result<T, StackyError> {
+        self.data().pop_deserialized()
+    }
+
+    fn data(&mut self) -> Stack<'_> {
+        Stack::new(self.stacky.stack_machine())
+    }
+
+    /// the values on the stack, bottom first, items without a value are left out
+    pub fn stack(&self) -> Vec<Value> {
+        self.stacky.stack().iter().filter_map(value).collect()
+    }
+
+    /// the number of items on the stack
+    pub fn depth(&self) -> usize {
+        self.stacky.stack().len()
+    }
+
+    /// the value of a variable, none if there is no such variable or it holds no value
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        self.stacky.variable(name).as_ref().and_then(value)
+    }
+
+    /// sets a variable, the code that runs next can use it as a word or with @
+    pub fn set_variable(&mut self, name: &str, value: impl Into<Value>) {
+        self.stacky.set_variable(name, entity(value.into()));
+    }
+
+    /// forgets the stack, words, variables and open files, the settings and native words are kept
+    pub fn reset(&mut self) {
+        self.stacky.reset();
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+
+use crate::{limits::Limit, span::Span};
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum StackyError {
+    /// an integer operation left the 64 bit range with the checked overflow policy
+    IntegerOverflow {
+        operation: &'static str,
+        left: i64,
+        right: i64,
+    },
+    /// a word needed more items than there were on the stack
+    StackUnderflow {
+        word: String,
+        required: usize,
+        present: usize,
+    },
+    /// the source could not be lexed or parsed
+    Syntax(String),
+    /// the stack effects of the program do not add up, found before it runs
+    Check(String),
+    /// reading a source file or writing the program output failed
+    Io(String),
+    Runtime(String),
+    /// the program used more of a resource than its limit allows
+    LimitExceeded {
+        limit: Limit,
+        max: u64,
+    },
+    /// the run was stopped through its cancel handle
+    Cancelled,
+    /// an error raised at a position in a source file
+    At {
+        span: Span,
+        error: Box<StackyError>,
+    },
+}
+
+impl StackyError {
+    /// an error of a running program, native words fail with these
+    pub fn runtime(message: impl Into<String>) -> Self {
+        StackyError::Runtime(message.into())
+    }
+
+    pub(crate) fn syntax(message: impl Into<String>) -> Self {
+        StackyError::Syntax(message.into())
+    }
+
+    /// attaches a position, errors keep the innermost position they were raised at
+    pub(crate) fn at(self, span: Span) -> Self {
+        match self {
+            StackyError::At { .. } => self,
+            error => StackyError::At {
+                span,
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// the error without its position
+    #[cfg(test)]
+    pub(crate) fn root(&self) -> &StackyError {
+        match self {
+            StackyError::At { error, .. } => error.root(),
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for StackyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackyError::IntegerOverflow {
+                operation,
+                left,
+                right,
+            } => write!(f, "Integer overflow: {} {} {}", left, operation, right),
+            StackyError::StackUnderflow {
+                word,
+                required,
+                present,
+            } => write!(
+                f,
+                "Stack underflow: {} requires {} items, {} present",
+                word, required, present
+            ),
+            StackyError::Syntax(message) => write!(f, "{}", message),
+            StackyError::Check(message) => write!(f, "{}", message),
+            StackyError::Io(message) => write!(f, "{}", message),
+            StackyError::Runtime(message) => write!(f, "{}", message),
+            StackyError::LimitExceeded { limit, max } => match limit {
+                Limit::Steps => write!(f, "Step limit of {} exceeded", max),
+                Limit::Stack => write!(f, "Stack limit of {} items exceeded", max),
+                Limit::Loops => write!(f, "Loop limit of {} nested loops exceeded", max),
+                Limit::Calls => write!(f, "Call limit of {} nested words exceeded", max),
+                Limit::ItemSize => write!(f, "Item size limit of {} bytes exceeded", max),
+                Limit::Output => write!(f, "Output limit of {} bytes exceeded", max),
+            },
+            StackyError::Cancelled => write!(f, "Cancelled"),
+            StackyError::At { span, error } => write!(f, "{}: {}", span, error),
+        }
+    }
+}
+
+impl std::error::Error for StackyError {}
+
+impl From<std::io::Error> for StackyError {
+    fn from(error: std::io::Error) -> Self {
+        StackyError::Io(format!("Failed to write output: {}", error))
+    }
+}
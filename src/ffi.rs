@@ -0,0 +1,236 @@
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use crate::{engine::Engine, error::StackyError};
+
+/// an engine for C programs, with what the last call left for the caller
+pub struct StackyEngine {
+    engine: Engine,
+    /// what the last eval printed
+    output: CString,
+    /// the message of the last error, none if the last call succeeded
+    error: Option<CString>,
+}
+
+impl StackyEngine {
+    /// remembers the error of a call, or that it succeeded
+    fn record<T>(&mut self, result: Result<T, StackyError>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.error = None;
+                Some(value)
+            }
+            Err(error) => {
+                self.error = Some(c_string(error.to_string()));
+                None
+            }
+        }
+    }
+}
+
+/// C strings end at the first NUL, so the text does as well
+pub(crate) fn c_string(mut text: String) -> CString {
+    if let Some(end) = text.find('\0') {
+        text.truncate(end);
+    }
+    CString::new(text).unwrap_or_default()
+}
+
+/// 0 if the call succeeded, -1 if it failed
+fn status<T>(result: Option<T>) -> c_int {
+    match result {
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+/// runs code called from C and turns a panic into an error, a panic must not unwind into C
+pub(crate) fn catch<T>(f: impl FnOnce() -> Result<T, StackyError>) -> Result<T, StackyError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = match (
+            payload.downcast_ref::<&str>(),
+            payload.downcast_ref::<String>(),
+        ) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => "unknown panic".to_string(),
+        };
+        Err(StackyError::runtime(format!("Internal error: {}", message)))
+    })
+}
+
+/// reads a string passed by C
+///
+/// # Safety
+///
+/// `text` has to be null or point to a NUL terminated string
+pub(crate) unsafe fn text<'a>(text: *const c_char) -> Result<&'a str, StackyError> {
+    if text.is_null() {
+        return Err(StackyError::runtime("Expected a string, got NULL"));
+    }
+    CStr::from_ptr(text)
+        .to_str()
+        .map_err(|_| StackyError::runtime("Expected a UTF-8 string"))
+}
+
+/// creates an engine that knows the words of the prelude, free it with `stacky_engine_free`, NULL
+/// if it cannot be created
+#[no_mangle]
+pub extern "C" fn stacky_engine_new() -> *mut StackyEngine {
+    match catch(|| Ok(Engine::new())) {
+        Ok(engine) => Box::into_raw(Box::new(StackyEngine {
+            engine,
+            output: CString::default(),
+            error: None,
+        })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// frees an engine, NULL is ignored
+///
+/// # Safety
+///
+/// `engine` has to be null or come from `stacky_engine_new` and must not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn stacky_engine_free(engine: *mut StackyEngine) {
+    if !engine.is_null() {
+        // a panic while dropping leaks what is left of the engine
+        let _ = catch(|| {
+            drop(Box::from_raw(engine));
+            Ok(())
+        });
+    }
+}
+
+/// runs source code, returns 0 if it succeeded and -1 if it failed
+///
+/// # Safety
+///
+/// `engine` has to come from `stacky_engine_new` and `source` has to be a NUL terminated string
+#[no_mangle]
+pub unsafe extern "C" fn stacky_eval(engine: *mut StackyEngine, source: *const c_char) -> c_int {
+    let Some(engine) = engine.as_mut() else {
+        return -1;
+    };
+    let result = catch(|| engine.engine.eval(text(source)?));
+    let output = engine.record(result);
+    engine.output = c_string(output.clone().unwrap_or_default());
+    status(output)
+}
+
+/// what the last `stacky_eval` printed, the string belongs to the engine and lives until the
+/// next eval
+///
+/// # Safety
+///
+/// `engine` has to come from `stacky_engine_new`
+#[no_mangle]
+pub unsafe extern "C" fn stacky_output(engine: *const StackyEngine) -> *const c_char {
+    match engine.as_ref() {
+        Some(engine) => engine.output.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// the message of the error of the last call, NULL if it succeeded, the string belongs to the
+/// engine and lives until the next call
+///
+/// # Safety
+///
+/// `engine` has to come from `stacky_engine_new`
+#[no_mangle]
+pub unsafe extern "C" fn stacky_last_error(engine: *const StackyEngine) -> *const c_char {
+    match engine.as_ref().and_then(|engine| engine.error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// pushes a number
+///
+/// # Safety
+///
+/// `engine` has to come from `stacky_engine_new`
+#[no_mangle]
+pub unsafe extern "C" fn stacky_push_int(engine: *mut StackyEngine, value: i64) {
+    if let Some(engine) = engine.as_mut() {
+        let result = catch(|| {
+            engine.engine.push(value);
+            Ok(())
+        });
+        engine.record(result);
+    }
+}
+
+/// pops a number into `value`, returns 0 if it succeeded and -1 if the top item is no number
+///
+/// # Safety
+///
+/// `engine` has to come from `stacky_engine_new` and `value` has to point to an int64_t
+#[no_mangle]
+pub unsafe extern "C" fn stacky_pop_int(engine: *mut StackyEngine, value: *mut i64) -> c_int {
+    let Some(engine) = engine.as_mut() else {
+        return -1;
+    };
+    let result = catch(|| engine.engine.pop_number());
+    let Some(number) = engine.record(result) else {
+        return -1;
+    };
+    if !value.is_null() {
+        *value = number;
+    }
+    0
+}
+
+/// pushes a copy of a string, returns 0 if it succeeded and -1 if it is no UTF-8
+///
+/// # Safety
+///
+/// `engine` has to come from `stacky_engine_new` and `value` has to be a NUL terminated string
+#[no_mangle]
+pub unsafe extern "C" fn stacky_push_string(
+    engine: *mut StackyEngine,
+    value: *const c_char,
+) -> c_int {
+    let Some(engine) = engine.as_mut() else {
+        return -1;
+    };
+    let result = catch(|| {
+        engine.engine.push(text(value)?);
+        Ok(())
+    });
+    status(engine.record(result))
+}
+
+/// pops a string, NULL if the top item is no string, free it with `stacky_string_free`
+///
+/// # Safety
+///
+/// `engine` has to come from `stacky_engine_new`
+#[no_mangle]
+pub unsafe extern "C" fn stacky_pop_string(engine: *mut StackyEngine) -> *mut c_char {
+    let Some(engine) = engine.as_mut() else {
+        return ptr::null_mut();
+    };
+    let result = catch(|| engine.engine.pop_string());
+    match engine.record(result) {
+        Some(value) => c_string(value).into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// frees a string returned by `stacky_pop_string`, NULL is ignored
+///
+/// # Safety
+///
+/// `value` has to be null or come from `stacky_pop_string` and must not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn stacky_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
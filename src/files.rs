@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+};
+
+/// the ior code of file words that succeeded
+pub(crate) const IOR_OK: i64 = 0;
+/// an error without a code of its own
+const IOR_OTHER: i64 = -1;
+const IOR_NOT_FOUND: i64 = -2;
+const IOR_PERMISSION_DENIED: i64 = -3;
+/// the handle is closed or was never opened
+const IOR_INVALID_HANDLE: i64 = -4;
+/// the access method is none of R/O, W/O and R/W
+const IOR_INVALID_ACCESS: i64 = -5;
+const IOR_INVALID_DATA: i64 = -6;
+
+/// the access methods pushed by R/O, W/O and R/W
+pub(crate) const READ_ONLY: i64 = 1;
+pub(crate) const WRITE_ONLY: i64 = 2;
+pub(crate) const READ_WRITE: i64 = 3;
+
+fn ior(error: io::Error) -> i64 {
+    match error.kind() {
+        io::ErrorKind::NotFound => IOR_NOT_FOUND,
+        io::ErrorKind::PermissionDenied => IOR_PERMISSION_DENIED,
+        io::ErrorKind::InvalidData => IOR_INVALID_DATA,
+        _ => IOR_OTHER,
+    }
+}
+
+/// the files a program opened, by handle, errors are ior codes
+#[derive(Default)]
+pub(crate) struct Files {
+    open: HashMap<u32, BufReader<File>>,
+    /// the last handle given out, handles are never reused so a closed handle stays invalid
+    last: u32,
+}
+
+impl Files {
+    /// opens a file, `create` empties the file or creates it if it does not exist
+    pub(crate) fn open(&mut self, path: &str, access: i64, create: bool) -> Result<u32, i64> {
+        let (read, write) = match access {
+            READ_ONLY => (true, false),
+            WRITE_ONLY => (false, true),
+            READ_WRITE => (true, true),
+            _ => return Err(IOR_INVALID_ACCESS),
+        };
+        let file = OpenOptions::new()
+            .read(read)
+            .write(write || create)
+            .create(create)
+            .truncate(create)
+            .open(path)
+            .map_err(ior)?;
+        self.last += 1;
+        self.open.insert(self.last, BufReader::new(file));
+        Ok(self.last)
+    }
+
+    fn get(&mut self, handle: u32) -> Result<&mut BufReader<File>, i64> {
+        self.open.get_mut(&handle).ok_or(IOR_INVALID_HANDLE)
+    }
+
+    /// reads up to `length` bytes, fewer at the end of the file
+    pub(crate) fn read(&mut self, handle: u32, length: usize) -> Result<String, i64> {
+        let mut bytes = vec![];
+        self.get(handle)?
+            .take(length as u64)
+            .read_to_end(&mut bytes)
+            .map_err(ior)?;
+        String::from_utf8(bytes).map_err(|_| IOR_INVALID_DATA)
+    }
+
+    /// reads a line without its line break, none at the end of the file
+    pub(crate) fn read_line(&mut self, handle: u32) -> Result<Option<String>, i64> {
+        let mut line = String::new();
+        if self.get(handle)?.read_line(&mut line).map_err(ior)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    pub(crate) fn write(&mut self, handle: u32, text: &str) -> Result<(), i64> {
+        let reader = self.get(handle)?;
+        // seeking drops what was read ahead, so the text is written right after what was read
+        let position = reader.stream_position().map_err(ior)?;
+        reader.seek(SeekFrom::Start(position)).map_err(ior)?;
+        reader.get_mut().write_all(text.as_bytes()).map_err(ior)
+    }
+
+    pub(crate) fn close(&mut self, handle: u32) -> Result<(), i64> {
+        self.open.remove(&handle).ok_or(IOR_INVALID_HANDLE)?;
+        Ok(())
+    }
+
+    pub(crate) fn size(&mut self, handle: u32) -> Result<u64, i64> {
+        let metadata = self.get(handle)?.get_ref().metadata().map_err(ior)?;
+        Ok(metadata.len())
+    }
+
+    pub(crate) fn delete(&mut self, path: &str) -> Result<(), i64> {
+        fs::remove_file(path).map_err(ior)
+    }
+}
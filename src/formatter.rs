@@ -0,0 +1,247 @@
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    cst::{Cst, SyntaxKind},
+    error::StackyError,
+    lexer::{keyword, Lexeme, LexemeKind, Lexer, Token},
+};
+
+/// the indentation of one level of nesting
+const INDENT: &str = "  ";
+
+/// formats source code: definitions start on their own line, definitions, IF and DO that span
+/// lines get their body indented, tokens are separated by one space and keywords are upper case
+///
+/// line breaks of the source are kept, with at most one blank line in a row, and so are comments
+pub fn format_source(source: &str) -> Result<String, StackyError> {
+    // words that become keywords change the structure, so the source is read again
+    let mut cst = parse(source)?;
+    let mut names = HashSet::new();
+    defined(&cst, &mut names);
+    casing(&mut cst, &names);
+    let cst = parse(&cst.text())?;
+
+    let mut formatter = Formatter::default();
+    formatter.node(&cst);
+    Ok(formatter.finish())
+}
+
+fn parse(source: &str) -> Result<Cst, StackyError> {
+    Ok(Cst::build(
+        Lexer::new(Arc::from("<input>")).lex_lossless(source)?,
+    ))
+}
+
+/// the children that are tokens or nodes, with their index
+fn significant(children: &[Cst]) -> impl Iterator<Item = (usize, &Cst)> {
+    children
+        .iter()
+        .enumerate()
+        .filter(|(_, child)| child.first_token().is_some())
+}
+
+/// the names the source defines with : or assigns with ->
+fn defined(cst: &Cst, names: &mut HashSet<String>) {
+    let Cst::Node { kind, children } = cst else {
+        return;
+    };
+    if let SyntaxKind::WordDefinition | SyntaxKind::SetVariable = kind {
+        if let Some((_, child)) = significant(children).nth(1) {
+            if let Some(Token::Identifier(name)) = child.token() {
+                names.insert(name.clone());
+            }
+        }
+    }
+    children.iter().for_each(|child| defined(child, names));
+}
+
+/// keywords are only recognized in upper case, so a word like `dup` is written `DUP` unless the
+/// source defines it
+fn casing(cst: &mut Cst, names: &HashSet<String>) {
+    match cst {
+        Cst::Leaf(Lexeme {
+            kind: LexemeKind::Token(Token::Identifier(name)),
+            text,
+            ..
+        }) if keyword(&name.to_ascii_uppercase()).is_some() && !names.contains(name) => {
+            *text = name.to_ascii_uppercase()
+        }
+        Cst::Leaf(_) => {}
+        Cst::Node { children, .. } => children.iter_mut().for_each(|child| casing(child, names)),
+    }
+}
+
+/// the children of a definition, IF or DO by their role
+struct Block {
+    /// the last child of the name and the stack effect of a definition, else the opener
+    header: usize,
+    /// the ELSE of an IF
+    middle: Option<usize>,
+    /// the closer, none if the block is not closed
+    end: Option<usize>,
+}
+
+impl Block {
+    fn new(kind: SyntaxKind, children: &[Cst]) -> Option<Self> {
+        let closer = match kind {
+            SyntaxKind::WordDefinition => Token::SemiColon,
+            SyntaxKind::If => Token::Then,
+            SyntaxKind::Loop => Token::Loop,
+            _ => return None,
+        };
+        let position = |token: &Token| {
+            children
+                .iter()
+                .position(|child| child.token() == Some(token))
+        };
+        let mut header = 0;
+        if kind == SyntaxKind::WordDefinition {
+            // the name and the stack effect stay on the line of the colon
+            let mut tokens = significant(children).skip(1);
+            if let Some((index, Cst::Leaf(_))) = tokens.next() {
+                if let Some(Token::Identifier(_)) = children[index].token() {
+                    header = index;
+                    if let Some((index, child)) = tokens.next() {
+                        if let Some(Token::StackEffect(_)) = child.token() {
+                            header = index;
+                        }
+                    }
+                }
+            }
+        }
+        Some(Self {
+            header,
+            middle: (kind == SyntaxKind::If)
+                .then(|| position(&Token::Else))
+                .flatten(),
+            end: position(&closer),
+        })
+    }
+}
+
+/// puts formatted lines together
+#[derive(Default)]
+struct Formatter {
+    lines: Vec<String>,
+    line: String,
+    depth: usize,
+    /// the line breaks in the source since the last word, at most 2
+    newlines: usize,
+    /// if the next word starts a line no matter what the source does
+    forced: bool,
+}
+
+impl Formatter {
+    fn node(&mut self, cst: &Cst) {
+        let (kind, children) = match cst {
+            Cst::Leaf(lexeme) => return self.leaf(lexeme),
+            Cst::Node { kind, children } => (*kind, children),
+        };
+        let Some(block) = Block::new(kind, children) else {
+            return children.iter().for_each(|child| self.node(child));
+        };
+        let mut before = HashSet::new();
+        let mut after = HashSet::new();
+        if kind == SyntaxKind::WordDefinition {
+            // every definition starts a line and nothing follows it on its line
+            before.insert(0);
+            after.extend(block.end);
+        }
+        let lines = cst.first_token().zip(cst.last_token());
+        if let (Some(end), Some((first, last))) = (block.end, lines) {
+            // the body of a block that spans lines goes on lines of its own
+            if first.span.line != last.span.line {
+                after.insert(block.header);
+                before.extend(block.middle);
+                after.extend(block.middle);
+                before.insert(end);
+            }
+        }
+
+        for (index, child) in children.iter().enumerate() {
+            if before.contains(&index) {
+                self.forced = true;
+            }
+            match child {
+                Cst::Leaf(lexeme) if index == 0 => {
+                    self.write(&lexeme.text, 0);
+                    self.depth += 1;
+                }
+                Cst::Leaf(lexeme) if Some(index) == block.middle => self.write(&lexeme.text, 1),
+                Cst::Leaf(lexeme) if Some(index) == block.end => {
+                    self.write(&lexeme.text, 1);
+                    self.depth -= 1;
+                }
+                _ => self.node(child),
+            }
+            if after.contains(&index) {
+                self.forced = true;
+            }
+        }
+        if block.end.is_none() {
+            self.depth -= 1;
+        }
+    }
+
+    fn leaf(&mut self, lexeme: &Lexeme) {
+        match &lexeme.kind {
+            LexemeKind::Whitespace => {
+                let newlines = lexeme.text.matches('\n').count().min(2);
+                self.newlines = self.newlines.max(newlines);
+            }
+            // a comment after a word that ends a line stays on the line
+            LexemeKind::LineComment => {
+                self.comment(&lexeme.text);
+                self.forced = true;
+            }
+            LexemeKind::Comment => self.comment(&lexeme.text),
+            LexemeKind::Token(Token::EOF) => {}
+            LexemeKind::Skipped => self.write(&lexeme.text, 0),
+            LexemeKind::Token(_) => self.write(&lexeme.text, 0),
+        }
+    }
+
+    /// appends a word, a word that starts a line is indented by the depth less `dedent`
+    fn write(&mut self, text: &str, dedent: usize) {
+        let newlines = match self.forced {
+            true => self.newlines.max(1),
+            false => self.newlines,
+        };
+        if newlines > 0 && !self.line.is_empty() {
+            self.lines.push(std::mem::take(&mut self.line));
+            if newlines > 1 {
+                self.lines.push(String::new());
+            }
+        }
+        if self.line.is_empty() {
+            self.line = INDENT.repeat(self.depth.saturating_sub(dedent));
+        } else {
+            self.line.push(' ');
+        }
+        self.line.push_str(text);
+        self.newlines = 0;
+        self.forced = false;
+    }
+
+    /// appends a comment, which only starts a line if it does so in the source
+    fn comment(&mut self, text: &str) {
+        let forced = self.forced && self.newlines == 0;
+        if forced {
+            self.forced = false;
+        }
+        self.write(text, 0);
+        self.forced = forced;
+    }
+
+    fn finish(mut self) -> String {
+        if !self.line.is_empty() {
+            self.lines.push(self.line);
+        }
+        let mut text = String::new();
+        for line in self.lines {
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
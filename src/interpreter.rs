@@ -1,13 +1,132 @@
-use core::panic;
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::Path;
 
 use crate::context::{Context, Variable};
-use crate::parser::Ast;
-use crate::stack_machine::{Entity, StackMachine};
+use crate::engine::{value, Value};
+use crate::error::StackyError;
+use crate::lexer::Token;
+use crate::limits::{CancelHandle, Limit, LimitedOutput, Limits, MAX_CALLS};
+use crate::native::{NativeWord, Stack};
+use crate::parser::{Ast, Node, Pragma};
+use crate::plugin;
+use crate::span::Span;
+use crate::stack_machine::{Entity, OverflowPolicy, StackMachine};
+
+/// the stack a word call needs at least, with less the call runs on a new stack of `STACK_SIZE`
+const RED_ZONE: usize = 128 * 1024;
+const STACK_SIZE: usize = 1024 * 1024;
+
+/// what a hook sees of the interpreter when a node runs
+pub struct Event<'a> {
+    pub(crate) node: &'a Node,
+    /// the words that are running, outermost first
+    pub(crate) calls: &'a [String],
+    pub(crate) stack: &'a [Entity],
+    pub(crate) loop_stack: &'a [i64],
+    pub(crate) context: &'a Context,
+}
+
+impl Event<'_> {
+    /// where the node starts
+    pub fn span(&self) -> &Span {
+        &self.node.span
+    }
+
+    /// the code of the node
+    pub fn code(&self) -> String {
+        self.node.ast.to_string()
+    }
+
+    /// the words that are running, outermost first
+    pub fn calls(&self) -> &[String] {
+        self.calls
+    }
+
+    /// the values on the stack, bottom first, items without a value are left out
+    pub fn stack(&self) -> Vec<Value> {
+        self.stack.iter().filter_map(value).collect()
+    }
+
+    /// the number of items on the stack
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// the index and limit of each running loop, outermost first
+    pub fn loops(&self) -> Vec<(i64, i64)> {
+        self.loop_stack
+            .chunks_exact(2)
+            .map(|pair| (pair[1], pair[0]))
+            .collect()
+    }
+
+    /// the variables visible to the node with their values, sorted by name
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        self.context
+            .variables()
+            .into_iter()
+            .filter_map(|(name, entity)| Some((name, value(&entity)?)))
+            .collect()
+    }
+}
+
+/// watches a program run, node by node
+pub trait Hook {
+    /// called before a node runs, an error stops the program
+    fn before(&mut self, _event: &Event) -> Result<(), StackyError> {
+        Ok(())
+    }
+
+    /// called after a node ran, with the error if it failed
+    fn after(&mut self, _event: &Event, _error: Option<&StackyError>) -> Result<(), StackyError> {
+        Ok(())
+    }
+
+    /// called when the body of a word starts to run
+    fn enter(&mut self, _word: &str) {}
+
+    /// called when the body of a word is done, also if it failed
+    fn leave(&mut self, _word: &str) {}
+}
+
+/// runs several hooks in order, the first error stops the program
+impl Hook for Vec<&mut dyn Hook> {
+    fn before(&mut self, event: &Event) -> Result<(), StackyError> {
+        self.iter_mut().try_for_each(|hook| hook.before(event))
+    }
+
+    fn after(&mut self, event: &Event, error: Option<&StackyError>) -> Result<(), StackyError> {
+        self.iter_mut()
+            .try_for_each(|hook| hook.after(event, error))
+    }
+
+    fn enter(&mut self, word: &str) {
+        self.iter_mut().for_each(|hook| hook.enter(word));
+    }
+
+    fn leave(&mut self, word: &str) {
+        self.iter_mut().for_each(|hook| hook.leave(word));
+    }
+}
+
+/// the hook of programs that are not watched
+pub(crate) struct NoHook;
+
+impl Hook for NoHook {}
 
 pub(crate) struct Interpreter {
     stack_machine: StackMachine,
     context: Context,
+    overflow_policy: OverflowPolicy,
+    /// the words that are running, outermost first
+    calls: Vec<String>,
+    limits: Limits,
+    /// the steps taken in the current run
+    steps: u64,
+    cancel: CancelHandle,
+    /// the words of the host program by name
+    natives: HashMap<String, Box<dyn NativeWord + Send>>,
 }
 
 impl Interpreter {
@@ -15,108 +134,390 @@ impl Interpreter {
         Self {
             stack_machine: StackMachine::new(),
             context: Context::new(None),
+            overflow_policy: OverflowPolicy::default(),
+            calls: vec![],
+            limits: Limits::default(),
+            steps: 0,
+            cancel: CancelHandle::default(),
+            natives: HashMap::new(),
+        }
+    }
+
+    /// sets the overflow policy every program starts with, pragmas only change it for their file
+    pub(crate) fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    pub(crate) fn set_limits(&mut self, limits: Limits) {
+        self.stack_machine.set_item_size(limits.item_size);
+        self.limits = limits;
+    }
+
+    /// allows or denies the file words access to the file system
+    pub(crate) fn set_file_access(&mut self, allowed: bool) {
+        self.stack_machine.set_file_access(allowed);
+    }
+
+    /// a handle to stop runs from another thread
+    pub(crate) fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub(crate) fn base(&self) -> u32 {
+        self.stack_machine.base()
+    }
+
+    /// forgets the stacks, words, variables and open files but keeps the settings and native words
+    pub(crate) fn reset(&mut self) {
+        self.stack_machine.reset();
+        self.context = Context::new(None);
+        for name in self.natives.keys() {
+            self.context.set(name.clone(), Variable::Native);
         }
+        self.calls.clear();
     }
 
-    pub(crate) fn run(&mut self, ast: &Ast, output: &mut impl Write) {
-        self.interpret(&ast, &mut self.context.clone(), output);
+    pub(crate) fn stack_machine(&mut self) -> &mut StackMachine {
+        &mut self.stack_machine
     }
 
-    fn interpret(&mut self, ast: &Ast, context: &mut Context, output: &mut impl Write) {
+    pub(crate) fn register(&mut self, name: &str, word: Box<dyn NativeWord + Send>) {
+        self.natives.insert(name.to_string(), word);
+        self.context.set(name.to_string(), Variable::Native);
+    }
+
+    /// registers the words of a plugin library
+    pub(crate) fn load_plugin(&mut self, path: &Path) -> Result<(), StackyError> {
+        for name in self.add_plugin(path)? {
+            self.context.set(name, Variable::Native);
+        }
+        Ok(())
+    }
+
+    /// adds the words of a plugin library to the native words and returns their names
+    fn add_plugin(&mut self, path: &Path) -> Result<Vec<String>, StackyError> {
+        let words = plugin::load(path)?;
+        let names = words.iter().map(|word| word.name.clone()).collect();
+        for word in words {
+            self.natives.insert(word.name.clone(), Box::new(word));
+        }
+        Ok(names)
+    }
+
+    pub(crate) fn stack(&self) -> &[Entity] {
+        self.stack_machine.stack()
+    }
+
+    /// the value of a variable, none if it is not defined or is a word
+    pub(crate) fn variable(&self, name: &str) -> Option<Entity> {
+        match self.context.get(name.to_string()) {
+            Ok(Variable::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_variable(&mut self, name: &str, value: Entity) {
+        self.context.set(name.to_string(), Variable::Value(value));
+    }
+
+    pub(crate) fn words(&self) -> HashSet<String> {
+        self.context.names()
+    }
+
+    /// runs a program, its words and variables stay defined for the next run
+    pub(crate) fn run(
+        &mut self,
+        ast: &Ast,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<(), StackyError> {
+        self.run_with(ast, input, output, &mut NoHook)
+    }
+
+    /// runs a program and lets the hook watch every node
+    pub(crate) fn run_with(
+        &mut self,
+        ast: &Ast,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+        hook: &mut dyn Hook,
+    ) -> Result<(), StackyError> {
+        self.stack_machine.set_overflow_policy(self.overflow_policy);
+        self.calls.clear();
+        self.steps = 0;
+        // an item pushed by a node that failed is not checked
+        self.stack_machine.take_oversized();
+        // a cancel that came after the last run ended is meant for that run
+        self.cancel.take();
+        let mut output = LimitedOutput::new(output, self.limits.output);
+        let mut context = std::mem::replace(&mut self.context, Context::new(None));
+        let result = self.interpret(ast, &mut context, input, &mut output, hook);
+        self.context = context;
+        result
+    }
+
+    fn event<'a>(&'a self, node: &'a Node, context: &'a Context) -> Event<'a> {
+        Event {
+            node,
+            calls: &self.calls,
+            stack: self.stack_machine.stack(),
+            loop_stack: self.stack_machine.loop_stack(),
+            context,
+        }
+    }
+
+    fn interpret(
+        &mut self,
+        ast: &Ast,
+        context: &mut Context,
+        input: &mut impl BufRead,
+        output: &mut LimitedOutput<impl Write>,
+        hook: &mut dyn Hook,
+    ) -> Result<(), StackyError> {
         match ast {
-            Ast::Number(x) => self.stack_machine.push(Entity::Number(x.clone())),
-            Ast::Operation(op) => self.stack_machine.execute(op, output),
-            Ast::Expressions(nodes) => {
-                for node in nodes {
-                    self.interpret(node, context, output);
+            Ast::Number(x) => self.stack_machine.push(Entity::Number(*x)),
+            Ast::BigNumber(x) => self.stack_machine.push_big(x.clone())?,
+            Ast::Float(x) => self.stack_machine.push(Entity::Float(*x)),
+            // plugins add native words, which the stack machine does not know
+            Ast::Operation(Token::Plugin) => {
+                self.stack_machine.require_file_access("PLUGIN")?;
+                let path = self.stack_machine.pop_string("PLUGIN")?;
+                for name in self.add_plugin(Path::new(&path))? {
+                    context.set(name, Variable::Native);
                 }
             }
-            Ast::WordDefinition { name, body } => {
-                context.set(name.clone(), Variable::Function { body: body.to_vec() });
+            Ast::Operation(op) => self.stack_machine.execute(op, input, output)?,
+            Ast::Expressions(nodes) => self.interpret_nodes(nodes, context, input, output, hook)?,
+            Ast::WordDefinition { name, body, .. } => {
+                context.set(
+                    name.clone(),
+                    Variable::Function {
+                        body: body.to_vec(),
+                    },
+                );
+            }
+            Ast::FunctionCall(ref name) | Ast::GetVariable(ref name) if name == "BASE" => {
+                let base = self.stack_machine.base();
+                self.stack_machine.push(Entity::Number(base as i64));
             }
             Ast::FunctionCall(ref name) => {
-                let var = context.get(name.clone());
+                let var = context.get(name.clone())?;
                 match var {
                     Variable::Function { body } => {
-                        self.interpret(&Ast::Expressions(body), context, output);
-                    },
-                    Variable::Variable(ref x) => {
+                        let depth = self.stack_machine.return_depth();
+                        let max = self.limits.calls.unwrap_or(MAX_CALLS).min(MAX_CALLS);
+                        if self.calls.len() >= max {
+                            return Err(exceeded(Limit::Calls, max as u64));
+                        }
+                        self.calls.push(name.clone());
+                        hook.enter(name);
+                        // the body runs on a new piece of stack if the thread is running out of it
+                        let result = stacker::maybe_grow(RED_ZONE, STACK_SIZE, || {
+                            self.interpret_nodes(&body, context, input, output, hook)
+                        });
+                        hook.leave(name);
+                        self.calls.pop();
+                        result?;
+                        let left = self.stack_machine.return_depth() as i64 - depth as i64;
+                        if left != 0 {
+                            return Err(StackyError::runtime(format!(
+                                "Unbalanced return stack: {} {} {} items",
+                                name,
+                                if left > 0 { "left" } else { "took" },
+                                left.abs()
+                            )));
+                        }
+                    }
+                    Variable::Native => self.call_native(name, hook)?,
+                    Variable::Value(ref x) => {
                         self.stack_machine.push(x.clone());
                     }
                 }
             }
-            Ast::StringLiteral(x) => {
-                let length = x.len();
-                for i in 0..length {
-                    self.stack_machine
-                        .push(Entity::Number(x.chars().nth(i).unwrap() as i32));
-                }
-                self.stack_machine.push(Entity::Number(length as i32));
-            }
+            Ast::StringLiteral(x) => self.stack_machine.push(Entity::String(x.clone())),
             Ast::If { if_body, else_body } => {
                 let condition = self.stack_machine.pop();
                 match condition {
                     Some(Entity::Number(c)) => {
                         if c == 1 {
-                            self.interpret(&Ast::Expressions(if_body.to_vec()), context, output);
+                            self.interpret_nodes(if_body, context, input, output, hook)?;
                         } else {
-                            self.interpret(&Ast::Expressions(else_body.to_vec()), context, output);
+                            self.interpret_nodes(else_body, context, input, output, hook)?;
                         }
                     }
-                    Some(_) => panic!("Cannot use non Number value as condition"),
-                    None => panic!("No conditional value for if"),
+                    Some(_) => {
+                        return Err(StackyError::runtime(
+                            "Cannot use non Number value as condition",
+                        ))
+                    }
+                    None => return Err(StackyError::runtime("No conditional value for if")),
                 }
             }
             Ast::Loop { body } => {
-                self.prepare_loop();
-                self.execute_loop(body, context, output);
+                self.prepare_loop()?;
+                self.execute_loop(body, context, input, output, hook)?;
             }
-            Ast::LoopVariable(ref x) => {
-                let value = self.stack_machine.get_loop(2 * x.clone() as usize);
+            Ast::LoopVariable(x) => {
+                let value = self.stack_machine.get_loop(2 * *x as usize);
                 match value {
                     Some(v) => {
                         self.stack_machine.push(Entity::Number(v));
                     }
-                    None => panic!("No loop-counter {:?}", x),
+                    None => return Err(StackyError::runtime(format!("No loop-counter {:?}", x))),
                 }
             }
+            Ast::SetVariable(ref x) if x == "BASE" => match self.stack_machine.pop() {
+                Some(Entity::Number(base)) => self.stack_machine.set_base(base)?,
+                Some(_) => return Err(StackyError::runtime("Cannot use non Number value as base")),
+                None => {
+                    return Err(StackyError::runtime(
+                        "Not enough items on the stack for variable assignment",
+                    ))
+                }
+            },
             Ast::SetVariable(ref x) => {
                 let value = self.stack_machine.pop();
                 match value {
                     Some(v) => {
-                        context.set(x.clone(), Variable::Variable(v));
-                    },
-                    None => panic!("Not enough items on the stack for variable assignment"),
+                        context.set(x.clone(), Variable::Value(v));
+                    }
+                    None => {
+                        return Err(StackyError::runtime(
+                            "Not enough items on the stack for variable assignment",
+                        ))
+                    }
                 }
-            },
+            }
             Ast::GetVariable(ref x) => {
-                let variable = context.get(x.clone());
+                let variable = context.get(x.clone())?;
                 match variable {
-                    Variable::Function { body: _ } => panic!("Cannot get the content of a function"),
-                    Variable::Variable(ref x) => {
+                    Variable::Function { body: _ } | Variable::Native => {
+                        return Err(StackyError::runtime("Cannot get the content of a function"))
+                    }
+                    Variable::Value(ref x) => {
                         self.stack_machine.push(x.clone());
-                    },
+                    }
                 }
-            },
+            }
+            Ast::Pragma(Pragma::Overflow(policy)) => {
+                self.stack_machine.set_overflow_policy(*policy);
+            }
+            Ast::Include { body, .. } => {
+                // pragmas only apply to the file they are in
+                let policy = self.stack_machine.overflow_policy();
+                let result = self.interpret_nodes(body, context, input, output, hook);
+                self.stack_machine.set_overflow_policy(policy);
+                result?;
+            }
         }
+        Ok(())
     }
-    fn prepare_loop(&mut self) {
+
+    fn call_native(&mut self, name: &str, hook: &mut dyn Hook) -> Result<(), StackyError> {
+        let Some(word) = self.natives.get_mut(name) else {
+            return Err(StackyError::runtime("Word not found"));
+        };
+        let present = self.stack_machine.stack().len();
+        if present < word.arity() {
+            return Err(StackyError::StackUnderflow {
+                word: name.to_string(),
+                required: word.arity(),
+                present,
+            });
+        }
+        hook.enter(name);
+        let result = word.call(&mut Stack::new(&mut self.stack_machine));
+        hook.leave(name);
+        result
+    }
+
+    fn interpret_nodes(
+        &mut self,
+        nodes: &[Node],
+        context: &mut Context,
+        input: &mut impl BufRead,
+        output: &mut LimitedOutput<impl Write>,
+        hook: &mut dyn Hook,
+    ) -> Result<(), StackyError> {
+        for node in nodes {
+            self.step().map_err(|e| e.at(node.span.clone()))?;
+            hook.before(&self.event(node, context))
+                .map_err(|e| e.at(node.span.clone()))?;
+            let result = self
+                .interpret(&node.ast, context, input, output, hook)
+                .and_then(|()| self.check_limits(output))
+                .map_err(|e| e.at(node.span.clone()));
+            hook.after(&self.event(node, context), result.as_ref().err())?;
+            result?;
+        }
+        Ok(())
+    }
+
+    /// counts a step and stops the run if it was cancelled or took too many steps
+    fn step(&mut self) -> Result<(), StackyError> {
+        if self.cancel.take() {
+            return Err(StackyError::Cancelled);
+        }
+        self.steps += 1;
+        match self.limits.steps {
+            Some(max) if self.steps > max => Err(exceeded(Limit::Steps, max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// the limits on what a node can leave behind
+    fn check_limits(&mut self, output: &LimitedOutput<impl Write>) -> Result<(), StackyError> {
+        if let Some(max) = self.limits.stack {
+            if self.stack_machine.stack().len() + self.stack_machine.return_depth() > max {
+                return Err(exceeded(Limit::Stack, max as u64));
+            }
+        }
+        if self.stack_machine.take_oversized() {
+            let max = self.limits.item_size.unwrap_or(0);
+            return Err(exceeded(Limit::ItemSize, max as u64));
+        }
+        if output.exceeded() {
+            let max = self.limits.output.unwrap_or(0);
+            return Err(exceeded(Limit::Output, max as u64));
+        }
+        Ok(())
+    }
+
+    fn prepare_loop(&mut self) -> Result<(), StackyError> {
         let index = self.stack_machine.pop();
         let limit = self.stack_machine.pop();
 
         match (index, limit) {
             (Some(Entity::Number(i)), Some(Entity::Number(l))) => {
+                if let Some(max) = self.limits.loops {
+                    // the loop stack holds a limit and an index for each loop
+                    if self.stack_machine.loop_stack().len() / 2 >= max {
+                        return Err(exceeded(Limit::Loops, max as u64));
+                    }
+                }
                 self.stack_machine.push_loop(l);
                 self.stack_machine.push_loop(i);
             }
-            (Some(_), Some(_)) => panic!("Cannot use non Number value as index or limit"),
-            (Some(_), None) => panic!("No limit value for loop"),
-            (None, Some(_)) => panic!("No index value for loop"),
-            (None, None) => panic!("No index and limit value for loop"),
+            (Some(_), Some(_)) => {
+                return Err(StackyError::runtime(
+                    "Cannot use non Number value as index or limit",
+                ))
+            }
+            (Some(_), None) => return Err(StackyError::runtime("No limit value for loop")),
+            (None, Some(_)) => return Err(StackyError::runtime("No index value for loop")),
+            (None, None) => return Err(StackyError::runtime("No index and limit value for loop")),
         }
+        Ok(())
     }
 
-    fn execute_loop(&mut self, body: &Vec<Ast>, context: &mut Context, output: &mut impl Write) {
+    fn execute_loop(
+        &mut self,
+        body: &[Node],
+        context: &mut Context,
+        input: &mut impl BufRead,
+        output: &mut LimitedOutput<impl Write>,
+        hook: &mut dyn Hook,
+    ) -> Result<(), StackyError> {
         loop {
             let index = self.stack_machine.get_loop(0);
             let limit = self.stack_machine.get_loop(1);
@@ -124,16 +525,25 @@ impl Interpreter {
             match (index, limit) {
                 (Some(i), Some(l)) => {
                     if i >= l {
-                        return;
+                        self.stack_machine.end_loop();
+                        return Ok(());
                     }
-                    self.interpret(&Ast::Expressions(body.to_vec()), context, output);
+                    // a round counts as a step, so even an empty loop can be stopped
+                    self.step()?;
+                    self.interpret_nodes(body, context, input, output, hook)?;
                     self.stack_machine.pop_loop();
                     self.stack_machine.push_loop(i + 1);
                 }
-                (Some(_), None) => panic!("No limit value for loop"),
-                (None, Some(_)) => panic!("No index value for loop"),
-                (None, None) => panic!("No index and limit value for loop"),
+                (Some(_), None) => return Err(StackyError::runtime("No limit value for loop")),
+                (None, Some(_)) => return Err(StackyError::runtime("No index value for loop")),
+                (None, None) => {
+                    return Err(StackyError::runtime("No index and limit value for loop"))
+                }
             }
         }
     }
 }
+
+fn exceeded(limit: Limit, max: u64) -> StackyError {
+    StackyError::LimitExceeded { limit, max }
+}
@@ -1,9 +1,19 @@
+use std::collections::HashSet;
+use std::fmt;
 use std::iter::Peekable;
 use std::str::Chars;
+use std::sync::Arc;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::{error::StackyError, span::Span};
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum Token {
-    Number(i32),
+    Number(i64),
+    BigNumber(BigInt),
+    Float(f64),
     StringLiteral(String),
     Identifier(String),
     LoopVariable(u8),
@@ -30,158 +40,674 @@ pub(crate) enum Token {
     Colon,
     SemiColon,
     Puts,
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
     Arrow,
     At,
+    IntToFloat,
+    FloatToInt,
+    FRound,
+    FFloor,
+    FTrunc,
+    Pragma,
+    Hex,
+    Decimal,
+    Binary,
+    Include,
+    Require,
+    Over,
+    Rot,
+    MinusRot,
+    Nip,
+    Tuck,
+    Pick,
+    Roll,
+    TwoDup,
+    TwoDrop,
+    TwoSwap,
+    TwoOver,
+    Depth,
+    Clear,
+    ToR,
+    FromR,
+    RFetch,
+    TwoToR,
+    TwoFromR,
+    Key,
+    Accept,
+    ReadLine,
+    NumberIn,
+    OpenFile,
+    CreateFile,
+    ReadFile,
+    WriteFile,
+    CloseFile,
+    FileSize,
+    DeleteFile,
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    ToArray,
+    FromArray,
+    ToMap,
+    FromMap,
+    Get,
+    Plugin,
+    /// a `( a b -- c )` comment right after the name of a new word
+    StackEffect(String),
+}
+
+/// tokens are shown the way they are written in the source
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let word = match self {
+            Token::Number(x) => return write!(f, "{}", x),
+            Token::BigNumber(x) => return write!(f, "{}", x),
+            Token::Float(x) => return write!(f, "{:?}", x),
+            Token::StringLiteral(x) => return write!(f, "{:?}", x),
+            Token::Identifier(x) => x,
+            Token::StackEffect(x) => return write!(f, "( {} )", x.trim()),
+            Token::LoopVariable(x) => ["I", "J", "K", "L", "M"][*x as usize],
+            Token::Add => "+",
+            Token::Sub => "-",
+            Token::Mul => "*",
+            Token::Div => "/",
+            Token::Emit => ".",
+            Token::Lt => "<",
+            Token::Gt => ">",
+            Token::Lte => "<=",
+            Token::Gte => ">=",
+            Token::Eq => "=",
+            Token::Percent => "%",
+            Token::DoubleEq => "==",
+            Token::Colon => ":",
+            Token::SemiColon => ";",
+            Token::Arrow => "->",
+            Token::At => "@",
+            Token::EOF => "end of input",
+            Token::If => "IF",
+            Token::Else => "ELSE",
+            Token::Then => "THEN",
+            Token::Dup => "DUP",
+            Token::Swap => "SWAP",
+            Token::Drop => "DROP",
+            Token::Over => "OVER",
+            Token::Rot => "ROT",
+            Token::MinusRot => "-ROT",
+            Token::Nip => "NIP",
+            Token::Tuck => "TUCK",
+            Token::Pick => "PICK",
+            Token::Roll => "ROLL",
+            Token::TwoDup => "2DUP",
+            Token::TwoDrop => "2DROP",
+            Token::TwoSwap => "2SWAP",
+            Token::TwoOver => "2OVER",
+            Token::Depth => "DEPTH",
+            Token::Clear => "CLEAR",
+            Token::ToR => ">R",
+            Token::FromR => "R>",
+            Token::RFetch => "R@",
+            Token::TwoToR => "2>R",
+            Token::TwoFromR => "2R>",
+            Token::Key => "KEY",
+            Token::Accept => "ACCEPT",
+            Token::ReadLine => "READ-LINE",
+            Token::NumberIn => "NUMBER-IN",
+            Token::OpenFile => "OPEN-FILE",
+            Token::CreateFile => "CREATE-FILE",
+            Token::ReadFile => "READ-FILE",
+            Token::WriteFile => "WRITE-FILE",
+            Token::CloseFile => "CLOSE-FILE",
+            Token::FileSize => "FILE-SIZE",
+            Token::DeleteFile => "DELETE-FILE",
+            Token::ReadOnly => "R/O",
+            Token::WriteOnly => "W/O",
+            Token::ReadWrite => "R/W",
+            Token::ToArray => ">ARRAY",
+            Token::FromArray => "ARRAY>",
+            Token::ToMap => ">MAP",
+            Token::FromMap => "MAP>",
+            Token::Get => "GET",
+            Token::Plugin => "PLUGIN",
+            Token::Do => "DO",
+            Token::Loop => "LOOP",
+            Token::Puts => "PUTS",
+            Token::IntToFloat => "S>F",
+            Token::FloatToInt => "F>S",
+            Token::FRound => "FROUND",
+            Token::FFloor => "FLOOR",
+            Token::FTrunc => "FTRUNC",
+            Token::Pragma => "PRAGMA",
+            Token::Hex => "HEX",
+            Token::Decimal => "DECIMAL",
+            Token::Binary => "BINARY",
+            Token::Include => "INCLUDE",
+            Token::Require => "REQUIRE",
+        };
+        write!(f, "{}", word)
+    }
+}
+
+/// what a lexeme of the lossless token stream is
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum LexemeKind {
+    Token(Token),
+    Whitespace,
+    /// a comment from \ to the end of the line, without the line break
+    LineComment,
+    /// a comment from ( to ), stack effects after the name of a word are tokens
+    Comment,
+    /// a character the lexer skips because it starts no token
+    Skipped,
+}
+
+/// a token or the whitespace or comment between tokens, with the text it was read from
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Lexeme {
+    pub(crate) kind: LexemeKind,
+    pub(crate) text: String,
+    pub(crate) span: Span,
 }
 
 pub(crate) struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
+    base: u32,
+    words: HashSet<String>,
+    prefer_native: bool,
+    file: Arc<str>,
+    line: usize,
+    column: usize,
+    start: (usize, usize),
+    /// the byte offset of the next character
+    offset: usize,
+    /// the byte offset of the token that is lexed right now
+    start_offset: usize,
+    /// the byte ranges of the tokens lexed so far, trailing whitespace included
+    ranges: Vec<(usize, usize)>,
 }
 
 impl<'a> Lexer<'a> {
-    pub(crate) fn new() -> Lexer<'a> {
+    /// creates a lexer for the source file `file`, which is only used to name it in spans
+    pub(crate) fn new(file: Arc<str>) -> Lexer<'a> {
         Lexer {
             chars: "".chars().peekable(),
+            base: 10,
+            words: HashSet::new(),
+            prefer_native: false,
+            file,
+            line: 1,
+            column: 1,
+            start: (1, 1),
+            offset: 0,
+            start_offset: 0,
+            ranges: vec![],
         }
     }
 
-    pub fn lex(&mut self, input: &'a str) -> Vec<Token> {
+    /// sets the base plain numbers are read in, HEX, DECIMAL, BINARY and `n -> BASE` change it while lexing
+    pub(crate) fn set_base(&mut self, base: u32) {
+        self.base = base;
+    }
+
+    /// names that are words and never numbers, even if they consist of digits of the current base
+    pub(crate) fn set_words(&mut self, words: HashSet<String>) {
+        self.words = words;
+    }
+
+    /// keeps native words when a definition with the same name is lexed, the parser drops those definitions
+    pub(crate) fn set_prefer_native(&mut self, prefer_native: bool) {
+        self.prefer_native = prefer_native;
+    }
+
+    pub fn lex(&mut self, input: &'a str) -> Result<Vec<(Token, Span)>, StackyError> {
         self.chars = input.chars().peekable();
+        self.line = 1;
+        self.column = 1;
+        self.offset = 0;
+        self.ranges.clear();
 
-        let mut tokens = vec![];
-        while let Some(token) = self.next_token() {
-            tokens.push(token);
+        let mut tokens: Vec<(Token, Span)> = vec![];
+        // how deep the token is inside definitions, IFs and DOs
+        let mut depth = 0_usize;
+        loop {
+            let token = match self.next_token(&tokens) {
+                Ok(Some(token)) => token,
+                Ok(None) => break,
+                Err(error) => return Err(error.at(self.start_span())),
+            };
+            if let Some(error) = base_change(&tokens, &token, depth) {
+                return Err(StackyError::syntax(error).at(self.start_span()));
+            }
+            match token {
+                Token::Colon | Token::If | Token::Do => depth += 1,
+                Token::SemiColon | Token::Then | Token::Loop => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            match (&tokens[..], &token) {
+                ([.., (Token::Number(base), _), (Token::Arrow, _)], Token::Identifier(name))
+                    if name == "BASE" && (2..=36).contains(base) =>
+                {
+                    self.base = *base as u32;
+                }
+                ([.., (Token::Colon | Token::Arrow, _)], Token::Identifier(name))
+                    if !(self.prefer_native && is_native(name)) =>
+                {
+                    self.words.insert(name.clone());
+                }
+                (_, Token::Hex) => self.base = 16,
+                (_, Token::Decimal) => self.base = 10,
+                (_, Token::Binary) => self.base = 2,
+                _ => {}
+            }
+            tokens.push((token, self.start_span()));
+            self.ranges.push((self.start_offset, self.offset));
+        }
+        self.start = (self.line, self.column);
+        tokens.push((Token::EOF, self.start_span()));
+        Ok(tokens)
+    }
+
+    /// lexes the input and keeps the whitespace and comments between the tokens, the texts of the
+    /// lexemes make up the input and the last lexeme is EOF with an empty text
+    pub(crate) fn lex_lossless(&mut self, input: &'a str) -> Result<Vec<Lexeme>, StackyError> {
+        let mut tokens = self.lex(input)?;
+        let (eof, eof_span) = tokens.pop().expect("the last token is EOF");
+        let mut lexemes = vec![];
+        let mut position = Position::default();
+        for ((token, span), &(start, end)) in tokens.into_iter().zip(&self.ranges) {
+            // identifiers take the whitespace after them, which is trivia
+            let end = start + input[start..end].trim_end().len();
+            self.trivia(input, &mut position, start, &mut lexemes);
+            lexemes.push(Lexeme {
+                kind: LexemeKind::Token(token),
+                text: input[start..end].to_string(),
+                span,
+            });
+            position.advance(input, end);
         }
-        tokens.push(Token::EOF);
-        tokens
+        self.trivia(input, &mut position, input.len(), &mut lexemes);
+        lexemes.push(Lexeme {
+            kind: LexemeKind::Token(eof),
+            text: String::new(),
+            span: eof_span,
+        });
+        Ok(lexemes)
     }
 
-    fn require_whitespace(&mut self) {
+    /// splits the text up to `end` into whitespace and comments
+    fn trivia(&self, input: &str, position: &mut Position, end: usize, lexemes: &mut Vec<Lexeme>) {
+        while position.offset < end {
+            let rest = &input[position.offset..end];
+            let (kind, length) = match rest.chars().next() {
+                Some('\\') => (
+                    LexemeKind::LineComment,
+                    rest.find('\n').unwrap_or(rest.len()),
+                ),
+                Some('(') => (
+                    LexemeKind::Comment,
+                    rest.find(')').map_or(rest.len(), |close| close + 1),
+                ),
+                Some(c) if !c.is_whitespace() => (LexemeKind::Skipped, c.len_utf8()),
+                _ => (
+                    LexemeKind::Whitespace,
+                    rest.find(|c: char| !c.is_whitespace())
+                        .unwrap_or(rest.len()),
+                ),
+            };
+            let span = position.span(&self.file);
+            let text = rest[..length].to_string();
+            position.advance(input, position.offset + length);
+            lexemes.push(Lexeme { kind, text, span });
+        }
+    }
+
+    /// consumes the next character and keeps track of the position
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// the span of the token that is lexed right now
+    fn start_span(&self) -> Span {
+        Span {
+            file: self.file.clone(),
+            line: self.start.0,
+            column: self.start.1,
+        }
+    }
+
+    fn require_whitespace(&mut self) -> Result<(), StackyError> {
         if let Some(&c) = self.chars.peek() {
             if !c.is_whitespace() {
-                panic!("Expected whitespace");
+                return Err(StackyError::syntax("Expected whitespace"));
             }
         }
-        self.chars.next(); // consume whitespace
+        self.bump(); // consume whitespace
+        Ok(())
     }
 
-    fn next_token(&mut self) -> Option<Token> {
+    fn next_token(&mut self, tokens: &[(Token, Span)]) -> Result<Option<Token>, StackyError> {
+        let previous = tokens.last().map(|(token, _)| token);
         while let Some(&c) = self.chars.peek() {
             if c.is_whitespace() {
-                self.chars.next();
+                self.bump();
+                continue;
+            }
+            self.start = (self.line, self.column);
+            self.start_offset = self.offset;
+
+            if c == '\\' && self.whitespace_follows(1) {
+                self.line_comment();
                 continue;
             }
+            if c == '(' && self.whitespace_follows(1) {
+                let comment = self.comment()?;
+                // a comment with -- after the name of a word declares its stack effect
+                let is_after_name =
+                    matches!(tokens, [.., (Token::Colon, _), (Token::Identifier(_), _)]);
+                if is_after_name && comment.split_whitespace().any(|item| item == "--") {
+                    return Ok(Some(Token::StackEffect(comment)));
+                }
+                continue;
+            }
+
+            // paths after INCLUDE and REQUIRE are read up to the next whitespace unless they are quoted
+            if matches!(previous, Some(Token::Include | Token::Require)) && c != '"' {
+                return Ok(Some(Token::StringLiteral(self.word())));
+            }
 
-            if c.is_numeric() {
-                return Some(self.number(false));
+            // keywords like S>F contain symbols, so they are matched as a whole word first
+            if let Some(token) = self.symbolic_keyword() {
+                return Ok(Some(token));
+            }
+
+            // the name of a new word is never a number, and never a keyword unless it redefines a native word
+            let is_name = matches!(previous, Some(Token::Colon | Token::Arrow | Token::At));
+            let is_definition = matches!(previous, Some(Token::Colon));
+
+            if c.is_ascii_digit() {
+                // words like 2DUP start with a digit but are no numbers
+                let name = self.name();
+                let is_word = is_name || self.words.contains(&name) || keyword(&name).is_some();
+                if is_word && !name.chars().all(|c| c.is_ascii_digit()) {
+                    return self.identifier(is_name, is_definition).map(Some);
+                }
+                return self.number(false).map(Some);
             }
 
             if c.is_ascii_alphabetic() {
-                return Some(self.identifier());
+                return self.identifier(is_name, is_definition).map(Some);
             }
 
             match c {
-                '"' => return Some(self.string()),
-                '>' => return Some(self.greater_than()),
-                '<' => return Some(self.less_than()),
-                '=' => return Some(self.equal()),
-                '-' => return Some(self.sub()),
+                '"' => return self.string().map(Some),
+                '\'' => return self.character().map(Some),
+                '>' => return Ok(Some(self.greater_than())),
+                '<' => return Ok(Some(self.less_than())),
+                '=' => return Ok(Some(self.equal())),
+                '-' => return self.sub().map(Some),
+                '$' if self.radix_digit_follows(1, 16) => return Ok(Some(self.prefixed(1, 16))),
+                '%' if self.radix_digit_follows(1, 2) => return Ok(Some(self.prefixed(1, 2))),
+                '#' if self.radix_digit_follows(1, 10) => return Ok(Some(self.prefixed(1, 10))),
                 _ => {}
             }
 
             // parse single character tokens
-            self.chars.next();
+            self.bump();
             match c {
-                '+' => return Some(Token::Add),
-                '*' => return Some(Token::Mul),
-                '/' => return Some(Token::Div),
-                '.' => return Some(Token::Emit),
-                ':' => return Some(Token::Colon),
-                ';' => return Some(Token::SemiColon),
-                '@' => return Some(Token::At),
-                '%' => return Some(Token::Percent),
+                '+' => return Ok(Some(Token::Add)),
+                '*' => return Ok(Some(Token::Mul)),
+                '/' => return Ok(Some(Token::Div)),
+                '.' => return Ok(Some(Token::Emit)),
+                ':' => return Ok(Some(Token::Colon)),
+                ';' => return Ok(Some(Token::SemiColon)),
+                '@' => return Ok(Some(Token::At)),
+                '%' => return Ok(Some(Token::Percent)),
                 _ => {}
             }
         }
 
-        None
+        Ok(None)
+    }
+
+    fn number(&mut self, is_negative: bool) -> Result<Token, StackyError> {
+        // 0x, 0b and 0o select the base no matter what BASE is
+        if self.chars.peek() == Some(&'0') {
+            let mut ahead = self.chars.clone();
+            ahead.next(); // skip the 0
+            let radix = match ahead.peek() {
+                Some('x') => 16,
+                Some('b') => 2,
+                Some('o') => 8,
+                _ => 0,
+            };
+            if radix != 0 && self.radix_digit_follows(2, radix) {
+                let token = self.prefixed(2, radix);
+                return Ok(if is_negative { negate(token) } else { token });
+            }
+        }
+
+        let mut number = String::default();
+        self.digits(&mut number, self.base.max(10));
+
+        // a dot only starts a fraction if a digit follows, so `1.` is still 1 followed by emit
+        if self.base == 10 && self.chars.peek() == Some(&'.') && self.digit_follows(1) {
+            number.push('.');
+            self.bump(); // consume the .
+            self.digits(&mut number, 10);
+            if matches!(self.chars.peek(), Some('e') | Some('E')) {
+                self.exponent(&mut number);
+            }
+
+            let num: f64 = number.parse().unwrap();
+            if is_negative {
+                return Ok(Token::Float(-num));
+            }
+            return Ok(Token::Float(num));
+        }
+
+        if !number.chars().all(|c| c.is_digit(self.base)) {
+            return Err(StackyError::syntax(format!(
+                "Invalid digit in {} for base {}",
+                number, self.base
+            )));
+        }
+        let token = integer(&number, self.base);
+        if is_negative {
+            return Ok(negate(token));
+        }
+        Ok(token)
     }
 
-    fn number(&mut self, is_negative: bool) -> Token {
+    /// reads a number after a prefix of `length` characters like $ or 0x
+    fn prefixed(&mut self, length: usize, radix: u32) -> Token {
+        for _ in 0..length {
+            self.bump(); // consume the prefix
+        }
         let mut number = String::default();
+        self.digits(&mut number, radix);
+        integer(&number, radix)
+    }
+
+    fn character(&mut self) -> Result<Token, StackyError> {
+        self.bump(); // consume the initial '
+        let c = match self.bump() {
+            Some('\\') => match self.bump() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '\'')) => c,
+                _ => return Err(StackyError::syntax("Unknown escape in character literal")),
+            },
+            Some(c) => c,
+            None => return Err(StackyError::syntax("Unterminated character literal")),
+        };
+        if self.bump() != Some('\'') {
+            return Err(StackyError::syntax("Unterminated character literal"));
+        }
+        Ok(Token::Number(c as i64))
+    }
+
+    fn digits(&mut self, number: &mut String, radix: u32) {
         while let Some(&c) = self.chars.peek() {
-            if c.is_numeric() {
+            if c.is_digit(radix) {
                 number.push(c);
-                self.chars.next();
+                self.bump();
             } else {
                 break;
             }
         }
+    }
 
-        let num = number.parse().unwrap();
+    fn exponent(&mut self, number: &mut String) {
+        let mut ahead = self.chars.clone();
+        ahead.next(); // skip the e
+        let offset = match ahead.peek() {
+            Some('+') | Some('-') => 2,
+            _ => 1,
+        };
+        if !self.digit_follows(offset) {
+            return;
+        }
 
-        if is_negative {
-            return Token::Number(num * -1);
+        for _ in 0..offset {
+            number.push(self.bump().unwrap());
         }
-        Token::Number(num)
+        self.digits(number, 10);
+    }
+
+    /// checks if the character `offset` positions ahead is a decimal digit
+    fn digit_follows(&self, offset: usize) -> bool {
+        self.radix_digit_follows(offset, 10)
+    }
+
+    fn radix_digit_follows(&self, offset: usize, radix: u32) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.nth(offset).is_some_and(|c| c.is_digit(radix))
+    }
+
+    /// the alphanumeric name starting at the next character, without consuming it
+    fn name(&self) -> String {
+        self.chars
+            .clone()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect()
     }
 
-    fn string(&mut self) -> Token {
-        self.chars.next(); // consume the initial "
+    /// checks if the character `offset` positions ahead is whitespace or the end of the input
+    fn whitespace_follows(&self, offset: usize) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.nth(offset).is_none_or(|c| c.is_whitespace())
+    }
+
+    /// skips a comment from \ to the end of the line
+    fn line_comment(&mut self) {
+        while let Some(c) = self.bump() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// reads a comment from ( to the next ) and returns the text between them
+    fn comment(&mut self) -> Result<String, StackyError> {
+        self.bump(); // consume the (
+        let mut comment = String::default();
+        while let Some(c) = self.bump() {
+            if c == ')' {
+                return Ok(comment);
+            }
+            comment.push(c);
+        }
+        Err(StackyError::syntax("Unterminated comment"))
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::default();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            word.push(c);
+            self.bump();
+        }
+        word
+    }
+
+    fn symbolic_keyword(&mut self) -> Option<Token> {
+        let word: String = self
+            .chars
+            .clone()
+            .take_while(|c| !c.is_whitespace())
+            .collect();
+        if word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return None; // plain identifiers and numbers are handled by their own rules
+        }
+
+        let token = keyword(&word)?;
+        for _ in 0..word.chars().count() {
+            self.bump();
+        }
+        Some(token)
+    }
+
+    fn string(&mut self) -> Result<Token, StackyError> {
+        self.bump(); // consume the initial "
         let mut string = String::default();
         while let Some(&c) = self.chars.peek() {
             if c == '"' {
-                self.chars.next(); // consume the final "
-                return Token::StringLiteral(string);
+                self.bump(); // consume the final "
+                return Ok(Token::StringLiteral(string));
             } else {
                 string.push(c);
-                self.chars.next();
+                self.bump();
             }
         }
-        self.require_whitespace();
-        Token::StringLiteral(string)
+        self.require_whitespace()?;
+        Ok(Token::StringLiteral(string))
     }
 
-    fn identifier(&mut self) -> Token {
+    fn identifier(&mut self, is_name: bool, is_definition: bool) -> Result<Token, StackyError> {
         let mut identifier = String::default();
         while let Some(&c) = self.chars.peek() {
             if c.is_ascii_alphanumeric() || c == '_' {
                 identifier.push(c);
-                self.chars.next();
+                self.bump();
             } else {
                 break;
             }
         }
 
-        self.require_whitespace();
+        self.require_whitespace()?;
 
-        // check if the identifier is a keyword
-        match identifier.as_str() {
-            "IF" => return Token::If,
-            "ELSE" => return Token::Else,
-            "THEN" => return Token::Then,
-            "DUP" => return Token::Dup,
-            "SWAP" => return Token::Swap,
-            "DROP" => return Token::Drop,
-            "DO" => return Token::Do,
-            "LOOP" => return Token::Loop,
-            "PUTS" => return Token::Puts,
-            "I" => return Token::LoopVariable(0),
-            "J" => return Token::LoopVariable(1),
-            "K" => return Token::LoopVariable(2),
-            "L" => return Token::LoopVariable(3),
-            "M" => return Token::LoopVariable(4),
-            _ => {}
+        // check if the identifier is a keyword, native words are replaced by definitions with their name
+        if let Some(token) = keyword(&identifier) {
+            let is_defined = is_definition || self.words.contains(&identifier);
+            if !(is_defined && is_native(&identifier)) {
+                return Ok(token);
+            }
         }
-        Token::Identifier(identifier)
+
+        // with a base above 10, words like FF are numbers unless they are defined
+        let is_number = identifier.chars().all(|c| c.is_digit(self.base));
+        if self.base > 10 && is_number && !is_name && !self.words.contains(&identifier) {
+            return Ok(integer(&identifier, self.base));
+        }
+        Ok(Token::Identifier(identifier))
     }
 
     fn greater_than(&mut self) -> Token {
-        self.chars.next(); // consume the initial >
+        self.bump(); // consume the initial >
         if let Some(&c) = self.chars.peek() {
             if c == '=' {
-                self.chars.next(); // consume the =
+                self.bump(); // consume the =
                 return Token::Gte;
             }
         }
@@ -189,10 +715,10 @@ impl<'a> Lexer<'a> {
     }
 
     fn less_than(&mut self) -> Token {
-        self.chars.next(); // consume the initial <
+        self.bump(); // consume the initial <
         if let Some(&c) = self.chars.peek() {
             if c == '=' {
-                self.chars.next(); // consume the =
+                self.bump(); // consume the =
                 return Token::Lte;
             }
         }
@@ -200,26 +726,197 @@ impl<'a> Lexer<'a> {
     }
 
     fn equal(&mut self) -> Token {
-        self.chars.next(); // consume the initial =
+        self.bump(); // consume the initial =
         if let Some(&c) = self.chars.peek() {
             if c == '=' {
-                self.chars.next(); // consume the =
+                self.bump(); // consume the =
                 return Token::DoubleEq;
             }
         }
         Token::Eq
     }
 
-    fn sub(&mut self) -> Token {
-        self.chars.next(); // consume the initial -
+    fn sub(&mut self) -> Result<Token, StackyError> {
+        self.bump(); // consume the initial -
         if let Some(&c) = self.chars.peek() {
             if c == '>' {
-                self.chars.next(); // consume the >
-                return Token::Arrow;
-            } else if c.is_numeric() {
+                self.bump(); // consume the >
+                return Ok(Token::Arrow);
+            } else if c.is_ascii_digit() {
                 return self.number(true);
             }
         }
-        Token::Sub
+        Ok(Token::Sub)
+    }
+}
+
+/// a position in the input while the lossless token stream is put together
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    /// moves to the byte offset `to`, counting the lines and columns on the way
+    fn advance(&mut self, input: &str, to: usize) {
+        for c in input[self.offset..to].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.offset = to;
+    }
+
+    fn span(&self, file: &Arc<str>) -> Span {
+        Span {
+            file: file.clone(),
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+pub(crate) fn keyword(word: &str) -> Option<Token> {
+    match word {
+        "IF" => Some(Token::If),
+        "ELSE" => Some(Token::Else),
+        "THEN" => Some(Token::Then),
+        "DUP" => Some(Token::Dup),
+        "SWAP" => Some(Token::Swap),
+        "DROP" => Some(Token::Drop),
+        "OVER" => Some(Token::Over),
+        "ROT" => Some(Token::Rot),
+        "-ROT" => Some(Token::MinusRot),
+        "NIP" => Some(Token::Nip),
+        "TUCK" => Some(Token::Tuck),
+        "PICK" => Some(Token::Pick),
+        "ROLL" => Some(Token::Roll),
+        "2DUP" => Some(Token::TwoDup),
+        "2DROP" => Some(Token::TwoDrop),
+        "2SWAP" => Some(Token::TwoSwap),
+        "2OVER" => Some(Token::TwoOver),
+        "DEPTH" => Some(Token::Depth),
+        "CLEAR" => Some(Token::Clear),
+        ">R" => Some(Token::ToR),
+        "R>" => Some(Token::FromR),
+        "R@" => Some(Token::RFetch),
+        "2>R" => Some(Token::TwoToR),
+        "2R>" => Some(Token::TwoFromR),
+        "KEY" => Some(Token::Key),
+        "ACCEPT" => Some(Token::Accept),
+        "READ-LINE" => Some(Token::ReadLine),
+        "NUMBER-IN" => Some(Token::NumberIn),
+        "OPEN-FILE" => Some(Token::OpenFile),
+        "CREATE-FILE" => Some(Token::CreateFile),
+        "READ-FILE" => Some(Token::ReadFile),
+        "WRITE-FILE" => Some(Token::WriteFile),
+        "CLOSE-FILE" => Some(Token::CloseFile),
+        "FILE-SIZE" => Some(Token::FileSize),
+        "DELETE-FILE" => Some(Token::DeleteFile),
+        "R/O" => Some(Token::ReadOnly),
+        "W/O" => Some(Token::WriteOnly),
+        "R/W" => Some(Token::ReadWrite),
+        ">ARRAY" => Some(Token::ToArray),
+        "ARRAY>" => Some(Token::FromArray),
+        ">MAP" => Some(Token::ToMap),
+        "MAP>" => Some(Token::FromMap),
+        "GET" => Some(Token::Get),
+        "PLUGIN" => Some(Token::Plugin),
+        "DO" => Some(Token::Do),
+        "LOOP" => Some(Token::Loop),
+        "PUTS" => Some(Token::Puts),
+        "S>F" => Some(Token::IntToFloat),
+        "F>S" => Some(Token::FloatToInt),
+        "FROUND" => Some(Token::FRound),
+        "FLOOR" => Some(Token::FFloor),
+        "FTRUNC" => Some(Token::FTrunc),
+        "PRAGMA" => Some(Token::Pragma),
+        "HEX" => Some(Token::Hex),
+        "DECIMAL" => Some(Token::Decimal),
+        "BINARY" => Some(Token::Binary),
+        "INCLUDE" => Some(Token::Include),
+        "REQUIRE" => Some(Token::Require),
+        "I" => Some(Token::LoopVariable(0)),
+        "J" => Some(Token::LoopVariable(1)),
+        "K" => Some(Token::LoopVariable(2)),
+        "L" => Some(Token::LoopVariable(3)),
+        "M" => Some(Token::LoopVariable(4)),
+        _ => None,
+    }
+}
+
+/// numbers are read in the base that is set when they are lexed, so the base may only change where
+/// the change always runs before the code after it: at the top level and to a number literal
+fn base_change(tokens: &[(Token, Span)], token: &Token, depth: usize) -> Option<String> {
+    let word = match (tokens, token) {
+        ([.., (Token::Arrow, _)], Token::Identifier(name)) if name == "BASE" => {
+            if !matches!(tokens, [.., (Token::Number(_), _), _]) {
+                return Some("-> BASE needs a number right before it".into());
+            }
+            "-> BASE".to_string()
+        }
+        (_, Token::Hex | Token::Decimal | Token::Binary) => token.to_string(),
+        _ => return None,
+    };
+    (depth > 0).then(|| {
+        format!(
+            "{} can only be used at the top level, outside of definitions, IF and DO",
+            word
+        )
+    })
+}
+
+/// keywords that are words run by the stack machine, as opposed to syntax like IF or DO
+pub(crate) fn is_native(name: &str) -> bool {
+    !matches!(
+        keyword(name),
+        None | Some(
+            Token::If
+                | Token::Else
+                | Token::Then
+                | Token::Do
+                | Token::Loop
+                | Token::LoopVariable(_)
+                | Token::Pragma
+                | Token::Include
+                | Token::Require
+        )
+    )
+}
+
+fn integer(digits: &str, radix: u32) -> Token {
+    match i64::from_str_radix(digits, radix) {
+        Ok(num) => Token::Number(num),
+        // literals beyond 64 bits are kept exact, the interpreter decides if they are allowed
+        Err(_) => Token::BigNumber(BigInt::parse_bytes(digits.as_bytes(), radix).unwrap()),
+    }
+}
+
+fn negate(token: Token) -> Token {
+    match token {
+        Token::Number(num) => match num.checked_neg() {
+            Some(num) => Token::Number(num),
+            None => Token::BigNumber(-BigInt::from(num)),
+        },
+        Token::BigNumber(num) => match (-&num).to_i64() {
+            // -9223372036854775808 only fits into 64 bits once it is negative
+            Some(num) => Token::Number(num),
+            None => Token::BigNumber(-num),
+        },
+        token => token,
     }
 }
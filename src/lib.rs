@@ -0,0 +1,52 @@
+//! stacky is a small Forth-like language, [`Engine`] runs it inside Rust programs
+//!
+//! ```
+//! use rust_stacky::{Engine, Value};
+//!
+//! let mut engine = Engine::new();
+//! engine.eval(": Square DUP * ;").unwrap();
+//! engine.push(7);
+//! engine.call("Square").unwrap();
+//! assert_eq!(engine.pop().unwrap(), Value::Number(49));
+//! ```
+
+mod bridge;
+mod checker;
+mod context;
+mod cst;
+mod debugger;
+mod engine;
+mod error;
+mod ffi;
+mod files;
+mod formatter;
+mod interpreter;
+mod lexer;
+mod limits;
+mod linter;
+mod loader;
+mod native;
+mod optimizer;
+mod parser;
+mod plugin;
+mod profiler;
+mod span;
+mod stack_machine;
+mod stacky;
+mod tests;
+mod tracer;
+
+pub use bridge::{from_value, to_value, Deserializer, Serializer};
+pub use debugger::Debugger;
+pub use engine::{Engine, Program, Value};
+pub use error::StackyError;
+pub use formatter::format_source;
+pub use interpreter::{Event, Hook};
+pub use limits::{CancelHandle, Limit, Limits, MAX_CALLS};
+pub use linter::{Lint, Linter, Rule};
+pub use native::{NativeWord, Stack};
+pub use plugin::{StackyPluginApi, StackyRegistrar, StackyStack, StackyWordFn, PLUGIN_ABI_VERSION};
+pub use profiler::Profiler;
+pub use span::Span;
+pub use stack_machine::OverflowPolicy;
+pub use tracer::Tracer;
@@ -0,0 +1,172 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// a resource a program may only use up to a limit
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub enum Limit {
+    /// nodes run and rounds of loops
+    Steps,
+    /// items on the data stack and the return stack
+    Stack,
+    /// loops running inside each other
+    Loops,
+    /// words running inside each other, never more than `MAX_CALLS`
+    Calls,
+    /// bytes of a single item, arrays and maps count the bytes of their items
+    ItemSize,
+    /// bytes written to the output
+    Output,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Limit::Steps => "steps",
+            Limit::Stack => "stack",
+            Limit::Loops => "loops",
+            Limit::Calls => "calls",
+            Limit::ItemSize => "item-size",
+            Limit::Output => "output",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// the most words that run inside each other, a lower calls limit lowers it
+pub const MAX_CALLS: usize = 1000;
+
+/// the limits of a run, no limit is set by default
+///
+/// ```
+/// let limits = rust_stacky::Limits::new().steps(10_000).output(4096);
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+#[non_exhaustive]
+pub struct Limits {
+    pub steps: Option<u64>,
+    pub stack: Option<usize>,
+    pub loops: Option<usize>,
+    pub calls: Option<usize>,
+    pub item_size: Option<usize>,
+    pub output: Option<usize>,
+}
+
+impl Limits {
+    /// no limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn steps(mut self, max: u64) -> Self {
+        self.steps = Some(max);
+        self
+    }
+
+    pub fn stack(mut self, max: usize) -> Self {
+        self.stack = Some(max);
+        self
+    }
+
+    pub fn loops(mut self, max: usize) -> Self {
+        self.loops = Some(max);
+        self
+    }
+
+    pub fn calls(mut self, max: usize) -> Self {
+        self.calls = Some(max);
+        self
+    }
+
+    pub fn item_size(mut self, max: usize) -> Self {
+        self.item_size = Some(max);
+        self
+    }
+
+    pub fn output(mut self, max: usize) -> Self {
+        self.output = Some(max);
+        self
+    }
+
+    /// sets a limit from `name=value`, like `steps=1000`
+    pub fn set(&mut self, setting: &str) -> Result<(), String> {
+        let Some((name, value)) = setting.split_once('=') else {
+            return Err(format!("Expected name=value, got {}", setting));
+        };
+        let value: usize = value
+            .parse()
+            .map_err(|_| format!("Invalid limit {} for {}", value, name))?;
+        match name {
+            "steps" => self.steps = Some(value as u64),
+            "stack" => self.stack = Some(value),
+            "loops" => self.loops = Some(value),
+            "calls" => self.calls = Some(value),
+            "item-size" => self.item_size = Some(value),
+            "output" => self.output = Some(value),
+            _ => return Err(format!("Unknown limit {}", name)),
+        }
+        Ok(())
+    }
+}
+
+/// stops a run from another thread, the run stops before its next step
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// stops the current run, nothing happens if none is running
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// if the run should stop, the request is used up by asking
+    pub(crate) fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// an output that takes no more than a number of bytes and remembers if more were written
+pub(crate) struct LimitedOutput<'a, W> {
+    output: &'a mut W,
+    left: Option<usize>,
+    exceeded: bool,
+}
+
+impl<'a, W: Write> LimitedOutput<'a, W> {
+    pub(crate) fn new(output: &'a mut W, limit: Option<usize>) -> Self {
+        Self {
+            output,
+            left: limit,
+            exceeded: false,
+        }
+    }
+
+    pub(crate) fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl<W: Write> Write for LimitedOutput<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(left) = self.left else {
+            return self.output.write(buf);
+        };
+        // the rest is dropped, the interpreter stops the program once the node is done
+        if buf.len() > left {
+            self.exceeded = true;
+        }
+        self.output.write_all(&buf[..buf.len().min(left)])?;
+        self.left = Some(left.saturating_sub(buf.len()));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
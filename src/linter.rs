@@ -0,0 +1,334 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{
+    cst::{Cst, SyntaxKind},
+    error::StackyError,
+    lexer::{keyword, Lexeme, LexemeKind, Lexer, Token},
+    span::Span,
+};
+
+/// a check of the linter
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Rule {
+    /// a word that is defined and never called
+    UnusedWord,
+    /// a word that is defined again, the second definition replaces the first
+    RedefinedWord,
+    /// a variable that is read before anything is assigned to it
+    UnassignedVariable,
+    /// a DO with constant bounds that never runs its body
+    EmptyLoop,
+    /// an IF or ELSE without a body
+    EmptyBranch,
+    /// a word or variable named like a built-in word or a loop variable
+    Shadowing,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedWord,
+        Rule::RedefinedWord,
+        Rule::UnassignedVariable,
+        Rule::EmptyLoop,
+        Rule::EmptyBranch,
+        Rule::Shadowing,
+    ];
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Rule::UnusedWord => "unused-word",
+            Rule::RedefinedWord => "redefined-word",
+            Rule::UnassignedVariable => "unassigned-variable",
+            Rule::EmptyLoop => "empty-loop",
+            Rule::EmptyBranch => "empty-branch",
+            Rule::Shadowing => "shadowing",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.to_string() == name)
+            .ok_or_else(|| format!("Unknown rule {}", name))
+    }
+}
+
+/// a problem the linter found
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+    pub rule: Rule,
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.span, self.message, self.rule)
+    }
+}
+
+/// checks source code for mistakes that are no errors, every rule is on by default
+///
+/// a comment with `lint: allow` and rule names, like `\ lint: allow unused-word`, turns the rules
+/// off for its line and the next one
+#[derive(Debug, Clone)]
+pub struct Linter {
+    rules: HashSet<Rule>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self {
+            rules: Rule::ALL.into_iter().collect(),
+        }
+    }
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// turns a rule on or off
+    pub fn set_rule(&mut self, rule: Rule, enabled: bool) {
+        match enabled {
+            true => self.rules.insert(rule),
+            false => self.rules.remove(&rule),
+        };
+    }
+
+    /// lints the source of the file `file`, which is only used to name it in spans, sources that do
+    /// not parse fail with the error the loader reports
+    pub fn lint(&self, file: &str, source: &str) -> Result<Vec<Lint>, StackyError> {
+        let cst = Cst::build(Lexer::new(Arc::from(file)).lex_lossless(source)?);
+        cst.lower()?;
+
+        let mut scan = Scan::default();
+        scan.node(&cst, None);
+        let mut allowed = HashMap::new();
+        collect_allowed(&cst, &mut allowed);
+        let mut lints = scan.finish();
+        lints.retain(|lint| {
+            let suppressed = allowed
+                .get(&lint.span.line)
+                .is_some_and(|rules: &HashSet<Rule>| rules.contains(&lint.rule));
+            self.rules.contains(&lint.rule) && !suppressed
+        });
+        lints.sort_by_key(|lint| (lint.span.line, lint.span.column));
+        Ok(lints)
+    }
+}
+
+/// collects the rules that comments turn off for each line
+fn collect_allowed(cst: &Cst, allowed: &mut HashMap<usize, HashSet<Rule>>) {
+    match cst {
+        Cst::Leaf(Lexeme {
+            kind: LexemeKind::LineComment | LexemeKind::Comment,
+            text,
+            span,
+        }) => {
+            let Some((_, rules)) = text.split_once("lint: allow") else {
+                return;
+            };
+            let rules = rules.trim_end_matches(')').split_whitespace();
+            for rule in rules.filter_map(|rule| rule.parse().ok()) {
+                for line in [span.line, span.line + 1] {
+                    allowed.entry(line).or_default().insert(rule);
+                }
+            }
+        }
+        Cst::Leaf(_) => {}
+        Cst::Node { children, .. } => children
+            .iter()
+            .for_each(|child| collect_allowed(child, allowed)),
+    }
+}
+
+/// what the linter learns from one walk over the tree
+#[derive(Default)]
+struct Scan {
+    lints: Vec<Lint>,
+    /// the definitions in source order
+    definitions: Vec<(String, Span)>,
+    /// the words called with the word they are called from
+    calls: Vec<(String, Option<String>)>,
+    assignments: Vec<(String, Span)>,
+    /// the variables read, with whether they are read inside a word
+    reads: Vec<(String, Span, bool)>,
+}
+
+impl Scan {
+    fn lint(&mut self, rule: Rule, span: &Span, message: String) {
+        self.lints.push(Lint {
+            rule,
+            span: span.clone(),
+            message,
+        });
+    }
+
+    /// walks a node that runs inside the definition of `word`, none at the top level
+    fn node(&mut self, cst: &Cst, word: Option<&str>) {
+        let (kind, children) = match cst {
+            Cst::Leaf(Lexeme {
+                kind: LexemeKind::Token(Token::Identifier(name)),
+                ..
+            }) => {
+                self.calls.push((name.clone(), word.map(str::to_string)));
+                return;
+            }
+            Cst::Leaf(_) => return,
+            Cst::Node { kind, children } => (*kind, children),
+        };
+        // the name after : , -> and @ is the second child that is no whitespace or comment
+        let index = nth_significant(children, 1);
+        let operand = match children.get(index) {
+            Some(Cst::Leaf(Lexeme {
+                kind: LexemeKind::Token(Token::Identifier(name)),
+                span,
+                ..
+            })) => Some((name.clone(), span)),
+            _ => None,
+        };
+
+        match kind {
+            SyntaxKind::WordDefinition => {
+                let Some((name, span)) = operand else {
+                    return self.body(children, word);
+                };
+                if let Some((_, first)) = self.definitions.iter().find(|(other, _)| *other == name)
+                {
+                    let message = format!("{} is already defined at line {}", name, first.line);
+                    self.lint(Rule::RedefinedWord, span, message);
+                }
+                self.shadowing(&name, span);
+                self.definitions.push((name.clone(), span.clone()));
+                self.body(&children[index + 1..], Some(&name));
+            }
+            SyntaxKind::SetVariable => {
+                if let Some((name, span)) = operand {
+                    self.shadowing(&name, span);
+                    self.assignments.push((name, span.clone()));
+                }
+            }
+            SyntaxKind::GetVariable => {
+                if let Some((name, span)) = operand {
+                    self.reads.push((name, span.clone(), word.is_some()));
+                }
+            }
+            SyntaxKind::If => {
+                let span = &children[0].first_token().unwrap().span;
+                let middle = children
+                    .iter()
+                    .position(|child| child.token() == Some(&Token::Else));
+                let end = children
+                    .iter()
+                    .position(|child| child.token() == Some(&Token::Then))
+                    .unwrap_or(children.len());
+                if is_empty(&children[1..middle.unwrap_or(end)]) {
+                    self.lint(Rule::EmptyBranch, span, "IF has an empty branch".into());
+                }
+                if let Some(middle) = middle {
+                    if is_empty(&children[middle + 1..end]) {
+                        let span = children[middle].first_token().unwrap().span.clone();
+                        self.lint(Rule::EmptyBranch, &span, "ELSE has an empty branch".into());
+                    }
+                }
+                self.body(children, word);
+            }
+            SyntaxKind::Loop => self.body(children, word),
+            // the operands of PRAGMA, INCLUDE and REQUIRE are no calls
+            SyntaxKind::Pragma | SyntaxKind::Include => {}
+            SyntaxKind::Program => self.body(children, word),
+        }
+    }
+
+    /// walks the children of a node and checks the loops among them
+    fn body(&mut self, children: &[Cst], word: Option<&str>) {
+        let significant: Vec<&Cst> = children
+            .iter()
+            .filter(|child| child.first_token().is_some())
+            .collect();
+        for (index, child) in significant.iter().enumerate() {
+            if let Cst::Node {
+                kind: SyntaxKind::Loop,
+                ..
+            } = child
+            {
+                let bounds = index
+                    .checked_sub(2)
+                    .map(|start| (significant[start].token(), significant[start + 1].token()));
+                if let Some((Some(Token::Number(limit)), Some(Token::Number(start)))) = bounds {
+                    if start >= limit {
+                        let span = child.first_token().unwrap().span.clone();
+                        let message = format!("DO from {} to {} never runs its body", start, limit);
+                        self.lint(Rule::EmptyLoop, &span, message);
+                    }
+                }
+            }
+            self.node(child, word);
+        }
+    }
+
+    /// checks the name of a new word or variable
+    fn shadowing(&mut self, name: &str, span: &Span) {
+        let message = match keyword(&name.to_ascii_uppercase()) {
+            Some(token @ Token::LoopVariable(_)) => {
+                format!("{} shadows the loop variable {}", name, token)
+            }
+            Some(token) => format!("{} shadows the built-in word {}", name, token),
+            None => return,
+        };
+        self.lint(Rule::Shadowing, span, message);
+    }
+
+    fn finish(mut self) -> Vec<Lint> {
+        for (name, span) in std::mem::take(&mut self.definitions) {
+            let called = self
+                .calls
+                .iter()
+                .any(|(call, from)| *call == name && from.as_ref() != Some(&name));
+            if !called {
+                self.lint(Rule::UnusedWord, &span, format!("{} is never used", name));
+            }
+        }
+        for (name, span, in_word) in std::mem::take(&mut self.reads) {
+            // words run after the code that defines them, so any assignment may come first
+            let assigned = self.assignments.iter().any(|(other, assignment)| {
+                *other == name
+                    && (in_word || (assignment.line, assignment.column) < (span.line, span.column))
+            });
+            if !assigned && name != "BASE" {
+                let message = format!("{} is read before it is assigned", name);
+                self.lint(Rule::UnassignedVariable, &span, message);
+            }
+        }
+        self.lints
+    }
+}
+
+/// the index of the `n`th child that is no whitespace or comment
+fn nth_significant(children: &[Cst], n: usize) -> usize {
+    children
+        .iter()
+        .enumerate()
+        .filter(|(_, child)| child.first_token().is_some())
+        .nth(n)
+        .map_or(children.len(), |(index, _)| index)
+}
+
+/// if a body holds only whitespace and comments
+fn is_empty(children: &[Cst]) -> bool {
+    children.iter().all(|child| child.first_token().is_none())
+}
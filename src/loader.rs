@@ -0,0 +1,189 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    cst::Cst,
+    error::StackyError,
+    lexer::Lexer,
+    parser::{Ast, Node},
+};
+
+/// reads source files and resolves the files they INCLUDE or REQUIRE
+pub(crate) struct Loader {
+    search_path: Vec<PathBuf>,
+    /// canonical paths of every file loaded so far, REQUIRE skips these
+    loaded: HashSet<PathBuf>,
+    /// the files that are being loaded right now, the last one is the innermost include
+    loading: Vec<PathBuf>,
+    /// if INCLUDE and REQUIRE may read files
+    file_access: bool,
+}
+
+impl Loader {
+    pub(crate) fn new() -> Self {
+        Self {
+            search_path: vec![],
+            loaded: HashSet::new(),
+            loading: vec![],
+            file_access: true,
+        }
+    }
+
+    /// forgets the files loaded so far, so REQUIRE loads them again
+    pub(crate) fn reset(&mut self) {
+        self.loaded.clear();
+        self.loading.clear();
+    }
+
+    /// allows or denies INCLUDE and REQUIRE access to the file system
+    pub(crate) fn set_file_access(&mut self, allowed: bool) {
+        self.file_access = allowed;
+    }
+
+    /// adds a directory that is searched for included files not found next to the including file
+    pub(crate) fn add_search_path(&mut self, dir: impl Into<PathBuf>) {
+        self.search_path.push(dir.into());
+    }
+
+    /// lexes and parses a source named `name`, includes are resolved relative to `dir`
+    pub(crate) fn load_source(
+        &mut self,
+        name: &str,
+        text: &str,
+        dir: &Path,
+        base: u32,
+        words: &HashSet<String>,
+    ) -> Result<Ast, StackyError> {
+        let mut lexer = Lexer::new(Arc::from(name));
+        lexer.set_base(base);
+        lexer.set_words(words.clone());
+        let mut ast = Cst::build(lexer.lex_lossless(text)?).lower()?;
+        self.resolve(&mut ast, dir, base, words)?;
+        Ok(ast)
+    }
+
+    pub(crate) fn load_file(
+        &mut self,
+        path: &Path,
+        base: u32,
+        words: &HashSet<String>,
+    ) -> Result<Ast, StackyError> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| StackyError::Io(format!("Cannot read {}: {}", path.display(), e)))?;
+        if let Some(start) = self.loading.iter().position(|file| *file == canonical) {
+            let cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(StackyError::runtime(format!(
+                "Include cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        let text = fs::read_to_string(&canonical)
+            .map_err(|e| StackyError::Io(format!("Cannot read {}: {}", path.display(), e)))?;
+        let dir = canonical.parent().unwrap_or(Path::new(".")).to_path_buf();
+
+        self.loaded.insert(canonical.clone());
+        self.loading.push(canonical);
+        let ast = self.load_source(&path.display().to_string(), &text, &dir, base, words);
+        self.loading.pop();
+        ast
+    }
+
+    /// loads the files of all INCLUDE and REQUIRE nodes in the ast
+    pub(crate) fn resolve(
+        &mut self,
+        ast: &mut Ast,
+        dir: &Path,
+        base: u32,
+        words: &HashSet<String>,
+    ) -> Result<(), StackyError> {
+        match ast {
+            Ast::Expressions(nodes) => self.resolve_nodes(nodes, dir, base, words),
+            Ast::WordDefinition { body, .. } | Ast::Loop { body } => reject_includes(body),
+            Ast::If { if_body, else_body } => {
+                reject_includes(if_body)?;
+                reject_includes(else_body)
+            }
+            Ast::Include { path, once, body } => {
+                if !self.file_access {
+                    let word = if *once { "REQUIRE" } else { "INCLUDE" };
+                    return Err(StackyError::runtime(format!(
+                        "{} needs file access, which is disabled",
+                        word
+                    )));
+                }
+                let path = self.find(path, dir)?;
+                let is_loaded = path
+                    .canonicalize()
+                    .is_ok_and(|path| self.loaded.contains(&path));
+                if *once && is_loaded {
+                    return Ok(());
+                }
+                if let Ast::Expressions(nodes) = self.load_file(&path, base, words)? {
+                    *body = nodes;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn resolve_nodes(
+        &mut self,
+        nodes: &mut [Node],
+        dir: &Path,
+        base: u32,
+        words: &HashSet<String>,
+    ) -> Result<(), StackyError> {
+        for node in nodes {
+            self.resolve(&mut node.ast, dir, base, words)
+                .map_err(|e| e.at(node.span.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// looks for a file next to the including file first and then in the search path
+    fn find(&self, path: &str, dir: &Path) -> Result<PathBuf, StackyError> {
+        let candidates = std::iter::once(dir).chain(self.search_path.iter().map(PathBuf::as_path));
+        for candidate in candidates {
+            let file = candidate.join(path);
+            if file.is_file() {
+                return Ok(file);
+            }
+        }
+        Err(StackyError::Io(format!("Cannot find {}", path)))
+    }
+}
+
+/// files are loaded before the program runs, so INCLUDE and REQUIRE only work where they run exactly
+/// once, an include in a word, IF or DO is an error
+fn reject_includes(nodes: &[Node]) -> Result<(), StackyError> {
+    for node in nodes {
+        match &node.ast {
+            Ast::Include { once, .. } => {
+                let word = if *once { "REQUIRE" } else { "INCLUDE" };
+                return Err(StackyError::syntax(format!(
+                    "{} can only be used at the top level, outside of definitions, IF and DO",
+                    word
+                ))
+                .at(node.span.clone()));
+            }
+            Ast::WordDefinition { body, .. } | Ast::Loop { body } => reject_includes(body)?,
+            Ast::If { if_body, else_body } => {
+                reject_includes(if_body)?;
+                reject_includes(else_body)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -1,35 +1,483 @@
-mod context;
-mod interpreter;
-mod lexer;
-mod parser;
-mod stack_machine;
-mod stacky;
-mod tests;
-
 use std::{
+    env,
     fs::{read_to_string, File},
-    io::Write,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    process, thread,
+    time::Duration,
+};
+
+use rust_stacky::{
+    format_source, Debugger, Engine, Hook, Limits, Linter, OverflowPolicy, Profiler, Rule,
+    StackyError, Tracer,
 };
 
+const USAGE: &str = "Usage: rust_stacky [debug [--break where]...] [--overflow checked|wrapping|saturating|bigint] [-I dir]... [--no-prelude] [--no-check] [--no-file-access] [--plugin path]... [--opt-level 0|1|2] [--limit name=value]... [--timeout seconds] [--input path] [--trace] [--trace-file path] [--trace-word name]... [--profile] [--profile-collapsed path] [--emit-tokens] [--emit-ast] [file]";
+
+const FMT_USAGE: &str = "Usage: rust_stacky fmt [--check] [file]...";
+
+const LINT_USAGE: &str = "Usage: rust_stacky lint [--allow rule]... [file]...";
+
+/// how a file is run outside of the debugger
+struct RunOptions {
+    emit_tokens: bool,
+    emit_ast: bool,
+    trace: Trace,
+    profile: Profile,
+}
+
+/// where the trace of a run goes and which words it covers
+#[derive(Default)]
+struct Trace {
+    enabled: bool,
+    /// stderr if not set
+    file: Option<String>,
+    /// all words if empty
+    words: Vec<String>,
+}
+
+/// what the profiler writes when the run is done
+#[derive(Default)]
+struct Profile {
+    /// a report to stderr
+    report: bool,
+    /// collapsed stacks for flamegraph tools to a file
+    collapsed: Option<String>,
+}
+
+impl Profile {
+    fn enabled(&self) -> bool {
+        self.report || self.collapsed.is_some()
+    }
+}
+
 fn main() {
-    let emit_tokens = true;
-    let emit_ast = true;
+    let mut emit_tokens = false;
+    let mut emit_ast = false;
+    let mut prelude = true;
+    let mut check = true;
+    let mut file_access = true;
+    let mut opt_level = 0;
+    let mut overflow_policy = OverflowPolicy::default();
+    let mut search_path = vec![];
+    let mut plugins = vec![];
+    let mut path = None;
+    let mut debug = false;
+    let mut breakpoints = vec![];
+    let mut trace = Trace::default();
+    let mut profile = Profile::default();
+    let mut limits = Limits::default();
+    let mut timeout = None;
+    let mut input_path = None;
 
-    // read file input as text and print it
-    let text = read_to_string("test_data/test.f").unwrap();
-    let mut stacky = stacky::Stacky::new();
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("fmt") {
+        args.next();
+        fmt(args.collect());
+        return;
+    }
+    if args.peek().map(String::as_str) == Some("lint") {
+        args.next();
+        lint(args.collect());
+        return;
+    }
+    if args.peek().map(String::as_str) == Some("debug") {
+        args.next();
+        debug = true;
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--break" if debug => match args.next() {
+                Some(breakpoint) => breakpoints.push(breakpoint),
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "--trace" => trace.enabled = true,
+            "--trace-file" | "--trace-word" => match args.next() {
+                Some(value) if arg == "--trace-file" => {
+                    trace.enabled = true;
+                    trace.file = Some(value);
+                }
+                Some(value) => {
+                    trace.enabled = true;
+                    trace.words.push(value);
+                }
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "--limit" => match args.next().map(|setting| limits.set(&setting)) {
+                Some(Ok(())) => {}
+                Some(Err(e)) => {
+                    eprintln!("{}\n{}", e, USAGE);
+                    process::exit(2);
+                }
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "--timeout" => match args.next().map(|seconds| seconds.parse()) {
+                Some(Ok(seconds)) => timeout = Some(Duration::from_secs(seconds)),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "--input" => match args.next() {
+                Some(path) => input_path = Some(path),
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "--profile" => profile.report = true,
+            "--profile-collapsed" => match args.next() {
+                Some(file) => profile.collapsed = Some(file),
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "--emit-tokens" => emit_tokens = true,
+            "--emit-ast" => emit_ast = true,
+            "--no-prelude" => prelude = false,
+            "--no-check" => check = false,
+            "--no-file-access" => file_access = false,
+            "--overflow" => match args.next().map(|policy| policy.parse()) {
+                Some(Ok(policy)) => overflow_policy = policy,
+                Some(Err(e)) => {
+                    eprintln!("{}\n{}", e, USAGE);
+                    process::exit(2);
+                }
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "--opt-level" => match args.next().map(|level| level.parse()) {
+                Some(Ok(level @ 0..=2)) => opt_level = level,
+                _ => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "--plugin" => match args.next() {
+                Some(plugin) => plugins.push(plugin),
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "-I" | "--include-path" => match args.next() {
+                Some(dir) => search_path.push(dir),
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            _ if arg.starts_with('-') => {
+                eprintln!("Unknown option {}\n{}", arg, USAGE);
+                process::exit(2);
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let mut engine = if prelude {
+        Engine::new()
+    } else {
+        Engine::without_prelude()
+    };
+    engine.set_overflow_policy(overflow_policy);
+    engine.set_check(check);
+    engine.set_opt_level(opt_level);
+    engine.set_limits(limits);
+    engine.set_file_access(file_access);
+    for dir in search_path {
+        engine.add_search_path(dir);
+    }
+    for plugin in plugins {
+        if let Err(error) = engine.load_plugin(&plugin) {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+    }
+
+    // programs read from the input file if there is one, the debugger reads its commands from
+    // stdin so debugged programs read nothing without one
+    let input: Option<Box<dyn BufRead>> = match input_path.map(|path| open(&path)) {
+        Some(Ok(file)) => Some(Box::new(file)),
+        Some(Err(error)) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+        None => None,
+    };
+
+    // without a file the input is read line by line
+    let Some(path) = path else {
+        if debug {
+            eprintln!("debug needs a file\n{}", USAGE);
+            process::exit(2);
+        }
+        repl(&mut engine, input);
+        return;
+    };
 
-    let tokens = stacky.lex(&text);
-    if emit_tokens {
+    // the run is stopped from another thread once the time is up
+    if let Some(timeout) = timeout {
+        let handle = engine.cancel_handle();
+        thread::spawn(move || {
+            thread::sleep(timeout);
+            handle.cancel();
+        });
+    }
+
+    let result = if debug {
+        let mut input = input.unwrap_or_else(|| Box::new(io::empty()));
+        debug_file(&mut engine, Path::new(&path), &breakpoints, &mut input)
+    } else {
+        let options = RunOptions {
+            emit_tokens,
+            emit_ast,
+            trace,
+            profile,
+        };
+        let mut input = input.unwrap_or_else(|| Box::new(io::stdin().lock()));
+        run_file(&mut engine, Path::new(&path), &options, &mut input)
+    };
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run_file(
+    engine: &mut Engine,
+    path: &Path,
+    options: &RunOptions,
+    input: &mut impl BufRead,
+) -> Result<(), StackyError> {
+    if options.emit_tokens {
+        let text = read_to_string(path).unwrap();
+        let tokens = engine.tokens(&text)?;
         let mut file = File::create("debug/tokens.txt").unwrap();
-        file.write_fmt(format_args!("{:#?}", tokens)).unwrap();
+        file.write_all(tokens.as_bytes()).unwrap();
     }
 
-    let ast = stacky.parse(&tokens);
-    if emit_ast {
+    let program = engine.load_file(path)?;
+    let program = engine.optimize(program);
+    if options.emit_ast {
         let mut file = File::create("debug/ast.txt").unwrap();
-        file.write_fmt(format_args!("{:#?}", ast)).unwrap();
+        file.write_fmt(format_args!("{:#?}", program)).unwrap();
+    }
+
+    let trace = &options.trace;
+    let mut tracer = match &trace.file {
+        _ if !trace.enabled => None,
+        Some(file) => Some(Tracer::new(
+            Box::new(BufWriter::new(create(file)?)) as Box<dyn Write>
+        )),
+        None => Some(Tracer::new(Box::new(io::stderr()) as Box<dyn Write>)),
+    };
+    if let Some(tracer) = &mut tracer {
+        for word in &trace.words {
+            tracer.add_word(word.clone());
+        }
     }
+    let mut profiler = options.profile.enabled().then(Profiler::new);
 
-    stacky.run(&ast, &mut std::io::stdout());
+    let mut hooks: Vec<&mut dyn Hook> = vec![];
+    if let Some(tracer) = &mut tracer {
+        hooks.push(tracer);
+    }
+    if let Some(profiler) = &mut profiler {
+        hooks.push(profiler);
+    }
+    let result = if hooks.is_empty() {
+        engine.run(&program, input, &mut io::stdout())
+    } else {
+        engine.run_with(&program, input, &mut io::stdout(), &mut hooks)
+    };
+
+    // the profile is written even if the program failed
+    if let Some(profiler) = profiler {
+        if options.profile.report {
+            profiler.report(&mut io::stderr())?;
+        }
+        if let Some(file) = &options.profile.collapsed {
+            profiler.collapsed(&mut BufWriter::new(create(file)?))?;
+        }
+    }
+    result
+}
+
+/// formats files in place, or stdin to stdout without files, with --check files are only
+/// compared with their formatted text
+fn fmt(args: Vec<String>) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if let Some(arg) = paths.iter().find(|arg| arg.starts_with('-')) {
+        eprintln!("Unknown option {}\n{}", arg, FMT_USAGE);
+        process::exit(2);
+    }
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        let formatted = io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|e| StackyError::Io(format!("Cannot read stdin: {}", e)))
+            .and_then(|_| format_source(&source));
+        match formatted {
+            Ok(formatted) if check && formatted != source => process::exit(1),
+            Ok(_) if check => {}
+            Ok(formatted) => print!("{}", formatted),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut failed = false;
+    for path in paths {
+        let formatted = read_to_string(path)
+            .map_err(|e| StackyError::Io(format!("Cannot read {}: {}", path, e)))
+            .and_then(|source| Ok((format_source(&source)?, source)));
+        match formatted {
+            Ok((formatted, source)) if formatted == source => {}
+            Ok(_) if check => {
+                println!("{} is not formatted", path);
+                failed = true;
+            }
+            Ok((formatted, _)) => {
+                if let Err(e) = std::fs::write(path, formatted) {
+                    eprintln!("error: Cannot write {}: {}", path, e);
+                    failed = true;
+                }
+            }
+            Err(error) => {
+                eprintln!("error: {}: {}", path, error);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn open(path: &str) -> Result<BufReader<File>, StackyError> {
+    let file =
+        File::open(path).map_err(|e| StackyError::Io(format!("Cannot open {}: {}", path, e)))?;
+    Ok(BufReader::new(file))
+}
+
+fn create(path: &str) -> Result<File, StackyError> {
+    File::create(path).map_err(|e| StackyError::Io(format!("Cannot create {}: {}", path, e)))
+}
+
+/// runs a file unoptimized under the debugger, which reads its commands from stdin
+fn debug_file(
+    engine: &mut Engine,
+    path: &Path,
+    breakpoints: &[String],
+    input: &mut impl BufRead,
+) -> Result<(), StackyError> {
+    let program = engine.load_file(path)?;
+
+    let mut debugger = Debugger::new(io::stdin().lock(), io::stdout());
+    for breakpoint in breakpoints {
+        debugger.add_breakpoint(breakpoint);
+    }
+    engine.run_with(&program, input, &mut io::stdout(), &mut debugger)
+}
+
+fn repl(engine: &mut Engine, mut input: Option<Box<dyn BufRead>>) {
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout();
+    loop {
+        let mut line = String::new();
+        if stdin.read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let result = match &mut input {
+            Some(input) => engine.eval_with(&line, input, &mut stdout),
+            // without an input file words like KEY read the lines after the program
+            None => engine.eval_with(&line, &mut stdin, &mut stdout),
+        };
+        match result {
+            Ok(()) => println!(" ok"),
+            Err(error) => println!(" error: {}", error),
+        }
+    }
+}
+
+fn lint(args: Vec<String>) {
+    let mut linter = Linter::new();
+    let mut paths = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--allow" => match args.next().map(|rule| rule.parse::<Rule>()) {
+                Some(Ok(rule)) => linter.set_rule(rule, false),
+                Some(Err(e)) => {
+                    eprintln!("{}\n{}", e, LINT_USAGE);
+                    process::exit(2);
+                }
+                None => {
+                    eprintln!("{}", LINT_USAGE);
+                    process::exit(2);
+                }
+            },
+            _ if arg.starts_with('-') => {
+                eprintln!("Unknown option {}\n{}", arg, LINT_USAGE);
+                process::exit(2);
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    let sources: Vec<(String, Result<String, StackyError>)> = match paths.is_empty() {
+        true => {
+            let mut source = String::new();
+            let source = io::stdin()
+                .read_to_string(&mut source)
+                .map(|_| source)
+                .map_err(|e| StackyError::Io(format!("Cannot read stdin: {}", e)));
+            vec![("<stdin>".to_string(), source)]
+        }
+        false => paths
+            .into_iter()
+            .map(|path| {
+                let source = read_to_string(&path)
+                    .map_err(|e| StackyError::Io(format!("Cannot read {}: {}", path, e)));
+                (path, source)
+            })
+            .collect(),
+    };
+
+    let mut failed = false;
+    for (path, source) in sources {
+        match source.and_then(|source| linter.lint(&path, &source)) {
+            Ok(lints) => {
+                for lint in &lints {
+                    println!("{}", lint);
+                }
+                failed |= !lints.is_empty();
+            }
+            Err(error) => {
+                eprintln!("error: {}", error);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
 }
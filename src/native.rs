@@ -0,0 +1,113 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    bridge::{from_value, to_value},
+    engine::{entity, value, Value},
+    error::StackyError,
+    stack_machine::StackMachine,
+};
+
+/// a word written in Rust that stacky code can call like any other word
+pub trait NativeWord {
+    /// how many items the word takes, a call with fewer items on the stack is a stack underflow
+    fn arity(&self) -> usize;
+
+    /// pops the arguments and pushes the results, an error stops the program that called it
+    fn call(&mut self, stack: &mut Stack) -> Result<(), StackyError>;
+}
+
+/// a native word made of a closure
+pub(crate) struct NativeFn<F> {
+    pub(crate) arity: usize,
+    pub(crate) function: F,
+}
+
+impl<F: FnMut(&mut Stack) -> Result<(), StackyError>> NativeWord for NativeFn<F> {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&mut self, stack: &mut Stack) -> Result<(), StackyError> {
+        (self.function)(stack)
+    }
+}
+
+/// the data stack as the host sees it
+pub struct Stack<'a> {
+    machine: &'a mut StackMachine,
+}
+
+impl<'a> Stack<'a> {
+    pub(crate) fn new(machine: &'a mut StackMachine) -> Self {
+        Self { machine }
+    }
+
+    pub fn push(&mut self, value: impl Into<Value>) {
+        self.machine.push(entity(value.into()));
+    }
+
+    /// pushes a Rust value, like a struct as a map
+    pub fn push_serialized<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StackyError> {
+        self.push(to_value(value)?);
+        Ok(())
+    }
+
+    /// takes the item on top of the stack, items without a value are left on the stack
+    pub fn pop(&mut self) -> Result<Value, StackyError> {
+        self.pop_as(Ok)
+    }
+
+    pub fn pop_number(&mut self) -> Result<i64, StackyError> {
+        self.pop_as(|value| match value {
+            Value::Number(x) => Ok(x),
+            value => Err(expected("a number", &value)),
+        })
+    }
+
+    pub fn pop_float(&mut self) -> Result<f64, StackyError> {
+        self.pop_as(|value| match value {
+            Value::Float(x) => Ok(x),
+            value => Err(expected("a float", &value)),
+        })
+    }
+
+    pub fn pop_string(&mut self) -> Result<String, StackyError> {
+        self.pop_as(|value| match value {
+            Value::String(x) => Ok(x),
+            value => Err(expected("a string", &value)),
+        })
+    }
+
+    /// takes the item on top of the stack as a Rust value, it is left on the stack if it does not fit
+    pub fn pop_deserialized<T: DeserializeOwned>(&mut self) -> Result<T, StackyError> {
+        self.pop_as(from_value)
+    }
+
+    /// pops the top item if `convert` accepts its value, otherwise it stays on the stack
+    fn pop_as<T>(
+        &mut self,
+        convert: impl FnOnce(Value) -> Result<T, StackyError>,
+    ) -> Result<T, StackyError> {
+        let Some(top) = self.machine.stack().last() else {
+            return Err(StackyError::StackUnderflow {
+                word: "pop".to_string(),
+                required: 1,
+                present: 0,
+            });
+        };
+        let value = value(top)
+            .ok_or_else(|| StackyError::runtime(format!("Expected any value, got {}", top)))?;
+        let result = convert(value)?;
+        self.machine.pop();
+        Ok(result)
+    }
+
+    /// the number of items on the stack
+    pub fn depth(&self) -> usize {
+        self.machine.stack().len()
+    }
+}
+
+fn expected(kind: &str, value: &Value) -> StackyError {
+    StackyError::runtime(format!("Expected {}, got {}", kind, value))
+}
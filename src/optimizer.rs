@@ -0,0 +1,328 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
+
+use crate::{
+    lexer::Token,
+    parser::{Ast, Node},
+    stack_machine::{Entity, StackMachine},
+};
+
+/// words with more nodes than this are not inlined
+const INLINE_LIMIT: usize = 8;
+
+/// rewrites a whole program into one with the same output and errors that does less work
+///
+/// - level 1 folds operations on literals and removes IF branches with a literal condition
+/// - level 2 also inlines small words and removes words that are never called
+pub(crate) struct Optimizer {
+    level: u8,
+    /// how often each word is defined in the program, only words defined once are inlined
+    definitions: HashMap<String, usize>,
+    /// the bodies of the words that can be inlined from here on
+    inline: HashMap<String, Vec<Node>>,
+    /// how deep in IF, LOOP and word bodies the current node is, only words defined at the top
+    /// level are certainly defined once their definition was passed
+    depth: usize,
+}
+
+impl Optimizer {
+    pub(crate) fn new(level: u8) -> Self {
+        Self {
+            level,
+            definitions: HashMap::new(),
+            inline: HashMap::new(),
+            depth: 0,
+        }
+    }
+
+    pub(crate) fn optimize(&mut self, ast: Ast) -> Ast {
+        if self.level == 0 {
+            return ast;
+        }
+        if self.level >= 2 {
+            count_definitions(&ast, &mut self.definitions);
+        }
+        let mut ast = self.ast(ast);
+        if self.level >= 2 {
+            while remove_unused(&mut ast) {}
+        }
+        ast
+    }
+
+    fn ast(&mut self, ast: Ast) -> Ast {
+        match ast {
+            Ast::Expressions(nodes) => Ast::Expressions(self.nodes(nodes)),
+            Ast::Include { path, once, body } => Ast::Include {
+                path,
+                once,
+                body: self.nodes(body),
+            },
+            ast => ast,
+        }
+    }
+
+    fn nodes(&mut self, nodes: Vec<Node>) -> Vec<Node> {
+        let mut result = vec![];
+        for node in nodes {
+            self.push(&mut result, node);
+        }
+        result
+    }
+
+    /// the optimized nodes of a body that only runs under some condition
+    fn body(&mut self, nodes: Vec<Node>) -> Vec<Node> {
+        self.depth += 1;
+        let nodes = self.nodes(nodes);
+        self.depth -= 1;
+        nodes
+    }
+
+    /// adds a node to the optimized nodes before it
+    fn push(&mut self, result: &mut Vec<Node>, node: Node) {
+        let Node { ast, span } = node;
+        match ast {
+            Ast::Operation(op) => {
+                result.push(Node {
+                    ast: Ast::Operation(op),
+                    span,
+                });
+                fold(result);
+            }
+            Ast::If { if_body, else_body } => {
+                let if_body = self.body(if_body);
+                let else_body = self.body(else_body);
+                match result.last() {
+                    Some(Node {
+                        ast: Ast::Number(condition),
+                        ..
+                    }) => {
+                        let body = if *condition == 1 { if_body } else { else_body };
+                        result.pop();
+                        for node in body {
+                            self.push(result, node);
+                        }
+                    }
+                    _ => result.push(Node {
+                        ast: Ast::If { if_body, else_body },
+                        span,
+                    }),
+                }
+            }
+            Ast::Loop { body } => result.push(Node {
+                ast: Ast::Loop {
+                    body: self.body(body),
+                },
+                span,
+            }),
+            Ast::WordDefinition { name, effect, body } => {
+                let body = self.body(body);
+                if self.is_inlinable(&name, &body) {
+                    self.inline.insert(name.clone(), body.clone());
+                }
+                result.push(Node {
+                    ast: Ast::WordDefinition { name, effect, body },
+                    span,
+                });
+            }
+            Ast::FunctionCall(name) if self.inline.contains_key(&name) => {
+                for node in self.inline[&name].clone() {
+                    self.push(result, node);
+                }
+            }
+            ast => result.push(Node {
+                ast: self.ast(ast),
+                span,
+            }),
+        }
+    }
+
+    fn is_inlinable(&self, name: &str, body: &[Node]) -> bool {
+        // calls resolve to the latest definition when they run, so words defined more than once
+        // or conditionally are left alone
+        self.level >= 2
+            && self.depth == 0
+            && self.definitions.get(name) == Some(&1)
+            && body.len() <= INLINE_LIMIT
+            && body.iter().all(|node| can_inline(&node.ast, name))
+    }
+}
+
+/// words that call themselves, define words or use the return stack, which has to be balanced
+/// when a word returns, are not inlined
+fn can_inline(ast: &Ast, name: &str) -> bool {
+    match ast {
+        Ast::FunctionCall(call) | Ast::GetVariable(call) | Ast::SetVariable(call) => call != name,
+        Ast::Operation(op) => !matches!(
+            op,
+            Token::ToR | Token::FromR | Token::RFetch | Token::TwoToR | Token::TwoFromR
+        ),
+        Ast::If { if_body, else_body } => if_body
+            .iter()
+            .chain(else_body)
+            .all(|node| can_inline(&node.ast, name)),
+        Ast::Loop { body } => body.iter().all(|node| can_inline(&node.ast, name)),
+        Ast::WordDefinition { .. } | Ast::Include { .. } | Ast::Pragma(_) => false,
+        _ => true,
+    }
+}
+
+/// replaces an operation on literals at the end of the nodes with its result
+fn fold(result: &mut Vec<Node>) {
+    let Some(Node {
+        ast: Ast::Operation(op),
+        ..
+    }) = result.last()
+    else {
+        return;
+    };
+    let arity = match op {
+        Token::Add
+        | Token::Sub
+        | Token::Mul
+        | Token::Div
+        | Token::Percent
+        | Token::Eq
+        | Token::Lt
+        | Token::Gt
+        | Token::Lte
+        | Token::Gte => 2,
+        Token::IntToFloat | Token::FloatToInt | Token::FRound | Token::FFloor | Token::FTrunc => 1,
+        _ => return,
+    };
+    if result.len() <= arity {
+        return;
+    }
+
+    let operands = &result[result.len() - 1 - arity..result.len() - 1];
+    let Some(operands) = operands
+        .iter()
+        .map(|node| literal(&node.ast))
+        .collect::<Option<Vec<Entity>>>()
+    else {
+        return;
+    };
+
+    // the operation runs on a stack machine, operations that fail are left for the program
+    let mut stack_machine = StackMachine::new();
+    for operand in operands {
+        stack_machine.push(operand);
+    }
+    if stack_machine
+        .execute(op, &mut io::empty(), &mut io::sink())
+        .is_err()
+    {
+        return;
+    }
+    let Some(ast) = stack_machine.pop().and_then(to_ast) else {
+        return;
+    };
+
+    let span = result[result.len() - 1 - arity].span.clone();
+    result.truncate(result.len() - 1 - arity);
+    result.push(Node { ast, span });
+}
+
+fn literal(ast: &Ast) -> Option<Entity> {
+    match ast {
+        Ast::Number(x) => Some(Entity::Number(*x)),
+        Ast::Float(x) => Some(Entity::Float(*x)),
+        Ast::StringLiteral(x) => Some(Entity::String(x.clone())),
+        _ => None,
+    }
+}
+
+fn to_ast(entity: Entity) -> Option<Ast> {
+    match entity {
+        Entity::Number(x) => Some(Ast::Number(x)),
+        Entity::Float(x) => Some(Ast::Float(x)),
+        Entity::String(x) => Some(Ast::StringLiteral(x)),
+        _ => None,
+    }
+}
+
+fn count_definitions(ast: &Ast, definitions: &mut HashMap<String, usize>) {
+    match ast {
+        Ast::WordDefinition { name, body, .. } => {
+            *definitions.entry(name.clone()).or_default() += 1;
+            count_body(body, definitions);
+        }
+        // a variable with the name of a word replaces the word
+        Ast::SetVariable(name) => *definitions.entry(name.clone()).or_default() += 1,
+        Ast::Expressions(nodes) | Ast::Include { body: nodes, .. } | Ast::Loop { body: nodes } => {
+            count_body(nodes, definitions)
+        }
+        Ast::If { if_body, else_body } => {
+            count_body(if_body, definitions);
+            count_body(else_body, definitions);
+        }
+        _ => {}
+    }
+}
+
+fn count_body(nodes: &[Node], definitions: &mut HashMap<String, usize>) {
+    for node in nodes {
+        count_definitions(&node.ast, definitions);
+    }
+}
+
+/// removes the definitions of words that are not used outside of their own body,
+/// returns if something was removed
+fn remove_unused(ast: &mut Ast) -> bool {
+    let mut used = HashSet::new();
+    uses(ast, "", &mut used);
+    remove(ast, &used)
+}
+
+fn uses(ast: &Ast, word: &str, used: &mut HashSet<String>) {
+    match ast {
+        Ast::FunctionCall(name) | Ast::GetVariable(name) if name != word => {
+            used.insert(name.clone());
+        }
+        Ast::WordDefinition { name, body, .. } => {
+            for node in body {
+                uses(&node.ast, name, used);
+            }
+        }
+        Ast::Expressions(nodes) | Ast::Include { body: nodes, .. } | Ast::Loop { body: nodes } => {
+            for node in nodes {
+                uses(&node.ast, word, used);
+            }
+        }
+        Ast::If { if_body, else_body } => {
+            for node in if_body.iter().chain(else_body) {
+                uses(&node.ast, word, used);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remove(ast: &mut Ast, used: &HashSet<String>) -> bool {
+    let nodes = match ast {
+        Ast::Expressions(nodes)
+        | Ast::Include { body: nodes, .. }
+        | Ast::Loop { body: nodes }
+        | Ast::WordDefinition { body: nodes, .. } => nodes,
+        Ast::If { if_body, else_body } => {
+            let removed = remove_nodes(if_body, used);
+            return remove_nodes(else_body, used) || removed;
+        }
+        _ => return false,
+    };
+    remove_nodes(nodes, used)
+}
+
+fn remove_nodes(nodes: &mut Vec<Node>, used: &HashSet<String>) -> bool {
+    let length = nodes.len();
+    nodes.retain(|node| match &node.ast {
+        Ast::WordDefinition { name, .. } => used.contains(name),
+        _ => true,
+    });
+    let mut removed = nodes.len() != length;
+    for node in nodes {
+        removed |= remove(&mut node.ast, used);
+    }
+    removed
+}
@@ -1,169 +1,222 @@
-use core::panic;
+use std::{fmt, str::FromStr};
 
-use crate::lexer::Token;
+use num_bigint::BigInt;
+
+use crate::{error::StackyError, lexer::Token, span::Span, stack_machine::OverflowPolicy};
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum Ast {
-    Number(i32),
+    Number(i64),
+    BigNumber(BigInt),
+    Float(f64),
     StringLiteral(String),
     WordDefinition {
         name: String,
-        body: Vec<Ast>,
+        effect: Option<StackEffect>,
+        body: Vec<Node>,
     },
     Operation(Token),
-    Expressions(Vec<Ast>),
+    Expressions(Vec<Node>),
     FunctionCall(String),
     If {
-        if_body: Vec<Ast>,
-        else_body: Vec<Ast>,
+        if_body: Vec<Node>,
+        else_body: Vec<Node>,
     },
     Loop {
-        body: Vec<Ast>,
+        body: Vec<Node>,
     },
     LoopVariable(u8),
     SetVariable(String),
     GetVariable(String),
+    Pragma(Pragma),
+    /// INCLUDE or REQUIRE, the body is filled in by the loader and empty if the file was already required
+    Include {
+        path: String,
+        once: bool,
+        body: Vec<Node>,
+    },
 }
 
-pub(crate) struct Parser<'a> {
-    tokens: &'a [Token],
-    pos: usize,
+/// an ast node together with the position it was parsed from
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Node {
+    pub(crate) ast: Ast,
+    pub(crate) span: Span,
 }
 
-impl<'a> Parser<'a> {
-    pub(crate) fn new() -> Parser<'a> {
-        Parser {
-            tokens: &[],
-            pos: 0,
-        }
-    }
+/// the items a word takes from and leaves on the stack, as declared by a `( a b -- c )` comment
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct StackEffect {
+    pub(crate) inputs: Vec<String>,
+    pub(crate) outputs: Vec<String>,
+}
 
-    fn advance(&mut self) {
-        self.pos += 1;
+impl FromStr for StackEffect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let items: Vec<&str> = s.split_whitespace().collect();
+        let Some(separator) = items.iter().position(|item| *item == "--") else {
+            return Err(format!("Expected -- in stack effect ( {} )", s.trim()));
+        };
+        let to_strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        Ok(StackEffect {
+            inputs: to_strings(&items[..separator]),
+            outputs: to_strings(&items[separator + 1..]),
+        })
     }
+}
 
-    pub(crate) fn parse(&mut self, tokens: &'a [Token]) -> Ast {
-        self.tokens = tokens;
-
-        let mut nodes = vec![];
-
-        loop {
-            let token = self.tokens[self.pos].clone();
-            if token == Token::EOF {
-                break;
-            }
-            nodes.push(self.get_node(token));
+impl fmt::Display for StackEffect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for item in &self.inputs {
+            write!(f, " {}", item)?;
         }
-
-        Ast::Expressions(nodes)
-    }
-
-    fn _is_conditional(&mut self, token: Token) -> bool {
-        [Token::Gt, Token::Gte, Token::Lt, Token::Lte, Token::Eq].contains(&token)
-    }
-
-    fn get_node(&mut self, token: Token) -> Ast {
-        self.advance();
-        match token {
-            Token::Number(x) => Ast::Number(x),
-            Token::Add => Ast::Operation(Token::Add),
-            Token::Sub => Ast::Operation(Token::Sub),
-            Token::Emit => Ast::Operation(Token::Emit),
-            Token::Mul => Ast::Operation(Token::Mul),
-            Token::Div => Ast::Operation(Token::Div),
-            Token::Dup => Ast::Operation(Token::Dup),
-            Token::Swap => Ast::Operation(Token::Swap),
-            Token::Drop => Ast::Operation(Token::Drop),
-            Token::Puts => Ast::Operation(Token::Puts),
-            Token::Eq => Ast::Operation(Token::Eq),
-            Token::Lt => Ast::Operation(Token::Lt),
-            Token::Gt => Ast::Operation(Token::Gt),
-            Token::Lte => Ast::Operation(Token::Lte),
-            Token::Gte => Ast::Operation(Token::Gte),
-            Token::Percent => Ast::Operation(Token::Percent),
-            Token::StringLiteral(ref x) => Ast::StringLiteral(x.clone()),
-            Token::Identifier(ref x) => Ast::FunctionCall(x.clone()),
-            Token::Colon => self.get_word(),
-            Token::If => self.get_if(),
-            Token::Do => self.get_loop(),
-            Token::LoopVariable(ref x) => Ast::LoopVariable(x.clone()),
-            Token::Arrow => self.get_arrow(),
-            Token::At => self.get_at(),
-            Token::SemiColon => {
-                panic!("Unexpected semicolon")
-            }
-            _ => panic!("Not yet implemented {:?}", token),
+        write!(f, " --")?;
+        for item in &self.outputs {
+            write!(f, " {}", item)?;
         }
+        write!(f, " )")
     }
+}
 
-    fn get_loop(&mut self) -> Ast {
-        let mut body = vec![];
-
-        let mut token = self.tokens[self.pos].clone();
-        while token != Token::Loop {
-            body.push(self.get_node(token.clone()));
-            token.clone_from(&self.tokens[self.pos]);
-        }
-        self.advance(); // advance past loop
-
-        Ast::Loop { body: body }
-    }
-
-    fn get_if(&mut self) -> Ast {
-        let mut if_body = vec![];
-        let mut else_body = vec![];
-
-        let mut token = self.tokens[self.pos].clone();
-        while token != Token::Then && token != Token::Else {
-            if_body.push(self.get_node(token.clone()));
-            token.clone_from(&self.tokens[self.pos])
-        }
-        if token == Token::Else {
-            self.advance(); // advance past else
-            token.clone_from(&self.tokens[self.pos]);
-            while token != Token::Then {
-                else_body.push(self.get_node(token.clone()));
-                token.clone_from(&self.tokens[self.pos]);
+/// a node is shown by the word that runs it, bodies are left out
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ast::Number(x) => write!(f, "{}", x),
+            Ast::BigNumber(x) => write!(f, "{}", x),
+            Ast::Float(x) => write!(f, "{:?}", x),
+            Ast::StringLiteral(x) => write!(f, "{:?}", x),
+            Ast::WordDefinition { name, .. } => write!(f, ": {}", name),
+            Ast::Operation(op) => write!(f, "{}", op),
+            Ast::Expressions(_) => write!(f, "..."),
+            Ast::FunctionCall(name) => write!(f, "{}", name),
+            Ast::If { .. } => write!(f, "IF"),
+            Ast::Loop { .. } => write!(f, "DO"),
+            Ast::LoopVariable(x) => write!(f, "{}", Token::LoopVariable(*x)),
+            Ast::SetVariable(name) => write!(f, "-> {}", name),
+            Ast::GetVariable(name) => write!(f, "@ {}", name),
+            Ast::Pragma(Pragma::Overflow(policy)) => {
+                write!(
+                    f,
+                    "PRAGMA OVERFLOW {}",
+                    format!("{:?}", policy).to_uppercase()
+                )
+            }
+            Ast::Include { path, once, .. } => {
+                let word = if *once { "REQUIRE" } else { "INCLUDE" };
+                write!(f, "{} {:?}", word, path)
             }
         }
-        self.advance(); // advance past then
-
-        Ast::If { if_body, else_body }
     }
+}
 
-    fn get_word(&mut self) -> Ast {
-        let mut body = vec![];
-        let mut name: String = "undefined".to_string();
-        let mut token = self.tokens[self.pos].clone();
-        if let Token::Identifier(ref x) = token {
-            name.clone_from(&x);
-        } else {
-            panic!("Expected identifier");
-        }
-        self.advance(); // advance past identifier
-        token.clone_from(&self.tokens[self.pos]);
+/// a directive that changes how the rest of the source file is run
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Pragma {
+    Overflow(OverflowPolicy),
+}
 
-        while token != Token::SemiColon {
-            body.push(self.get_node(token.clone()));
-            token.clone_from(&self.tokens[self.pos]);
-        }
-        self.advance(); // advance past semicolon
-        Ast::WordDefinition { name, body }
-    }
+/// parses the tokens of a source, through the same syntax tree as sources that are loaded
+#[cfg(test)]
+pub(crate) fn parse(tokens: &[(Token, Span)]) -> Result<Ast, StackyError> {
+    use crate::{
+        cst::Cst,
+        lexer::{Lexeme, LexemeKind},
+    };
+
+    let lexemes = tokens
+        .iter()
+        .map(|(token, span)| Lexeme {
+            kind: LexemeKind::Token(token.clone()),
+            text: token.to_string(),
+            span: span.clone(),
+        })
+        .collect();
+    Cst::build(lexemes).lower()
+}
 
-    fn get_arrow(&mut self) -> Ast {
-        if let Token::Identifier(ref x) = self.tokens[self.pos].clone() {
-            self.advance(); // advance past the identifier
-            return Ast::SetVariable(x.clone())
+/// the node of a token that stands for itself, like a number or an operation
+pub(crate) fn atom(token: Token, span: &Span) -> Result<Ast, StackyError> {
+    let ast = match token {
+        Token::Number(x) => Ast::Number(x),
+        Token::BigNumber(ref x) => Ast::BigNumber(x.clone()),
+        Token::Float(x) => Ast::Float(x),
+        Token::Add => Ast::Operation(Token::Add),
+        Token::Sub => Ast::Operation(Token::Sub),
+        Token::Emit => Ast::Operation(Token::Emit),
+        Token::Mul => Ast::Operation(Token::Mul),
+        Token::Div => Ast::Operation(Token::Div),
+        Token::Dup => Ast::Operation(Token::Dup),
+        Token::Swap => Ast::Operation(Token::Swap),
+        Token::Drop => Ast::Operation(Token::Drop),
+        Token::Over => Ast::Operation(Token::Over),
+        Token::Rot => Ast::Operation(Token::Rot),
+        Token::MinusRot => Ast::Operation(Token::MinusRot),
+        Token::Nip => Ast::Operation(Token::Nip),
+        Token::Tuck => Ast::Operation(Token::Tuck),
+        Token::Pick => Ast::Operation(Token::Pick),
+        Token::Roll => Ast::Operation(Token::Roll),
+        Token::TwoDup => Ast::Operation(Token::TwoDup),
+        Token::TwoDrop => Ast::Operation(Token::TwoDrop),
+        Token::TwoSwap => Ast::Operation(Token::TwoSwap),
+        Token::TwoOver => Ast::Operation(Token::TwoOver),
+        Token::Depth => Ast::Operation(Token::Depth),
+        Token::Clear => Ast::Operation(Token::Clear),
+        Token::Key => Ast::Operation(Token::Key),
+        Token::Accept => Ast::Operation(Token::Accept),
+        Token::ReadLine => Ast::Operation(Token::ReadLine),
+        Token::NumberIn => Ast::Operation(Token::NumberIn),
+        Token::OpenFile => Ast::Operation(Token::OpenFile),
+        Token::CreateFile => Ast::Operation(Token::CreateFile),
+        Token::ReadFile => Ast::Operation(Token::ReadFile),
+        Token::WriteFile => Ast::Operation(Token::WriteFile),
+        Token::CloseFile => Ast::Operation(Token::CloseFile),
+        Token::FileSize => Ast::Operation(Token::FileSize),
+        Token::DeleteFile => Ast::Operation(Token::DeleteFile),
+        Token::ReadOnly => Ast::Operation(Token::ReadOnly),
+        Token::WriteOnly => Ast::Operation(Token::WriteOnly),
+        Token::ReadWrite => Ast::Operation(Token::ReadWrite),
+        Token::ToArray => Ast::Operation(Token::ToArray),
+        Token::FromArray => Ast::Operation(Token::FromArray),
+        Token::ToMap => Ast::Operation(Token::ToMap),
+        Token::FromMap => Ast::Operation(Token::FromMap),
+        Token::Get => Ast::Operation(Token::Get),
+        Token::Plugin => Ast::Operation(Token::Plugin),
+        Token::ToR => Ast::Operation(Token::ToR),
+        Token::FromR => Ast::Operation(Token::FromR),
+        Token::RFetch => Ast::Operation(Token::RFetch),
+        Token::TwoToR => Ast::Operation(Token::TwoToR),
+        Token::TwoFromR => Ast::Operation(Token::TwoFromR),
+        Token::Puts => Ast::Operation(Token::Puts),
+        Token::Eq => Ast::Operation(Token::Eq),
+        Token::Lt => Ast::Operation(Token::Lt),
+        Token::Gt => Ast::Operation(Token::Gt),
+        Token::Lte => Ast::Operation(Token::Lte),
+        Token::Gte => Ast::Operation(Token::Gte),
+        Token::Percent => Ast::Operation(Token::Percent),
+        Token::IntToFloat => Ast::Operation(Token::IntToFloat),
+        Token::FloatToInt => Ast::Operation(Token::FloatToInt),
+        Token::FRound => Ast::Operation(Token::FRound),
+        Token::FFloor => Ast::Operation(Token::FFloor),
+        Token::FTrunc => Ast::Operation(Token::FTrunc),
+        Token::Hex => Ast::Operation(Token::Hex),
+        Token::Decimal => Ast::Operation(Token::Decimal),
+        Token::Binary => Ast::Operation(Token::Binary),
+        Token::StringLiteral(ref x) => Ast::StringLiteral(x.clone()),
+        Token::Identifier(ref x) => Ast::FunctionCall(x.clone()),
+        Token::LoopVariable(x) => Ast::LoopVariable(x),
+        Token::SemiColon => {
+            return Err(StackyError::syntax("Unexpected semicolon").at(span.clone()))
         }
-        panic!("Expected identifier after ->");
-    }
-
-    fn get_at(&mut self) -> Ast {
-        if let Token::Identifier(ref x) = self.tokens[self.pos].clone() {
-            self.advance(); // advance past the identifier
-            return Ast::GetVariable(x.clone())
+        Token::EOF => return Err(StackyError::syntax("Unexpected end of input").at(span.clone())),
+        _ => {
+            return Err(
+                StackyError::syntax(format!("Not yet implemented {:?}", token)).at(span.clone()),
+            )
         }
-        panic!("Expected identifier after @");
-    }
+    };
+    Ok(ast)
 }
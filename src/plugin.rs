@@ -0,0 +1,241 @@
+use std::{
+    ffi::{c_char, c_int},
+    path::Path,
+    ptr,
+    sync::Arc,
+};
+
+use libloading::{Library, Symbol};
+
+use crate::{
+    error::StackyError,
+    ffi::{c_string, catch, stacky_string_free, text},
+    native::{NativeWord, Stack},
+};
+
+/// the version of the plugin interface, a plugin built for another version is not loaded
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// a word of a plugin, returns 0 if it succeeded and anything else if it failed
+pub type StackyWordFn = unsafe extern "C" fn(stack: *mut StackyStack) -> c_int;
+
+/// the functions a plugin uses, passed to its init function
+#[repr(C)]
+pub struct StackyPluginApi {
+    /// the ABI version of the loading stacky
+    pub abi_version: u32,
+    /// adds a word that is called with at least `arity` items on the stack, returns 0 if it
+    /// succeeded and -1 if the name is no UTF-8
+    pub register_word: unsafe extern "C" fn(
+        registrar: *mut StackyRegistrar,
+        name: *const c_char,
+        arity: usize,
+        word: StackyWordFn,
+    ) -> c_int,
+    pub push_int: unsafe extern "C" fn(stack: *mut StackyStack, value: i64),
+    /// returns 0 if it succeeded and -1 if the top item is no number
+    pub pop_int: unsafe extern "C" fn(stack: *mut StackyStack, value: *mut i64) -> c_int,
+    /// pushes a copy, returns 0 if it succeeded and -1 if it is no UTF-8
+    pub push_string: unsafe extern "C" fn(stack: *mut StackyStack, value: *const c_char) -> c_int,
+    /// NULL if the top item is no string, free it with `string_free`
+    pub pop_string: unsafe extern "C" fn(stack: *mut StackyStack) -> *mut c_char,
+    pub string_free: unsafe extern "C" fn(value: *mut c_char),
+    /// sets the message of the error the word fails with when it returns
+    pub fail: unsafe extern "C" fn(stack: *mut StackyStack, message: *const c_char),
+}
+
+/// collects the words a plugin registers
+pub struct StackyRegistrar {
+    words: Vec<(String, usize, StackyWordFn)>,
+}
+
+/// the stack of a running plugin word
+pub struct StackyStack {
+    /// only valid while the word runs
+    stack: *mut Stack<'static>,
+    /// the error of the last operation or the one set by `fail`
+    error: Option<StackyError>,
+}
+
+impl StackyStack {
+    /// remembers the error of an operation
+    fn record<T>(&mut self, result: Result<T, StackyError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.error = Some(error);
+                None
+            }
+        }
+    }
+}
+
+/// lives as long as the program, so plugins may keep a pointer to it
+static API: StackyPluginApi = StackyPluginApi {
+    abi_version: PLUGIN_ABI_VERSION,
+    register_word,
+    push_int,
+    pop_int,
+    push_string,
+    pop_string,
+    string_free,
+    fail,
+};
+
+unsafe extern "C" fn register_word(
+    registrar: *mut StackyRegistrar,
+    name: *const c_char,
+    arity: usize,
+    word: StackyWordFn,
+) -> c_int {
+    let (Some(registrar), Ok(name)) = (registrar.as_mut(), text(name)) else {
+        return -1;
+    };
+    let result = catch(|| {
+        registrar.words.push((name.to_string(), arity, word));
+        Ok(())
+    });
+    match result {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+unsafe extern "C" fn push_int(stack: *mut StackyStack, value: i64) {
+    if let Some(stack) = stack.as_mut() {
+        let result = catch(|| {
+            (*stack.stack).push(value);
+            Ok(())
+        });
+        stack.record(result);
+    }
+}
+
+unsafe extern "C" fn pop_int(stack: *mut StackyStack, value: *mut i64) -> c_int {
+    let Some(stack) = stack.as_mut() else {
+        return -1;
+    };
+    let result = catch(|| (*stack.stack).pop_number());
+    let Some(number) = stack.record(result) else {
+        return -1;
+    };
+    if !value.is_null() {
+        *value = number;
+    }
+    0
+}
+
+unsafe extern "C" fn push_string(stack: *mut StackyStack, value: *const c_char) -> c_int {
+    let Some(stack) = stack.as_mut() else {
+        return -1;
+    };
+    let result = catch(|| {
+        (*stack.stack).push(text(value)?);
+        Ok(())
+    });
+    match stack.record(result) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+unsafe extern "C" fn pop_string(stack: *mut StackyStack) -> *mut c_char {
+    let Some(stack) = stack.as_mut() else {
+        return ptr::null_mut();
+    };
+    let result = catch(|| (*stack.stack).pop_string());
+    match stack.record(result) {
+        Some(value) => c_string(value).into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn string_free(value: *mut c_char) {
+    stacky_string_free(value);
+}
+
+unsafe extern "C" fn fail(stack: *mut StackyStack, message: *const c_char) {
+    if let Some(stack) = stack.as_mut() {
+        let message = text(message).unwrap_or("Plugin word failed");
+        stack.error = Some(StackyError::runtime(message));
+    }
+}
+
+/// a word of a plugin, it keeps the library loaded
+pub(crate) struct PluginWord {
+    pub(crate) name: String,
+    arity: usize,
+    function: StackyWordFn,
+    _library: Arc<Library>,
+}
+
+impl NativeWord for PluginWord {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&mut self, stack: &mut Stack) -> Result<(), StackyError> {
+        let mut stack = StackyStack {
+            stack: (stack as *mut Stack).cast(),
+            error: None,
+        };
+        // SAFETY: the stack outlives the call and the library is loaded while the word exists
+        let status = unsafe { (self.function)(&mut stack) };
+        match status {
+            0 => Ok(()),
+            _ => Err(stack
+                .error
+                .unwrap_or_else(|| StackyError::runtime(format!("{} failed", self.name)))),
+        }
+    }
+}
+
+/// loads a shared library and returns the words it registers
+///
+/// it exports `uint32_t stacky_plugin_abi_version(void)` and
+/// `int stacky_plugin_init(const StackyPluginApi *api, StackyRegistrar *registrar)`
+pub(crate) fn load(path: &Path) -> Result<Vec<PluginWord>, StackyError> {
+    let name = path.display();
+    let error =
+        |e: libloading::Error| StackyError::Io(format!("Cannot load plugin {}: {}", name, e));
+
+    // SAFETY: loading a library runs its initializers, plugins are trusted like the host program
+    let library = unsafe { Library::new(path) }.map_err(error)?;
+    let version = unsafe {
+        let abi_version: Symbol<unsafe extern "C" fn() -> u32> =
+            library.get(b"stacky_plugin_abi_version").map_err(error)?;
+        abi_version()
+    };
+    if version != PLUGIN_ABI_VERSION {
+        return Err(StackyError::runtime(format!(
+            "Plugin {} is built for ABI version {}, this stacky has version {}",
+            name, version, PLUGIN_ABI_VERSION
+        )));
+    }
+
+    let mut registrar = StackyRegistrar { words: vec![] };
+    let status = unsafe {
+        let init: Symbol<
+            unsafe extern "C" fn(*const StackyPluginApi, *mut StackyRegistrar) -> c_int,
+        > = library.get(b"stacky_plugin_init").map_err(error)?;
+        init(&API, &mut registrar)
+    };
+    if status != 0 {
+        return Err(StackyError::runtime(format!(
+            "Plugin {} failed to initialize",
+            name
+        )));
+    }
+
+    let library = Arc::new(library);
+    Ok(registrar
+        .words
+        .into_iter()
+        .map(|(name, arity, function)| PluginWord {
+            name,
+            arity,
+            function,
+            _library: library.clone(),
+        })
+        .collect())
+}
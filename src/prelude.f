@@ -0,0 +1,14 @@
+\ The prelude is loaded before user code, run with --no-prelude to leave it out.
+\ Definitions of words that are implemented natively are skipped.
+
+: OVER ( a b -- a b a ) 1 PICK ;
+: ROT ( a b c -- b c a ) 2 ROLL ;
+: NIP ( a b -- b ) >R DROP R> ;
+: TUCK ( a b -- b a b ) 1 ROLL 1 PICK ;
+: 2DUP ( a b -- a b a b ) OVER OVER ;
+
+: NEGATE ( n -- -n ) -1 * ;
+: ABS ( n -- |n| ) DUP 0 < IF NEGATE THEN ;
+: MIN ( a b -- min ) 2DUP > IF NIP ELSE DROP THEN ;
+: MAX ( a b -- max ) 2DUP < IF NIP ELSE DROP THEN ;
+: SQUARE ( n -- n*n ) DUP * ;
@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::StackyError,
+    interpreter::{Event, Hook},
+    span::Lines,
+};
+
+/// the name of the code outside of words in collapsed stacks
+const MAIN: &str = "main";
+
+/// what is known about a word after a run
+#[derive(Debug, Default, Clone)]
+pub(crate) struct WordProfile {
+    pub(crate) calls: u64,
+    /// the time spent in the word including the words it called, recursive calls are only
+    /// counted once
+    pub(crate) inclusive: Duration,
+    /// the time spent in the word itself
+    pub(crate) exclusive: Duration,
+}
+
+/// a word that is running
+struct Frame {
+    word: String,
+    start: Instant,
+    /// the time spent in the words it called
+    children: Duration,
+}
+
+/// counts how often words are called and lines are run and how long words take
+pub struct Profiler {
+    start: Instant,
+    words: HashMap<String, WordProfile>,
+    /// how often the program ran into each line of each file
+    hits: HashMap<(Arc<str>, usize), u64>,
+    /// the exclusive time of each stack of words, like `main;Cube;Square`
+    stacks: HashMap<String, Duration>,
+    frames: Vec<Frame>,
+    lines: Lines,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            words: HashMap::new(),
+            hits: HashMap::new(),
+            stacks: HashMap::new(),
+            frames: vec![],
+            lines: Lines::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn word(&self, name: &str) -> Option<&WordProfile> {
+        self.words.get(name)
+    }
+
+    /// how often the program ran into a line of a file
+    #[cfg(test)]
+    pub(crate) fn hits(&self, file: &str, line: usize) -> u64 {
+        self.hits
+            .get(&(Arc::from(file), line))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn stack(&self) -> String {
+        let mut stack = MAIN.to_string();
+        for frame in &self.frames {
+            stack.push(';');
+            stack.push_str(&frame.word);
+        }
+        stack
+    }
+
+    /// the time outside of words since the profiler was created
+    fn main_time(&self) -> Duration {
+        let words: Duration = self.stacks.values().sum();
+        self.start.elapsed().saturating_sub(words)
+    }
+
+    /// writes the words sorted by inclusive time and the lines sorted by hits
+    pub fn report(&self, output: &mut impl Write) -> io::Result<()> {
+        let mut words: Vec<_> = self.words.iter().collect();
+        words.sort_by(|(a, x), (b, y)| y.inclusive.cmp(&x.inclusive).then(a.cmp(b)));
+        let width = words.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let width = width.max("word".len());
+        writeln!(
+            output,
+            "{:<width$} {:>10} {:>14} {:>14}",
+            "word", "calls", "inclusive ms", "exclusive ms"
+        )?;
+        for (name, profile) in words {
+            writeln!(
+                output,
+                "{:<width$} {:>10} {:>14.3} {:>14.3}",
+                name,
+                profile.calls,
+                profile.inclusive.as_secs_f64() * 1000.0,
+                profile.exclusive.as_secs_f64() * 1000.0
+            )?;
+        }
+
+        let mut lines: Vec<_> = self.hits.iter().collect();
+        lines.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+        let lines: Vec<_> = lines
+            .into_iter()
+            .map(|((file, line), hits)| (format!("{}:{}", file, line), hits))
+            .collect();
+        let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+        let width = width.max("line".len());
+        writeln!(output)?;
+        writeln!(output, "{:<width$} {:>10}", "line", "hits")?;
+        for (line, hits) in lines {
+            writeln!(output, "{:<width$} {:>10}", line, hits)?;
+        }
+        Ok(())
+    }
+
+    /// writes the exclusive time of each stack of words in microseconds, one stack per line,
+    /// the format flamegraph tools read
+    pub fn collapsed(&self, output: &mut impl Write) -> io::Result<()> {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+        writeln!(output, "{} {}", MAIN, self.main_time().as_micros())?;
+        for (stack, time) in stacks {
+            writeln!(output, "{} {}", stack, time.as_micros())?;
+        }
+        Ok(())
+    }
+}
+
+impl Hook for Profiler {
+    fn before(&mut self, event: &Event) -> Result<(), StackyError> {
+        let span = &event.node.span;
+        if self.lines.starts_line(span, event.calls.len()) {
+            *self.hits.entry((span.file.clone(), span.line)).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, word: &str) {
+        self.words.entry(word.to_string()).or_default().calls += 1;
+        self.frames.push(Frame {
+            word: word.to_string(),
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn leave(&mut self, _word: &str) {
+        let stack = self.stack();
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        let time = frame.start.elapsed();
+        let exclusive = time.saturating_sub(frame.children);
+        *self.stacks.entry(stack).or_default() += exclusive;
+        if let Some(parent) = self.frames.last_mut() {
+            parent.children += time;
+        }
+
+        let recursive = self.frames.iter().any(|parent| parent.word == frame.word);
+        let profile = self.words.entry(frame.word).or_default();
+        profile.exclusive += exclusive;
+        if !recursive {
+            profile.inclusive += time;
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::{fmt, sync::Arc};
+
+/// the position of a token or node in a source file
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub file: Arc<str>,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// finds the nodes a running program runs into a line with, so each run of a line counts once
+///
+/// a jump back within a line, like a loop on one line, runs into it again, returning from a word
+/// called in the line does not
+#[derive(Debug, Default)]
+pub(crate) struct Lines {
+    /// the position of the previous node at each call depth
+    previous: Vec<Option<Span>>,
+}
+
+impl Lines {
+    /// if the node at the span that runs `depth` words deep starts a line
+    pub(crate) fn starts_line(&mut self, span: &Span, depth: usize) -> bool {
+        self.previous.resize(depth + 1, None);
+        let starts = match &self.previous[depth] {
+            Some(previous) => {
+                previous.file != span.file
+                    || previous.line != span.line
+                    || previous.column >= span.column
+            }
+            None => true,
+        };
+        self.previous[depth] = Some(span.clone());
+        starts
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
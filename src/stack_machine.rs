@@ -1,18 +1,107 @@
-use std::io::Write;
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
 
-use crate::{lexer::Token, parser::Ast};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
+
+use crate::{
+    error::StackyError,
+    files::{Files, IOR_OK, READ_ONLY, READ_WRITE, WRITE_ONLY},
+    lexer::Token,
+    parser::Ast,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum Entity {
-    Number(i32),
+    Number(i64),
+    BigInt(BigInt),
+    Float(f64),
     String(String),
+    #[allow(dead_code)]
     Pointer(u32),
+    #[allow(dead_code)]
     Function(Ast),
+    /// a handle of a file opened by OPEN-FILE or CREATE-FILE
+    File(u32),
+    /// items made into one by >ARRAY or pushed by the host
+    Array(Vec<Entity>),
+    /// keys and their values, in the order they were added
+    Map(Vec<(Entity, Entity)>),
+}
+
+/// entities are shown in decimal, strings in quotes so they can be told apart from numbers
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entity::Number(x) => write!(f, "{}", x),
+            Entity::BigInt(x) => write!(f, "{}", x),
+            Entity::Float(x) => write!(f, "{:?}", x),
+            Entity::String(x) => write!(f, "{:?}", x),
+            Entity::Pointer(x) => write!(f, "#{:X}", x),
+            Entity::Function(ast) => write!(f, "FUNC: {}", ast),
+            Entity::File(x) => write!(f, "FILE: {}", x),
+            Entity::Array(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    write!(f, "{}{}", separator, item)?;
+                }
+                write!(f, "]")
+            }
+            Entity::Map(pairs) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in pairs.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    write!(f, "{}{}: {}", separator, key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// what happens when an integer result does not fit into 64 bits
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[non_exhaustive]
+pub enum OverflowPolicy {
+    #[default]
+    Checked,
+    Wrapping,
+    Saturating,
+    BigInt,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "checked" => Ok(OverflowPolicy::Checked),
+            "wrapping" => Ok(OverflowPolicy::Wrapping),
+            "saturating" => Ok(OverflowPolicy::Saturating),
+            "bigint" => Ok(OverflowPolicy::BigInt),
+            _ => Err(format!("Unknown overflow policy {}", s)),
+        }
+    }
 }
 
 pub(crate) struct StackMachine {
     stack: Vec<Entity>,
-    loop_stack: Vec<i32>,
+    loop_stack: Vec<i64>,
+    /// values stashed with >R, words have to take them back before they return
+    return_stack: Vec<Entity>,
+    overflow_policy: OverflowPolicy,
+    base: u32,
+    files: Files,
+    /// if the file words may touch the file system
+    file_access: bool,
+    /// the most bytes a single item may take
+    item_size: Option<usize>,
+    /// if an item larger than the item size was pushed since the last check
+    oversized: bool,
 }
 
 impl StackMachine {
@@ -20,25 +109,105 @@ impl StackMachine {
         Self {
             stack: Vec::new(),
             loop_stack: Vec::new(),
+            return_stack: Vec::new(),
+            overflow_policy: OverflowPolicy::default(),
+            base: 10,
+            files: Files::default(),
+            file_access: true,
+            item_size: None,
+            oversized: false,
+        }
+    }
+
+    /// empties the stacks and closes the files, the settings are kept
+    pub(crate) fn reset(&mut self) {
+        *self = Self {
+            overflow_policy: self.overflow_policy,
+            file_access: self.file_access,
+            item_size: self.item_size,
+            ..Self::new()
+        };
+    }
+
+    pub(crate) fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    pub(crate) fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    /// allows or denies the file words access to the file system
+    pub(crate) fn set_file_access(&mut self, allowed: bool) {
+        self.file_access = allowed;
+    }
+
+    pub(crate) fn set_item_size(&mut self, limit: Option<usize>) {
+        self.item_size = limit;
+    }
+
+    /// if an item larger than the item size was pushed since the last call, every new item is
+    /// pushed, so this also covers the values stored in variables
+    pub(crate) fn take_oversized(&mut self) -> bool {
+        std::mem::take(&mut self.oversized)
+    }
+
+    /// the base integers are printed in
+    pub(crate) fn base(&self) -> u32 {
+        self.base
+    }
+
+    pub(crate) fn set_base(&mut self, base: i64) -> Result<(), StackyError> {
+        if !(2..=36).contains(&base) {
+            return Err(StackyError::runtime(format!(
+                "Invalid base {}, it has to be between 2 and 36",
+                base
+            )));
         }
+        self.base = base as u32;
+        Ok(())
     }
+
     pub(crate) fn push(&mut self, entity: Entity) {
+        if self.item_size.is_some_and(|max| size(&entity) > max) {
+            self.oversized = true;
+        }
         self.stack.push(entity);
     }
 
+    /// the data stack, bottom first
+    pub(crate) fn stack(&self) -> &[Entity] {
+        &self.stack
+    }
+
+    /// the limits and indices of the running loops, outermost first
+    pub(crate) fn loop_stack(&self) -> &[i64] {
+        &self.loop_stack
+    }
+
     pub(crate) fn pop(&mut self) -> Option<Entity> {
         self.stack.pop()
     }
 
-    pub(crate) fn pop_loop(&mut self) -> Option<i32> {
+    pub(crate) fn pop_loop(&mut self) -> Option<i64> {
         self.loop_stack.pop()
     }
 
-    pub(crate) fn push_loop(&mut self, number: i32) {
+    pub(crate) fn push_loop(&mut self, number: i64) {
         self.loop_stack.push(number);
     }
 
-    pub(crate) fn get_loop(&mut self, index: usize) -> Option<i32> {
+    /// removes the index and limit of the innermost loop once it is done
+    pub(crate) fn end_loop(&mut self) {
+        let length = self.loop_stack.len().saturating_sub(2);
+        self.loop_stack.truncate(length);
+    }
+
+    pub(crate) fn return_depth(&self) -> usize {
+        self.return_stack.len()
+    }
+
+    pub(crate) fn get_loop(&mut self, index: usize) -> Option<i64> {
         let (res, overflow) = self.loop_stack.len().overflowing_sub(index + 1);
         if overflow {
             return None;
@@ -46,16 +215,133 @@ impl StackMachine {
         self.loop_stack.get(res).cloned()
     }
 
+    pub(crate) fn push_big(&mut self, number: BigInt) -> Result<(), StackyError> {
+        if self.overflow_policy != OverflowPolicy::BigInt {
+            return Err(StackyError::runtime(format!(
+                "Number {} does not fit into 64 bits, big integers are disabled",
+                number
+            )));
+        }
+        self.push(normalize(number));
+        Ok(())
+    }
+
+    /// fails with a stack underflow if there are less than `required` items on the stack
+    fn require(&self, word: &'static str, required: usize) -> Result<(), StackyError> {
+        if self.stack.len() < required {
+            return Err(StackyError::StackUnderflow {
+                word: word.to_string(),
+                required,
+                present: self.stack.len(),
+            });
+        }
+        Ok(())
+    }
+
+    fn require_return(&self, word: &'static str, required: usize) -> Result<(), StackyError> {
+        if self.return_stack.len() < required {
+            return Err(StackyError::runtime(format!(
+                "Return stack underflow: {} requires {} items, {} present",
+                word,
+                required,
+                self.return_stack.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// a copy of the item `n` positions below the top of the stack
+    fn nth(&self, n: usize) -> Entity {
+        self.stack[self.stack.len() - 1 - n].clone()
+    }
+
+    /// moves the item `n` positions below the top of the stack to the top
+    fn roll(&mut self, n: usize) {
+        let item = self.stack.remove(self.stack.len() - 1 - n);
+        self.push(item);
+    }
+
+    /// the index on top of the stack for PICK and ROLL, which is left on the stack
+    fn index(&self, word: &'static str) -> Result<usize, StackyError> {
+        self.require(word, 1)?;
+        match self.nth(0) {
+            Entity::Number(n) if n >= 0 => Ok(n as usize),
+            entity => Err(StackyError::runtime(format!(
+                "{} expects a non-negative number, got {:?}",
+                word, entity
+            ))),
+        }
+    }
+
+    /// pushes the results of a file word followed by its ior code
+    fn push_all<const N: usize>(&mut self, entities: [Entity; N], ior: i64) {
+        for entity in entities {
+            self.push(entity);
+        }
+        self.push(Entity::Number(ior));
+    }
+
+    pub(crate) fn require_file_access(&self, word: &str) -> Result<(), StackyError> {
+        if self.file_access {
+            return Ok(());
+        }
+        Err(StackyError::runtime(format!(
+            "{} needs file access, which is disabled",
+            word
+        )))
+    }
+
+    fn pop_number(&mut self, word: &'static str) -> Result<i64, StackyError> {
+        self.require(word, 1)?;
+        match self.pop() {
+            Some(Entity::Number(n)) => Ok(n),
+            Some(entity) => Err(StackyError::runtime(format!(
+                "{} expects a number, got {}",
+                word, entity
+            ))),
+            None => unreachable!(),
+        }
+    }
+
+    pub(crate) fn pop_string(&mut self, word: &'static str) -> Result<String, StackyError> {
+        self.require(word, 1)?;
+        match self.pop() {
+            Some(Entity::String(s)) => Ok(s),
+            Some(entity) => Err(StackyError::runtime(format!(
+                "{} expects a string, got {}",
+                word, entity
+            ))),
+            None => unreachable!(),
+        }
+    }
+
+    fn pop_file(&mut self, word: &'static str) -> Result<u32, StackyError> {
+        self.require(word, 1)?;
+        match self.pop() {
+            Some(Entity::File(handle)) => Ok(handle),
+            Some(entity) => Err(StackyError::runtime(format!(
+                "{} expects a file, got {}",
+                word, entity
+            ))),
+            None => unreachable!(),
+        }
+    }
+
     pub(crate) fn _get(&mut self, pointer: u32) -> Option<Entity> {
         self.stack.get(pointer as usize).cloned()
     }
 
-    pub(crate) fn execute(&mut self, op: &Token, output: &mut impl Write) {
+    pub(crate) fn execute(
+        &mut self,
+        op: &Token,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<(), StackyError> {
         match op {
             Token::Gt => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
                         if a < b {
                             self.push(Entity::Number(1));
@@ -63,14 +349,37 @@ impl StackMachine {
                             self.push(Entity::Number(0));
                         }
                     }
-                    (Some(a), Some(b)) => panic!("Cannot compare non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough arguments for comparison gt"),
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        if a < b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        if a < b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot compare non-numbers {:?} {:?}",
+                            a, b
+                        )))
+                    }
+                    _ => {
+                        return Err(StackyError::runtime(
+                            "Not enough arguments for comparison gt",
+                        ))
+                    }
                 }
             }
             Token::Gte => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
                         if a <= b {
                             self.push(Entity::Number(1));
@@ -78,14 +387,37 @@ impl StackMachine {
                             self.push(Entity::Number(0));
                         }
                     }
-                    (Some(a), Some(b)) => panic!("Cannot compare non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough arguments for comparison gte"),
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        if a <= b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        if a <= b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot compare non-numbers {:?} {:?}",
+                            a, b
+                        )))
+                    }
+                    _ => {
+                        return Err(StackyError::runtime(
+                            "Not enough arguments for comparison gte",
+                        ))
+                    }
                 }
             }
             Token::Lt => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
                         if a > b {
                             self.push(Entity::Number(1));
@@ -93,14 +425,37 @@ impl StackMachine {
                             self.push(Entity::Number(0));
                         }
                     }
-                    (Some(a), Some(b)) => panic!("Cannot compare non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough arguments for comparison lt"),
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        if a > b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        if a > b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot compare non-numbers {:?} {:?}",
+                            a, b
+                        )))
+                    }
+                    _ => {
+                        return Err(StackyError::runtime(
+                            "Not enough arguments for comparison lt",
+                        ))
+                    }
                 }
             }
             Token::Lte => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
                         if a >= b {
                             self.push(Entity::Number(1));
@@ -108,14 +463,37 @@ impl StackMachine {
                             self.push(Entity::Number(0));
                         }
                     }
-                    (Some(a), Some(b)) => panic!("Cannot compare non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough arguments for comparison lte"),
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        if a >= b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        if a >= b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot compare non-numbers {:?} {:?}",
+                            a, b
+                        )))
+                    }
+                    _ => {
+                        return Err(StackyError::runtime(
+                            "Not enough arguments for comparison lte",
+                        ))
+                    }
                 }
             }
             Token::Eq => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
                         if a == b {
                             self.push(Entity::Number(1));
@@ -123,6 +501,20 @@ impl StackMachine {
                             self.push(Entity::Number(0));
                         }
                     }
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        if a == b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        if a == b {
+                            self.push(Entity::Number(1));
+                        } else {
+                            self.push(Entity::Number(0));
+                        }
+                    }
                     (Some(Entity::String(ref a)), Some(Entity::String(ref b))) => {
                         if a == b {
                             self.push(Entity::Number(1));
@@ -145,7 +537,7 @@ impl StackMachine {
                         }
                     }
                     (Some(_), None) | (None, Some(_)) => {
-                        panic!("Not enough items on stack to compare");
+                        return Err(StackyError::runtime("Not enough items on stack to compare"));
                     }
                     _ => {
                         // by default unequal
@@ -156,118 +548,474 @@ impl StackMachine {
             Token::Add => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
-                        self.push(Entity::Number(a + b));
+                        let result = self.integer_operation(op, b, a)?;
+                        self.push(result);
+                    }
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        self.push(Entity::Float(a + b));
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        self.push(normalize(b + a));
                     }
                     (Some(Entity::String(a)), Some(Entity::String(b))) => {
                         self.push(Entity::String(format!("{}{}", b, a)));
                     }
-                    (Some(a), Some(b)) => panic!("Cannot add non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough items on stack to add"),
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot add non-numbers {:?} {:?}",
+                            a, b
+                        )))
+                    }
+                    _ => return Err(StackyError::runtime("Not enough items on stack to add")),
                 }
             }
             Token::Sub => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
-                        self.push(Entity::Number(b - a));
+                        let result = self.integer_operation(op, b, a)?;
+                        self.push(result);
+                    }
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        self.push(Entity::Float(b - a));
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        self.push(normalize(b - a));
+                    }
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot subtract non-numbers {:?} {:?}",
+                            a, b
+                        )))
+                    }
+                    _ => {
+                        return Err(StackyError::runtime(
+                            "Not enough items on stack to subtract",
+                        ))
                     }
-                    (Some(a), Some(b)) => panic!("Cannot subtract non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough items on stack to subtract"),
                 }
             }
             Token::Mul => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
-                        self.push(Entity::Number(a * b));
+                        let result = self.integer_operation(op, b, a)?;
+                        self.push(result);
+                    }
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        self.push(Entity::Float(a * b));
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        self.push(normalize(b * a));
+                    }
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot multiply non-numbers {:?} {:?}",
+                            a, b
+                        )))
+                    }
+                    _ => {
+                        return Err(StackyError::runtime(
+                            "Not enough items on stack to multiply",
+                        ))
                     }
-                    (Some(a), Some(b)) => panic!("Cannot multiply non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough items on stack to multiply"),
                 }
             }
             Token::Div => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(0)), Some(Entity::Number(_))) => {
-                        panic!("Cannot divide by zero");
+                        return Err(StackyError::runtime("Cannot divide by zero"));
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(_))) if a.is_zero() => {
+                        return Err(StackyError::runtime("Cannot divide by zero"));
                     }
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
-                        self.push(Entity::Number(b / a));
+                        let result = self.integer_operation(op, b, a)?;
+                        self.push(result);
+                    }
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        self.push(Entity::Float(b / a));
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        self.push(normalize(b / a));
+                    }
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot divide non-numbers {:?} {:?}",
+                            a, b
+                        )))
                     }
-                    (Some(a), Some(b)) => panic!("Cannot divide non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough items on stack to divide"),
+                    _ => return Err(StackyError::runtime("Not enough items on stack to divide")),
                 }
             }
             Token::Percent => {
                 let a = self.pop();
                 let b = self.pop();
-                match (a, b) {
+                match promote(a, b) {
                     (Some(Entity::Number(0)), Some(Entity::Number(_))) => {
-                        panic!("Modulo by zero is undefined");
+                        return Err(StackyError::runtime("Modulo by zero is undefined"));
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(_))) if a.is_zero() => {
+                        return Err(StackyError::runtime("Modulo by zero is undefined"));
                     }
                     (Some(Entity::Number(a)), Some(Entity::Number(b))) => {
-                        self.push(Entity::Number(b % a));
+                        let result = self.integer_operation(op, b, a)?;
+                        self.push(result);
+                    }
+                    (Some(Entity::Float(a)), Some(Entity::Float(b))) => {
+                        self.push(Entity::Float(b % a));
+                    }
+                    (Some(Entity::BigInt(a)), Some(Entity::BigInt(b))) => {
+                        self.push(normalize(b % a));
+                    }
+                    (Some(a), Some(b)) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot divide non-numbers {:?} {:?}",
+                            a, b
+                        )))
                     }
-                    (Some(a), Some(b)) => panic!("Cannot divide non-numbers {:?} {:?}", a, b),
-                    _ => panic!("Not enough items on stack to divide"),
+                    _ => return Err(StackyError::runtime("Not enough items on stack to divide")),
                 }
             }
             Token::Emit => {
                 let a = self.pop();
                 match a {
                     Some(Entity::Number(a)) => {
-                        write!(output, "{}", a);
+                        let a = BigInt::from(a).to_str_radix(self.base).to_uppercase();
+                        write!(output, "{}", a)?;
+                    }
+                    Some(Entity::BigInt(a)) => {
+                        write!(output, "{}", a.to_str_radix(self.base).to_uppercase())?;
+                    }
+                    Some(Entity::Float(a)) => {
+                        // debug formatting keeps the fraction, so 3.0 does not print like 3
+                        write!(output, "{:?}", a)?;
                     }
                     Some(Entity::String(a)) => {
-                        write!(output, "{}", a);
+                        write!(output, "{}", a)?;
                     }
                     Some(Entity::Pointer(a)) => {
-                        write!(output, "#{:X}", a);
+                        write!(output, "#{:X}", a)?;
                     }
                     Some(Entity::Function(a)) => {
-                        if let Ast::WordDefinition { name, body: _ } = a {
-                            write!(output, "FUNC: {:?}", name);
+                        if let Ast::WordDefinition { name, .. } = a {
+                            write!(output, "FUNC: {:?}", name)?;
                         } else {
-                            panic!("Something went wrong");
+                            return Err(StackyError::runtime("Something went wrong"));
                         }
                     }
+                    Some(entity @ (Entity::File(_) | Entity::Array(_) | Entity::Map(_))) => {
+                        write!(output, "{}", entity)?
+                    }
                     None => {
-                        panic!("Not enough items on stack to emit");
+                        return Err(StackyError::runtime("Not enough items on stack to emit"));
                     }
                 }
             }
             Token::Dup => {
-                let a = self.pop();
-                match a {
-                    Some(a) => {
-                        self.push(a.clone());
-                        self.push(a);
+                self.require("DUP", 1)?;
+                self.push(self.nth(0));
+            }
+            Token::Swap => {
+                self.require("SWAP", 2)?;
+                let a = self.pop().unwrap();
+                let b = self.pop().unwrap();
+                self.push(b);
+                self.push(a);
+            }
+            Token::Drop => {
+                self.require("DROP", 1)?;
+                self.pop();
+            }
+            Token::Over => {
+                self.require("OVER", 2)?;
+                self.push(self.nth(1));
+            }
+            Token::Rot => {
+                self.require("ROT", 3)?;
+                self.roll(2);
+            }
+            Token::MinusRot => {
+                self.require("-ROT", 3)?;
+                let a = self.stack.remove(self.stack.len() - 1);
+                self.stack.insert(self.stack.len() - 2, a);
+            }
+            Token::Nip => {
+                self.require("NIP", 2)?;
+                self.stack.remove(self.stack.len() - 2);
+            }
+            Token::Tuck => {
+                self.require("TUCK", 2)?;
+                self.stack.insert(self.stack.len() - 2, self.nth(0));
+            }
+            Token::Pick => {
+                let n = self.index("PICK")?;
+                self.require("PICK", n + 2)?;
+                self.pop();
+                self.push(self.nth(n));
+            }
+            Token::Roll => {
+                let n = self.index("ROLL")?;
+                self.require("ROLL", n + 2)?;
+                self.pop();
+                self.roll(n);
+            }
+            Token::TwoDup => {
+                self.require("2DUP", 2)?;
+                self.push(self.nth(1));
+                self.push(self.nth(1));
+            }
+            Token::TwoDrop => {
+                self.require("2DROP", 2)?;
+                self.stack.truncate(self.stack.len() - 2);
+            }
+            Token::TwoSwap => {
+                self.require("2SWAP", 4)?;
+                self.roll(3);
+                self.roll(3);
+            }
+            Token::TwoOver => {
+                self.require("2OVER", 4)?;
+                self.push(self.nth(3));
+                self.push(self.nth(3));
+            }
+            Token::Depth => {
+                let depth = self.stack.len() as i64;
+                self.push(Entity::Number(depth));
+            }
+            Token::Clear => self.stack.clear(),
+            Token::Key => {
+                let c = read_char(input).map_err(read_error)?;
+                self.push(Entity::Number(c.map_or(-1, |c| c as i64)));
+            }
+            Token::Accept => {
+                let length = self.index("ACCEPT")?;
+                self.pop();
+                let line = read_line(input).map_err(read_error)?.unwrap_or_default();
+                self.push(Entity::String(line.chars().take(length).collect()));
+            }
+            // with a file on top READ-LINE reads from the file instead of the input
+            Token::ReadLine if matches!(self.stack.last(), Some(Entity::File(_))) => {
+                let handle = self.pop_file("READ-LINE")?;
+                match self.files.read_line(handle) {
+                    Ok(Some(line)) => {
+                        self.push_all([Entity::String(line), Entity::Number(1)], IOR_OK)
+                    }
+                    Ok(None) => {
+                        self.push_all([Entity::String(String::new()), Entity::Number(0)], IOR_OK)
+                    }
+                    Err(ior) => {
+                        self.push_all([Entity::String(String::new()), Entity::Number(0)], ior)
                     }
-                    None => panic!("Not enough items on stack to dup"),
                 }
             }
-            Token::Swap => {
-                let a = self.pop();
-                let b = self.pop();
-                match (a, b) {
-                    (Some(a), Some(b)) => {
-                        self.push(b);
-                        self.push(a);
+            Token::ReadLine => match read_line(input).map_err(read_error)? {
+                Some(line) => {
+                    self.push(Entity::String(line));
+                    self.push(Entity::Number(1));
+                }
+                None => {
+                    self.push(Entity::String(String::new()));
+                    self.push(Entity::Number(0));
+                }
+            },
+            Token::ReadOnly => self.push(Entity::Number(READ_ONLY)),
+            Token::WriteOnly => self.push(Entity::Number(WRITE_ONLY)),
+            Token::ReadWrite => self.push(Entity::Number(READ_WRITE)),
+            Token::OpenFile | Token::CreateFile => {
+                let word = if *op == Token::OpenFile {
+                    "OPEN-FILE"
+                } else {
+                    "CREATE-FILE"
+                };
+                self.require_file_access(word)?;
+                self.require(word, 2)?;
+                let access = self.pop_number(word)?;
+                let path = self.pop_string(word)?;
+                match self.files.open(&path, access, *op == Token::CreateFile) {
+                    Ok(handle) => self.push_all([Entity::File(handle)], IOR_OK),
+                    // the handle of a file that failed to open is never valid
+                    Err(ior) => self.push_all([Entity::File(0)], ior),
+                }
+            }
+            Token::ReadFile => {
+                self.require_file_access("READ-FILE")?;
+                self.require("READ-FILE", 2)?;
+                let handle = self.pop_file("READ-FILE")?;
+                let length = self.index("READ-FILE")?;
+                self.pop();
+                match self.files.read(handle, length) {
+                    Ok(text) => self.push_all([Entity::String(text)], IOR_OK),
+                    Err(ior) => self.push_all([Entity::String(String::new())], ior),
+                }
+            }
+            Token::WriteFile => {
+                self.require_file_access("WRITE-FILE")?;
+                self.require("WRITE-FILE", 2)?;
+                let handle = self.pop_file("WRITE-FILE")?;
+                let text = self.pop_string("WRITE-FILE")?;
+                let result = self.files.write(handle, &text);
+                self.push_all([], result.err().unwrap_or(IOR_OK));
+            }
+            Token::CloseFile => {
+                self.require_file_access("CLOSE-FILE")?;
+                let handle = self.pop_file("CLOSE-FILE")?;
+                let result = self.files.close(handle);
+                self.push_all([], result.err().unwrap_or(IOR_OK));
+            }
+            Token::FileSize => {
+                self.require_file_access("FILE-SIZE")?;
+                let handle = self.pop_file("FILE-SIZE")?;
+                match self.files.size(handle) {
+                    Ok(size) => self.push_all([Entity::Number(size as i64)], IOR_OK),
+                    Err(ior) => self.push_all([Entity::Number(0)], ior),
+                }
+            }
+            Token::DeleteFile => {
+                self.require_file_access("DELETE-FILE")?;
+                let path = self.pop_string("DELETE-FILE")?;
+                let result = self.files.delete(&path);
+                self.push_all([], result.err().unwrap_or(IOR_OK));
+            }
+            Token::ToArray => {
+                let n = self.index(">ARRAY")?;
+                self.require(">ARRAY", n + 1)?;
+                self.pop();
+                let items = self.stack.split_off(self.stack.len() - n);
+                self.push(Entity::Array(items));
+            }
+            Token::FromArray => {
+                self.require("ARRAY>", 1)?;
+                match self.pop() {
+                    Some(Entity::Array(items)) => {
+                        let count = items.len() as i64;
+                        self.stack.extend(items);
+                        self.push(Entity::Number(count));
+                    }
+                    Some(entity) => {
+                        return Err(StackyError::runtime(format!(
+                            "ARRAY> expects an array, got {}",
+                            entity
+                        )))
                     }
-                    _ => panic!("Not enough items on stack to swap"),
+                    None => unreachable!(),
                 }
             }
-            Token::Drop => {
-                let a = self.pop();
-                match a {
-                    Some(_) => {}
-                    None => panic!("Not enough items on stack to drop"),
+            Token::ToMap => {
+                let n = self.index(">MAP")?;
+                self.require(">MAP", 2 * n + 1)?;
+                self.pop();
+                let items = self.stack.split_off(self.stack.len() - 2 * n);
+                let mut pairs: Vec<(Entity, Entity)> = vec![];
+                for pair in items.chunks(2) {
+                    let (key, value) = (pair[0].clone(), pair[1].clone());
+                    // a later value of a key replaces the earlier one
+                    match pairs.iter_mut().find(|(k, _)| *k == key) {
+                        Some((_, v)) => *v = value,
+                        None => pairs.push((key, value)),
+                    }
                 }
+                self.push(Entity::Map(pairs));
+            }
+            Token::FromMap => {
+                self.require("MAP>", 1)?;
+                match self.pop() {
+                    Some(Entity::Map(pairs)) => {
+                        let count = pairs.len() as i64;
+                        for (key, value) in pairs {
+                            self.push(key);
+                            self.push(value);
+                        }
+                        self.push(Entity::Number(count));
+                    }
+                    Some(entity) => {
+                        return Err(StackyError::runtime(format!(
+                            "MAP> expects a map, got {}",
+                            entity
+                        )))
+                    }
+                    None => unreachable!(),
+                }
+            }
+            Token::Get => {
+                self.require("GET", 2)?;
+                let key = self.pop().unwrap();
+                let container = self.pop().unwrap();
+                let item = match (&container, &key) {
+                    (Entity::Array(items), Entity::Number(index)) => usize::try_from(*index)
+                        .ok()
+                        .and_then(|index| items.get(index))
+                        .ok_or_else(|| {
+                            StackyError::runtime(format!(
+                                "Index {} is out of range for an array of {} items",
+                                index,
+                                items.len()
+                            ))
+                        })?,
+                    (Entity::Map(pairs), key) => pairs
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, value)| value)
+                        .ok_or_else(|| {
+                            StackyError::runtime(format!("No key {} in the map", key))
+                        })?,
+                    (Entity::Array(_), key) => {
+                        return Err(StackyError::runtime(format!(
+                            "GET expects a number as index of an array, got {}",
+                            key
+                        )))
+                    }
+                    (container, _) => {
+                        return Err(StackyError::runtime(format!(
+                            "GET expects an array or a map, got {}",
+                            container
+                        )))
+                    }
+                };
+                self.push(item.clone());
+            }
+            Token::NumberIn => {
+                let line = read_line(input).map_err(read_error)?.unwrap_or_default();
+                match i64::from_str_radix(line.trim(), self.base) {
+                    Ok(number) => {
+                        self.push(Entity::Number(number));
+                        self.push(Entity::Number(1));
+                    }
+                    Err(_) => {
+                        self.push(Entity::Number(0));
+                        self.push(Entity::Number(0));
+                    }
+                }
+            }
+            Token::ToR => {
+                self.require(">R", 1)?;
+                let a = self.stack.remove(self.stack.len() - 1);
+                self.return_stack.push(a);
+            }
+            Token::FromR => {
+                self.require_return("R>", 1)?;
+                let a = self.return_stack.remove(self.return_stack.len() - 1);
+                self.push(a);
+            }
+            Token::RFetch => {
+                self.require_return("R@", 1)?;
+                self.push(self.return_stack[self.return_stack.len() - 1].clone());
+            }
+            Token::TwoToR => {
+                self.require("2>R", 2)?;
+                let pair = self.stack.split_off(self.stack.len() - 2);
+                self.return_stack.extend(pair);
+            }
+            Token::TwoFromR => {
+                self.require_return("2R>", 2)?;
+                let pair = self.return_stack.split_off(self.return_stack.len() - 2);
+                self.stack.extend(pair);
             }
             Token::Puts => {
                 let length = self.pop();
@@ -280,15 +1028,262 @@ impl StackMachine {
                                 Some(Entity::Number(c)) => {
                                     string.push(c as u8 as char);
                                 }
-                                _ => panic!("Cannot puts non-number"),
+                                _ => return Err(StackyError::runtime("Cannot puts non-number")),
                             }
                         }
-                        write!(output, "{}", string.chars().rev().collect::<String>());
+                        write!(output, "{}", string.chars().rev().collect::<String>())?;
+                    }
+                    Some(Entity::String(string)) => write!(output, "{}", string)?,
+                    _ => return Err(StackyError::runtime("Cannot puts non-number")),
+                }
+            }
+            Token::IntToFloat => {
+                let a = self.pop();
+                match a {
+                    Some(Entity::Number(a)) => self.push(Entity::Float(a as f64)),
+                    Some(Entity::BigInt(a)) => self.push(Entity::Float(big_to_float(&a))),
+                    Some(a) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot convert non-integer {:?} to float",
+                            a
+                        )))
+                    }
+                    None => {
+                        return Err(StackyError::runtime(
+                            "Not enough items on stack to convert to float",
+                        ))
                     }
-                    _ => panic!("Cannot puts non-number"),
                 }
             }
-            _ => panic!("Cannot execute token: {:?}", op),
+            Token::FloatToInt => {
+                let a = self.pop();
+                match a {
+                    Some(Entity::Float(a)) => {
+                        let integer = self.float_to_int(a)?;
+                        self.push(integer);
+                    }
+                    Some(a) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot convert non-float {:?} to integer",
+                            a
+                        )))
+                    }
+                    None => {
+                        return Err(StackyError::runtime(
+                            "Not enough items on stack to convert to integer",
+                        ))
+                    }
+                }
+            }
+            Token::FRound | Token::FFloor | Token::FTrunc => {
+                let a = self.pop();
+                match a {
+                    Some(Entity::Float(a)) => {
+                        let rounded = match op {
+                            Token::FRound => a.round(),
+                            Token::FFloor => a.floor(),
+                            _ => a.trunc(),
+                        };
+                        self.push(Entity::Float(rounded));
+                    }
+                    Some(a) => {
+                        return Err(StackyError::runtime(format!(
+                            "Cannot round non-float {:?}",
+                            a
+                        )))
+                    }
+                    None => return Err(StackyError::runtime("Not enough items on stack to round")),
+                }
+            }
+            Token::Hex => self.base = 16,
+            Token::Decimal => self.base = 10,
+            Token::Binary => self.base = 2,
+            _ => {
+                return Err(StackyError::runtime(format!(
+                    "Cannot execute token: {:?}",
+                    op
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// applies an integer operation to b and a, following the overflow policy if it does not fit
+    fn integer_operation(&self, op: &Token, b: i64, a: i64) -> Result<Entity, StackyError> {
+        let (operation, checked, wrapping, saturating, exact): (
+            _,
+            _,
+            _,
+            _,
+            fn(BigInt, i64) -> BigInt,
+        ) = match op {
+            Token::Add => (
+                "+",
+                b.checked_add(a),
+                b.wrapping_add(a),
+                b.saturating_add(a),
+                |b, a| b + a,
+            ),
+            Token::Sub => (
+                "-",
+                b.checked_sub(a),
+                b.wrapping_sub(a),
+                b.saturating_sub(a),
+                |b, a| b - a,
+            ),
+            Token::Mul => (
+                "*",
+                b.checked_mul(a),
+                b.wrapping_mul(a),
+                b.saturating_mul(a),
+                |b, a| b * a,
+            ),
+            Token::Div => (
+                "/",
+                b.checked_div(a),
+                b.wrapping_div(a),
+                b.saturating_div(a),
+                |b, a| b / a,
+            ),
+            // the only overflowing remainder is i64::MIN % -1, which is exactly 0 under every policy
+            Token::Percent => (
+                "%",
+                b.checked_rem(a).or((a == -1).then_some(0)),
+                b.wrapping_rem(a),
+                b.wrapping_rem(a),
+                |b, a| b % a,
+            ),
+            _ => {
+                return Err(StackyError::runtime(format!(
+                    "Cannot execute token: {:?}",
+                    op
+                )))
+            }
+        };
+
+        match (checked, self.overflow_policy) {
+            (Some(result), _) => Ok(Entity::Number(result)),
+            (None, OverflowPolicy::Checked) => Err(StackyError::IntegerOverflow {
+                operation,
+                left: b,
+                right: a,
+            }),
+            (None, OverflowPolicy::Wrapping) => Ok(Entity::Number(wrapping)),
+            (None, OverflowPolicy::Saturating) => Ok(Entity::Number(saturating)),
+            (None, OverflowPolicy::BigInt) => Ok(normalize(exact(BigInt::from(b), a))),
+        }
+    }
+
+    fn float_to_int(&self, a: f64) -> Result<Entity, StackyError> {
+        let truncated = a.trunc();
+        if truncated.is_finite() && truncated >= i64::MIN as f64 && truncated < i64::MAX as f64 {
+            return Ok(Entity::Number(truncated as i64));
+        }
+        match (BigInt::from_f64(truncated), self.overflow_policy) {
+            (Some(big), OverflowPolicy::BigInt) => Ok(Entity::BigInt(big)),
+            // saturating casts clamp to the 64 bit range
+            (Some(_), OverflowPolicy::Saturating) => Ok(Entity::Number(truncated as i64)),
+            _ => Err(StackyError::runtime(format!(
+                "Cannot convert {:?} to integer",
+                a
+            ))),
+        }
+    }
+}
+
+/// converts the operands to a common kind: numbers become big integers or floats to match the other
+fn promote(a: Option<Entity>, b: Option<Entity>) -> (Option<Entity>, Option<Entity>) {
+    match (a, b) {
+        (Some(Entity::Number(a)), Some(Entity::Float(b))) => {
+            (Some(Entity::Float(a as f64)), Some(Entity::Float(b)))
+        }
+        (Some(Entity::Float(a)), Some(Entity::Number(b))) => {
+            (Some(Entity::Float(a)), Some(Entity::Float(b as f64)))
+        }
+        (Some(Entity::Number(a)), Some(Entity::BigInt(b))) => (
+            Some(Entity::BigInt(BigInt::from(a))),
+            Some(Entity::BigInt(b)),
+        ),
+        (Some(Entity::BigInt(a)), Some(Entity::Number(b))) => (
+            Some(Entity::BigInt(a)),
+            Some(Entity::BigInt(BigInt::from(b))),
+        ),
+        (Some(Entity::BigInt(a)), Some(Entity::Float(b))) => (
+            Some(Entity::Float(big_to_float(&a))),
+            Some(Entity::Float(b)),
+        ),
+        (Some(Entity::Float(a)), Some(Entity::BigInt(b))) => (
+            Some(Entity::Float(a)),
+            Some(Entity::Float(big_to_float(&b))),
+        ),
+        (a, b) => (a, b),
+    }
+}
+
+/// the bytes an item takes, arrays and maps take the bytes of their items
+fn size(entity: &Entity) -> usize {
+    match entity {
+        Entity::String(x) => x.len(),
+        Entity::BigInt(x) => x.bits().div_ceil(8) as usize,
+        Entity::Array(items) => items.iter().map(size).sum(),
+        Entity::Map(pairs) => pairs
+            .iter()
+            .map(|(key, value)| size(key) + size(value))
+            .sum(),
+        _ => 8,
+    }
+}
+
+/// big integers that fit into 64 bits are turned back into plain numbers
+fn normalize(number: BigInt) -> Entity {
+    match number.to_i64() {
+        Some(number) => Entity::Number(number),
+        None => Entity::BigInt(number),
+    }
+}
+
+fn big_to_float(number: &BigInt) -> f64 {
+    number.to_f64().unwrap_or(f64::NAN)
+}
+
+fn read_error(error: io::Error) -> StackyError {
+    StackyError::Io(format!("Failed to read input: {}", error))
+}
+
+/// reads one character, bytes that are no valid UTF-8 read as the replacement character
+fn read_char(input: &mut impl BufRead) -> io::Result<Option<char>> {
+    let mut bytes = [0; 4];
+    if input.read(&mut bytes[..1])? == 0 {
+        return Ok(None);
+    }
+    let length = match bytes[0] {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    };
+    let mut read = 1;
+    while read < length {
+        match input.read(&mut bytes[read..length])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    let text = String::from_utf8_lossy(&bytes[..read]);
+    Ok(text.chars().next())
+}
+
+/// reads a line without its line break, none at the end of the input
+fn read_line(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
         }
     }
+    Ok(Some(line))
 }
@@ -1,35 +1,273 @@
-use std::io::Write;
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+    sync::Arc,
+};
 
 use crate::{
-    interpreter::Interpreter,
+    checker::Checker,
+    cst::Cst,
+    error::StackyError,
+    interpreter::{Hook, Interpreter},
     lexer::{Lexer, Token},
-    parser::{Ast, Parser},
+    limits::{CancelHandle, Limits},
+    loader::Loader,
+    native::NativeWord,
+    optimizer::Optimizer,
+    parser::{Ast, Node},
+    span::Span,
+    stack_machine::{Entity, OverflowPolicy, StackMachine},
 };
 
-pub(crate) struct Stacky<'a> {
-    lexer: Lexer<'a>,
-    parser: Parser<'a>,
+/// the name used in spans for source that does not come from a file
+const INPUT: &str = "<input>";
+
+/// the name used in spans for words called by the host
+const CALL: &str = "<call>";
+
+/// words written in stacky that are loaded before user code
+const PRELUDE: &str = include_str!("prelude.f");
+
+pub(crate) struct Stacky {
     interpreter: Interpreter,
+    loader: Loader,
+    checker: Checker,
+    check: bool,
+    opt_level: u8,
+    /// if the prelude is loaded again when the state is reset
+    prelude: bool,
 }
 
-impl<'a> Stacky<'a> {
+impl Stacky {
     pub(crate) fn new() -> Self {
+        let mut stacky = Self::without_prelude();
+        stacky.prelude = true;
+        stacky
+            .load_prelude(PRELUDE)
+            .expect("the prelude is valid stacky");
+        stacky
+    }
+
+    /// creates an interpreter that only knows the native words
+    pub(crate) fn without_prelude() -> Self {
         Self {
-            lexer: Lexer::new(),
-            parser: Parser::new(),
             interpreter: Interpreter::new(),
+            loader: Loader::new(),
+            checker: Checker::new(),
+            check: true,
+            opt_level: 0,
+            prelude: false,
+        }
+    }
+
+    /// runs a library of definitions, definitions of native words are skipped so the native words are kept
+    pub(crate) fn load_prelude(&mut self, text: &str) -> Result<(), StackyError> {
+        let mut lexer = Lexer::new(Arc::from("<prelude>"));
+        lexer.set_base(self.interpreter.base());
+        lexer.set_words(self.interpreter.words());
+        lexer.set_prefer_native(true);
+        let ast = Cst::build(lexer.lex_lossless(text)?).lower_preferring_native()?;
+        self.checker.check(&ast)?;
+        self.try_run(&ast, &mut io::sink())
+    }
+
+    pub(crate) fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.interpreter.set_overflow_policy(policy);
+    }
+
+    /// limits what the following runs may use
+    pub(crate) fn set_limits(&mut self, limits: Limits) {
+        self.interpreter.set_limits(limits);
+    }
+
+    /// allows or denies the file words access to the file system, it is allowed by default
+    pub(crate) fn set_file_access(&mut self, allowed: bool) {
+        self.interpreter.set_file_access(allowed);
+        self.loader.set_file_access(allowed);
+    }
+
+    /// a handle to stop runs from another thread
+    pub(crate) fn cancel_handle(&self) -> CancelHandle {
+        self.interpreter.cancel_handle()
+    }
+
+    /// adds a directory that INCLUDE and REQUIRE search after the directory of the including file
+    pub(crate) fn add_search_path(&mut self, dir: impl Into<std::path::PathBuf>) {
+        self.loader.add_search_path(dir);
+    }
+
+    /// forgets the stack, words, variables and open files but keeps the settings and native words
+    pub(crate) fn reset(&mut self) {
+        self.interpreter.reset();
+        self.loader.reset();
+        self.checker = Checker::new();
+        if self.prelude {
+            self.load_prelude(PRELUDE)
+                .expect("the prelude is valid stacky");
+        }
+    }
+
+    pub(crate) fn stack_machine(&mut self) -> &mut StackMachine {
+        self.interpreter.stack_machine()
+    }
+
+    pub(crate) fn register(&mut self, name: &str, word: Box<dyn NativeWord + Send>) {
+        self.interpreter.register(name, word);
+    }
+
+    pub(crate) fn load_plugin(&mut self, path: &Path) -> Result<(), StackyError> {
+        self.interpreter.load_plugin(path)
+    }
+
+    /// the data stack, bottom first
+    pub(crate) fn stack(&self) -> &[Entity] {
+        self.interpreter.stack()
+    }
+
+    pub(crate) fn variable(&self, name: &str) -> Option<Entity> {
+        self.interpreter.variable(name)
+    }
+
+    pub(crate) fn set_variable(&mut self, name: &str, value: Entity) {
+        self.interpreter.set_variable(name, value);
+    }
+
+    /// runs a word by its name
+    pub(crate) fn call(
+        &mut self,
+        word: &str,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<(), StackyError> {
+        let call = Node {
+            ast: Ast::FunctionCall(word.to_string()),
+            span: Span {
+                file: Arc::from(CALL),
+                line: 1,
+                column: 1,
+            },
+        };
+        self.try_run_with_input(&Ast::Expressions(vec![call]), input, output)
+    }
+
+    /// lexes, parses and runs the input, the state of the interpreter is kept between calls
+    #[cfg(test)]
+    pub(crate) fn eval(&mut self, input: &str, output: &mut impl Write) -> Result<(), StackyError> {
+        self.eval_with_input(input, &mut io::empty(), output)
+    }
+
+    /// like eval, the program reads from `input` with words like KEY and READ-LINE
+    pub(crate) fn eval_with_input(
+        &mut self,
+        source: &str,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<(), StackyError> {
+        let words = self.interpreter.words();
+        let ast = self.loader.load_source(
+            INPUT,
+            source,
+            Path::new("."),
+            self.interpreter.base(),
+            &words,
+        )?;
+        self.check(&ast)?;
+        self.try_run_with_input(&ast, input, output)
+    }
+
+    /// turns the stack effect checks that run before a program off or on
+    pub(crate) fn set_check(&mut self, check: bool) {
+        self.check = check;
+    }
+
+    /// sets how much whole programs are optimized, from 0 for not at all to 2
+    pub(crate) fn set_opt_level(&mut self, level: u8) {
+        self.opt_level = level;
+    }
+
+    /// optimizes a whole program, words it does not call are removed so no input may follow it
+    pub(crate) fn optimize(&self, ast: Ast) -> Ast {
+        Optimizer::new(self.opt_level).optimize(ast)
+    }
+
+    /// checks the stack effects of the words in the program before it runs
+    pub(crate) fn check(&mut self, ast: &Ast) -> Result<(), StackyError> {
+        if !self.check {
+            return Ok(());
         }
+        self.checker.check(ast)
+    }
+
+    /// lexes and parses a file together with the files it includes
+    pub(crate) fn load_file(&mut self, path: &Path) -> Result<Ast, StackyError> {
+        let words = self.interpreter.words();
+        self.loader.load_file(path, self.interpreter.base(), &words)
+    }
+
+    /// the lexer reads numbers in the current BASE and knows the words defined so far
+    pub(crate) fn try_lex(&mut self, input: &str) -> Result<Vec<(Token, Span)>, StackyError> {
+        let mut lexer = Lexer::new(Arc::from(INPUT));
+        lexer.set_base(self.interpreter.base());
+        lexer.set_words(self.interpreter.words());
+        lexer.lex(input)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn try_parse(&mut self, tokens: &[(Token, Span)]) -> Result<Ast, StackyError> {
+        let mut ast = crate::parser::parse(tokens)?;
+        let words = self.interpreter.words();
+        self.loader
+            .resolve(&mut ast, Path::new("."), self.interpreter.base(), &words)?;
+        Ok(ast)
     }
 
-    pub(crate) fn lex(&mut self, input: &'a str) -> Vec<Token> {
-        self.lexer.lex(input)
+    pub(crate) fn try_run(
+        &mut self,
+        ast: &Ast,
+        output: &mut impl Write,
+    ) -> Result<(), StackyError> {
+        self.try_run_with_input(ast, &mut io::empty(), output)
     }
 
-    pub(crate) fn parse(&mut self, tokens: &'a [Token]) -> Ast {
-        self.parser.parse(tokens)
+    /// runs a program that reads from `input`
+    pub(crate) fn try_run_with_input(
+        &mut self,
+        ast: &Ast,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<(), StackyError> {
+        self.interpreter.run(ast, input, output)
     }
 
+    /// runs a program that reads from `input` and lets the hook watch every node
+    pub(crate) fn try_run_with(
+        &mut self,
+        ast: &Ast,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+        hook: &mut dyn Hook,
+    ) -> Result<(), StackyError> {
+        self.interpreter.run_with(ast, input, output, hook)
+    }
+
+    // the tests rely on the panic messages, so these wrappers panic on errors
+
+    #[cfg(test)]
+    pub(crate) fn lex(&mut self, input: &str) -> Vec<(Token, Span)> {
+        self.try_lex(input)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn parse(&mut self, tokens: &[(Token, Span)]) -> Ast {
+        self.try_parse(tokens)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    #[cfg(test)]
     pub(crate) fn run(&mut self, ast: &Ast, output: &mut impl Write) {
-        self.interpreter.run(ast, output);
+        if let Err(error) = self.try_run(ast, output) {
+            panic!("{}", error);
+        }
     }
 }
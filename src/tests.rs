@@ -1,20 +1,59 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+
+    use proptest::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    use crate::bridge::{from_value, to_value};
+    use crate::cst::Cst;
+    use crate::debugger::Debugger;
+    use crate::engine::{Engine, Value};
+    use crate::error::StackyError;
+    use crate::ffi::{
+        catch, stacky_engine_free, stacky_engine_new, stacky_eval, stacky_last_error,
+    };
+    use crate::formatter::format_source;
+    use crate::interpreter::{Event, Hook};
+    use crate::lexer::{LexemeKind, Lexer, Token};
+    use crate::limits::{Limit, Limits, MAX_CALLS};
+    use crate::linter::{Linter, Rule};
+    use crate::native::{NativeWord, Stack};
+    use crate::parser::{parse, Ast};
+    use crate::profiler::Profiler;
+    use crate::stack_machine::OverflowPolicy;
     use crate::stacky::Stacky;
+    use crate::tracer::Tracer;
+
+    /// writes the files into a fresh directory below the temp dir and returns the directory
+    fn source_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("rust_stacky_{}", std::process::id()))
+            .join(name);
+        for (file, text) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        dir
+    }
 
     #[test]
     fn add() {
         let input = "1 2 + .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "3"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -22,14 +61,14 @@ mod tests {
         let input = "1 2 - .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "-1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -37,14 +76,14 @@ mod tests {
         let input = "3 2 * .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "6"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -52,14 +91,14 @@ mod tests {
         let input = "4 2 / .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "2"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -67,14 +106,14 @@ mod tests {
         let input = "3 2 / .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -82,14 +121,14 @@ mod tests {
         let input = ":Test 1 2 + . ; Test";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "3"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -98,7 +137,7 @@ mod tests {
         let input = ":Test 1 2 + . ; Testl";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
     }
@@ -108,28 +147,28 @@ mod tests {
         let input = "1 1 = IF \"TRUE\" ELSE \"FALSE\" THEN PUTS";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "TRUE"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
     #[test]
     fn if_test_false() {
         let input = "1 2 = IF \"TRUE\" ELSE \"FALSE\" THEN PUTS";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "FALSE"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -137,14 +176,14 @@ mod tests {
         let input = "1 2 < .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -152,14 +191,14 @@ mod tests {
         let input = "2 2 < .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "0"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -167,14 +206,14 @@ mod tests {
         let input = "2 2 <= .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -182,14 +221,14 @@ mod tests {
         let input = "1 2 <= .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -197,14 +236,14 @@ mod tests {
         let input = "3 2 <= .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "0"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -212,14 +251,14 @@ mod tests {
         let input = "3 2 > .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -227,14 +266,14 @@ mod tests {
         let input = "2 2 > .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "0"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -242,14 +281,14 @@ mod tests {
         let input = "2 2 >= .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -257,14 +296,14 @@ mod tests {
         let input = "3 2 >= .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -272,14 +311,14 @@ mod tests {
         let input = "1 2 >= .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "0"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -288,7 +327,7 @@ mod tests {
         let input = ": 1 2 + ;";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
     }
@@ -298,14 +337,14 @@ mod tests {
         let input = "2 DUP + .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "4"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -313,14 +352,14 @@ mod tests {
         let input = "1 2 SWAP . .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "21"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -328,14 +367,14 @@ mod tests {
         let input = "1 2 DROP . ";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -343,14 +382,14 @@ mod tests {
         let input = "10 0 DO 1 . LOOP";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "1111111111"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -358,14 +397,14 @@ mod tests {
         let input = "2 2 DO 1 . LOOP";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
-            Ok(v) => assert!(v == ""),
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v.is_empty()),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
     }
 
     #[test]
@@ -373,13 +412,2624 @@ mod tests {
         let input = "10 -1 + .";
         let mut output: Vec<u8> = Vec::new();
         let mut stacky = Stacky::new();
-        let tokens = stacky.lex(&input);
+        let tokens = stacky.lex(input);
         let ast = stacky.parse(&tokens);
         stacky.run(&ast, &mut output);
 
-        let _s = match String::from_utf8(output) {
+        match String::from_utf8(output) {
             Ok(v) => assert!(v == "9"),
             Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-        };
+        }
+    }
+
+    #[test]
+    fn float_add() {
+        let input = "1.5 2.25 + .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "3.75"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn float_mixed_arithmetic() {
+        let input = "1 0.5 + . 3 2.0 / .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "1.51.5"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn float_prints_fraction() {
+        let input = "6.0 .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "6.0"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn float_negative_exponent() {
+        let input = "-1.5e2 .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "-150.0"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn float_compare_mixed() {
+        let input = "1 1.5 < . 2.0 2 = .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "11"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn float_conversions() {
+        let input = "3 S>F . 7.9 F>S . -7.9 F>S .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "3.07-7"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn float_rounding() {
+        let input = "2.5 FROUND . -2.5 FLOOR . -2.5 FTRUNC .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "3.0-3.0-2.0"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn number_followed_by_dot_emits() {
+        let input = "1. 2 .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "12"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn numbers_are_64_bit() {
+        let input = "3000000000 3 * .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "9000000000"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Integer overflow")]
+    fn integer_overflow() {
+        let input = "9223372036854775807 1 +";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn big_integer_grows() {
+        let input = "9223372036854775807 1 + .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_overflow_policy(OverflowPolicy::BigInt);
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "9223372036854775808"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn big_integer_literal() {
+        let input = "123456789012345678901234567890 DUP * .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_overflow_policy(OverflowPolicy::BigInt);
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "15241578753238836750495351562536198787501905199875019052100"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn big_integer_shrinks() {
+        let input = "9223372036854775807 1 + 9223372036854775807 - 2 * .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_overflow_policy(OverflowPolicy::BigInt);
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "2"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit into 64 bits")]
+    fn big_integer_literal_disabled() {
+        let input = "123456789012345678901234567890 .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Integer overflow: 9223372036854775807 + 1")]
+    fn overflow_checked_error() {
+        let input = "9223372036854775807 1 + .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn overflow_wrapping() {
+        let input = "9223372036854775807 1 + . -9223372036854775808 -1 / .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_overflow_policy(OverflowPolicy::Wrapping);
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "-9223372036854775808-9223372036854775808"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn remainder_of_min_by_minus_one_is_zero() {
+        let policies = [
+            OverflowPolicy::Checked,
+            OverflowPolicy::Wrapping,
+            OverflowPolicy::Saturating,
+            OverflowPolicy::BigInt,
+        ];
+        for policy in policies {
+            let mut output: Vec<u8> = Vec::new();
+            let mut stacky = Stacky::new();
+            stacky.set_overflow_policy(policy);
+            stacky
+                .eval("-9223372036854775808 -1 % .", &mut output)
+                .unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), "0", "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn overflow_saturating() {
+        let input = "9223372036854775807 1 + . -9223372036854775807 10 - .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_overflow_policy(OverflowPolicy::Saturating);
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "9223372036854775807-9223372036854775808"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn overflow_pragma() {
+        let input = "PRAGMA OVERFLOW WRAPPING 9223372036854775807 1 + .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "-9223372036854775808"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "only allowed at the top level")]
+    fn overflow_pragma_in_word() {
+        let input = ": Test PRAGMA OVERFLOW WRAPPING ;";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown overflow policy")]
+    fn overflow_pragma_unknown() {
+        let input = "PRAGMA OVERFLOW SOMETIMES";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn overflow_structured_error() {
+        let input = "-9223372036854775808 -1 *";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+
+        assert_eq!(
+            stacky.try_run(&ast, &mut output).unwrap_err().root(),
+            &StackyError::IntegerOverflow {
+                operation: "*",
+                left: i64::MIN,
+                right: -1
+            }
+        );
+    }
+
+    #[test]
+    fn hex_literals() {
+        let input = "$FF 0xff + .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "510"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn binary_and_octal_literals() {
+        let input = "%1010 0b11 + . 0o17 .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "1315"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn percent_is_still_modulo() {
+        let input = "7 3 % .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "1"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn char_literal() {
+        let input = "'A' . '\\n' .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "6510"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn hex_printing() {
+        let input = "255 HEX . DECIMAL -255 HEX . DECIMAL 255 .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "FF-FF255"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn base_variable() {
+        let input = "16 -> BASE FF . @ BASE DECIMAL .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "FF16"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn hex_defined_word() {
+        let input = ": FF 1 ; HEX FF . 10 .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "110"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid digit in 2 for base 2")]
+    fn binary_invalid_digit() {
+        let input = "BINARY 2 .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid base 1")]
+    fn invalid_base() {
+        let input = "1 -> BASE";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn base_changes_only_at_the_top_level() {
+        let mut engine = Engine::new();
+        assert_eq!(
+            engine.eval(": H HEX ; 10 .").unwrap_err().to_string(),
+            "<input>:1:5: HEX can only be used at the top level, outside of definitions, IF and DO"
+        );
+        assert_eq!(
+            engine.eval("0 IF HEX THEN 10 .").unwrap_err().to_string(),
+            "<input>:1:6: HEX can only be used at the top level, outside of definitions, IF and DO"
+        );
+        assert_eq!(
+            engine.eval(": X 16 -> BASE ;").unwrap_err().to_string(),
+            "<input>:1:11: -> BASE can only be used at the top level, outside of definitions, IF and DO"
+        );
+        assert_eq!(
+            engine.eval("@ BASE -> BASE").unwrap_err().to_string(),
+            "<input>:1:11: -> BASE needs a number right before it"
+        );
+        assert_eq!(engine.eval("HEX 10 DECIMAL .").unwrap(), "16");
+    }
+
+    #[test]
+    fn base_is_kept_between_evals() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.eval("HEX : Twice DUP + ;", &mut output).unwrap();
+        stacky.eval("FF Twice .", &mut output).unwrap();
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "1FE"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn include() {
+        let dir = source_dir(
+            "include",
+            &[
+                ("main.f", "INCLUDE square.f 3 Square ."),
+                ("square.f", ": Square DUP * ;"),
+            ],
+        );
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let ast = stacky.load_file(&dir.join("main.f")).unwrap();
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "9"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn include_is_relative_to_the_including_file() {
+        let dir = source_dir(
+            "relative",
+            &[
+                ("main.f", "INCLUDE lib/a.f A ."),
+                ("lib/a.f", "INCLUDE b.f : A B 1 + ;"),
+                ("lib/b.f", ": B 41 ;"),
+            ],
+        );
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let ast = stacky.load_file(&dir.join("main.f")).unwrap();
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "42"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn include_search_path() {
+        let dir = source_dir("search_path", &[("lib/hello.f", "\"Hello\" PUTS")]);
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.add_search_path(dir.join("lib"));
+        stacky.eval("INCLUDE hello.f", &mut output).unwrap();
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "Hello"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn require_loads_a_file_once() {
+        let dir = source_dir(
+            "require",
+            &[
+                ("main.f", "REQUIRE one.f REQUIRE one.f INCLUDE one.f"),
+                ("one.f", "1 ."),
+            ],
+        );
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let ast = stacky.load_file(&dir.join("main.f")).unwrap();
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "11"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = source_dir("cycle", &[("a.f", "INCLUDE b.f"), ("b.f", "INCLUDE a.f")]);
+        let mut stacky = Stacky::new();
+        let error = stacky.load_file(&dir.join("a.f")).unwrap_err();
+
+        assert!(error.to_string().contains("Include cycle: "));
+        assert!(error.to_string().contains("a.f -> "));
+    }
+
+    #[test]
+    fn include_missing_file() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky.eval("INCLUDE missing.f", &mut output).unwrap_err();
+
+        assert_eq!(error.to_string(), "<input>:1:1: Cannot find missing.f");
+    }
+
+    #[test]
+    fn include_only_at_the_top_level() {
+        let dir = source_dir(
+            "nested_include",
+            &[
+                ("if.f", "0 IF INCLUDE missing.f THEN"),
+                ("word.f", ": Load REQUIRE lib.f ; Load Load"),
+                ("lib.f", "1 ."),
+            ],
+        );
+        let mut stacky = Stacky::new();
+        let error = stacky.load_file(&dir.join("if.f")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:1:6: INCLUDE can only be used at the top level, outside of definitions, IF and DO",
+                dir.join("if.f").display()
+            )
+        );
+        let error = stacky.load_file(&dir.join("word.f")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:1:8: REQUIRE can only be used at the top level, outside of definitions, IF and DO",
+                dir.join("word.f").display()
+            )
+        );
+
+        let mut output: Vec<u8> = Vec::new();
+        let error = stacky
+            .eval("5 0 DO INCLUDE lib.f LOOP", &mut output)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:8: INCLUDE can only be used at the top level, outside of definitions, IF and DO"
+        );
+    }
+
+    #[test]
+    fn pragma_is_scoped_to_the_included_file() {
+        let dir = source_dir(
+            "pragma",
+            &[
+                ("main.f", "INCLUDE wrapping.f 9223372036854775807 1 + ."),
+                (
+                    "wrapping.f",
+                    "PRAGMA OVERFLOW WRAPPING 9223372036854775807 1 + .",
+                ),
+            ],
+        );
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let ast = stacky.load_file(&dir.join("main.f")).unwrap();
+        let error = stacky.try_run(&ast, &mut output).unwrap_err();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "-9223372036854775808");
+        assert_eq!(
+            error.root(),
+            &StackyError::IntegerOverflow {
+                operation: "+",
+                left: i64::MAX,
+                right: 1
+            }
+        );
+    }
+
+    #[test]
+    fn error_names_the_file_and_line() {
+        let dir = source_dir(
+            "location",
+            &[("main.f", "INCLUDE lib.f"), ("lib.f", "1 .\n  2 Missing")],
+        );
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let ast = stacky.load_file(&dir.join("main.f")).unwrap();
+        let error = stacky.try_run(&ast, &mut output).unwrap_err();
+
+        let lib = dir.join("lib.f");
+        assert_eq!(
+            error.to_string(),
+            format!("{}:2:5: Word not found", lib.display())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "<input>:2:3: Word not found")]
+    fn error_location() {
+        let input = "1 .\n  Missing";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn prelude_stack_words() {
+        let input = "1 2 OVER . . . 1 2 3 ROT . . . 1 2 NIP . 1 2 TUCK . . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "1211322212"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn prelude_definitions_use_no_variables() {
+        // run as user code, the definitions replace the native words
+        let prelude = include_str!("prelude.f");
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::without_prelude();
+        stacky.eval(prelude, &mut output).unwrap();
+        let input = "1 2 OVER . . . 1 2 3 ROT . . . 1 2 NIP . 1 2 TUCK . . . 3 4 MIN . 3 4 MAX .";
+        stacky.eval(input, &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "121132221234");
+        for name in ["PRELUDE_A", "PRELUDE_B", "PRELUDE_C"] {
+            assert_eq!(stacky.variable(name), None);
+        }
+    }
+
+    #[test]
+    fn prelude_2dup() {
+        let input = "1 2 2DUP . . . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "2121"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn prelude_math() {
+        let input = "5 NEGATE . -5 ABS . 3 7 MIN . 7 3 MAX . -1.5 ABS . 4 SQUARE .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "-55371.516"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn comments() {
+        let input = "\\ a line comment\n1 ( a comment ) 2 + . \\ another one";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "3"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "<input>:1:3: Unterminated comment")]
+    fn unterminated_comment() {
+        let input = "1 ( never closed";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn redefine_native_word() {
+        let input = ": DUP 42 ; 1 DUP . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "421"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Word not found")]
+    fn without_prelude() {
+        let input = "4 SQUARE .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::without_prelude();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn prelude_prefers_native_words() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::without_prelude();
+        stacky.load_prelude(": DUP 42 ; : Twice DUP + ;").unwrap();
+        stacky.eval("3 Twice .", &mut output).unwrap();
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "6"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn rot() {
+        let input = "1 2 3 ROT . . . 1 2 3 -ROT . . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "132213"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn nip_tuck() {
+        let input = "1 2 NIP . 1 2 TUCK . . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "2212"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn pick() {
+        let input = "10 20 30 2 PICK . 0 PICK . DEPTH .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "10303"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn roll() {
+        let input = "10 20 30 2 ROLL . . . 1 2 0 ROLL . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "10302021"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn double_words() {
+        let input = "1 2 3 4 2SWAP . . . . 1 2 3 4 2OVER . . . . . . 1 2 3 2DROP .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "21432143211"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn depth_and_clear() {
+        let input = "DEPTH . 1 2 3 DEPTH . CLEAR DEPTH .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "030"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Stack underflow: OVER requires 2 items, 1 present")]
+    fn over_underflow() {
+        let input = "1 OVER";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Stack underflow: PICK requires 7 items, 3 present")]
+    fn pick_underflow() {
+        let input = "1 2 5 PICK";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    #[should_panic(expected = "PICK expects a non-negative number")]
+    fn pick_negative() {
+        let input = "1 -1 PICK";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn pick_huge_count() {
+        assert_eq!(
+            Engine::new()
+                .eval("1 2 100000000000 PICK")
+                .unwrap_err()
+                .to_string(),
+            "<input>:1:18: Stack underflow: PICK requires 100000000002 items, 3 present"
+        );
+        assert_eq!(
+            Engine::new()
+                .eval("1 9223372036854775807 ROLL")
+                .unwrap_err()
+                .to_string(),
+            "<input>:1:23: Stack underflow: ROLL requires 9223372036854775809 items, 2 present"
+        );
+        assert_eq!(
+            Engine::new()
+                .eval("65 66 100000000000 PUTS")
+                .unwrap_err()
+                .to_string(),
+            "<input>:1:20: Cannot puts non-number"
+        );
+    }
+
+    #[test]
+    fn underflow_structured_error() {
+        let input = "1 2 3 2SWAP";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+
+        assert_eq!(
+            stacky.try_run(&ast, &mut output).unwrap_err().root(),
+            &StackyError::StackUnderflow {
+                word: "2SWAP".to_string(),
+                required: 4,
+                present: 3
+            }
+        );
+
+        let mut engine = Engine::new();
+        assert_eq!(
+            engine.eval("1 SWAP").unwrap_err().root(),
+            &StackyError::StackUnderflow {
+                word: "SWAP".to_string(),
+                required: 2,
+                present: 1
+            }
+        );
+        assert_eq!(engine.depth(), 1);
+    }
+
+    #[test]
+    fn return_stack() {
+        let input = "1 2 >R . R> . 3 >R R@ R> . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "1233"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn return_stack_pairs() {
+        let input = "1 2 2>R DEPTH . 2R> . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "021"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn return_stack_in_word() {
+        let input = ": Under >R 10 + R> ; 1 2 Under . .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "211"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Return stack underflow: R> requires 1 items, 0 present")]
+    fn return_stack_underflow() {
+        let input = "R>";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unbalanced return stack: Stash left 1 items")]
+    fn return_stack_left_in_word() {
+        let input = ": Stash >R ; 1 Stash";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unbalanced return stack: Take took 1 items")]
+    fn return_stack_taken_in_word() {
+        let input = ": Take R> ; 1 >R Take";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+    }
+
+    #[test]
+    fn nested_loops() {
+        let input = "3 0 DO 2 0 DO J . LOOP LOOP";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "001122"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn declared_stack_effect() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky
+            .eval(": Square ( n -- n*n ) DUP * ; 3 Square .", &mut output)
+            .unwrap();
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "9"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn declared_stack_effect_passes_items_through() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky
+            .eval(
+                ": Keep ( a b -- a b ) ; : Hello ( -- ) \"Hello\" PUTS ; 1 2 Keep . . Hello",
+                &mut output,
+            )
+            .unwrap();
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "21Hello"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn unknown_stack_effect_is_not_checked() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky
+            .eval(
+                ": Print PUTS ; : Down 1 - DUP 0 > IF Down THEN ; 5 Down .",
+                &mut output,
+            )
+            .unwrap();
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "0"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn stack_effect_mismatch() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky
+            .eval(": Square ( a b -- c ) DUP * ;", &mut output)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:1: Square takes 1 and leaves 1 items, but is declared as ( a b -- c )"
+        );
+    }
+
+    #[test]
+    fn stack_effect_of_called_words() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky
+            .eval(": Eat ( a -- ) DROP ;\n: Nothing ( -- ) Eat ;", &mut output)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:2:1: Nothing takes 1 and leaves 0 items, but is declared as ( -- )"
+        );
+    }
+
+    #[test]
+    fn if_branches_differ() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky
+            .eval(": Maybe 1 = IF 5 THEN ;", &mut output)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:13: IF branches change the stack depth differently: by 1 and by 0"
+        );
+    }
+
+    #[test]
+    fn loop_body_unbalanced() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky
+            .eval(": Count 10 0 DO I LOOP ;", &mut output)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:14: LOOP body changes the stack depth by 1"
+        );
+    }
+
+    #[test]
+    fn disable_stack_effect_check() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_check(false);
+        stacky
+            .eval(": Maybe 1 = IF 5 THEN ; 1 Maybe .", &mut output)
+            .unwrap();
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "5"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn string_values() {
+        let input = "\"Hello\" DUP = . \"Hello, \" \"World\" + PUTS \"!\" .";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "1Hello, World!"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn puts_characters() {
+        let input = "72 105 2 PUTS";
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        stacky.run(&ast, &mut output);
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "Hi"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn type_error_string_arithmetic() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky.eval("\"abc\" 1 +", &mut output).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:9: + cannot take a string and a number"
+        );
+    }
+
+    #[test]
+    fn type_error_if_condition() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky.eval("\"abc\" IF 1 . THEN", &mut output).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:7: IF expects a number, got a string"
+        );
+    }
+
+    #[test]
+    fn type_error_through_stack_words() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky.eval("\"a\" 1 OVER S>F", &mut output).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:12: S>F expects a number, got a string"
+        );
+    }
+
+    #[test]
+    fn type_error_declared_input() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky
+            .eval(": Inc ( s:string -- n ) 1 + ;", &mut output)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:27: + cannot take a string and a number"
+        );
+    }
+
+    #[test]
+    fn type_error_declared_output() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky
+            .eval(": Half ( n -- x:string ) S>F 2 / ;", &mut output)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:1: Half leaves a float as x:string, but it is declared as a string"
+        );
+    }
+
+    #[test]
+    fn type_error_word_input() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky
+            .eval(": Truncate ( x:float -- n ) F>S ;\n1 Truncate", &mut output)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:2:3: Truncate expects a float, got a number"
+        );
+    }
+
+    #[test]
+    fn declared_types() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.eval(": Truncate ( x:float -- n:number ) F>S ; : Greet ( s:string -- ) PUTS ; 2.5 Truncate . \"!\" Greet", &mut output).unwrap();
+
+        match String::from_utf8(output) {
+            Ok(v) => assert!(v == "2!"),
+            Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        }
+    }
+
+    #[test]
+    fn unknown_type_in_stack_effect() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let error = stacky
+            .eval(": Nothing ( x:text -- ) DROP ;", &mut output)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:1: Unknown type text of x:text"
+        );
+    }
+
+    /// the asts of the top level nodes of a program optimized at a level
+    fn optimized(stacky: &mut Stacky, level: u8, input: &str) -> Vec<Ast> {
+        stacky.set_opt_level(level);
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        match stacky.optimize(ast) {
+            Ast::Expressions(nodes) => nodes.into_iter().map(|node| node.ast).collect(),
+            ast => panic!("Expected expressions, got {:?}", ast),
+        }
+    }
+
+    /// runs a program optimized at a level and returns its output or error
+    fn run_optimized(level: u8, input: &str) -> Result<String, String> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_opt_level(level);
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        let ast = stacky.optimize(ast);
+        stacky
+            .try_run(&ast, &mut output)
+            .map_err(|e| e.to_string())?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn optimizer_folds_constants() {
+        let mut stacky = Stacky::new();
+        assert_eq!(
+            optimized(&mut stacky, 1, "2 3 + 4 * . 1.5 FROUND 2 3 < ."),
+            vec![
+                Ast::Number(20),
+                Ast::Operation(Token::Emit),
+                Ast::Float(2.0),
+                Ast::Number(1),
+                Ast::Operation(Token::Emit),
+            ]
+        );
+        assert_eq!(run_optimized(1, "2 3 + 4 * ."), Ok("20".to_string()));
+    }
+
+    #[test]
+    fn optimizer_keeps_errors() {
+        for input in ["9223372036854775807 1 + .", "1 0 / .", "1 0 % ."] {
+            assert_eq!(run_optimized(1, input), run_optimized(0, input));
+            assert!(run_optimized(1, input).is_err());
+        }
+    }
+
+    #[test]
+    fn optimizer_removes_dead_branches() {
+        let mut stacky = Stacky::new();
+        assert_eq!(
+            optimized(&mut stacky, 1, "1 2 < IF 3 ELSE 4 THEN 5 + ."),
+            vec![Ast::Number(8), Ast::Operation(Token::Emit)]
+        );
+    }
+
+    #[test]
+    fn optimizer_inlines_words() {
+        let mut stacky = Stacky::new();
+        assert_eq!(
+            optimized(&mut stacky, 2, ": Square DUP * ; 3 Square ."),
+            vec![
+                Ast::Number(3),
+                Ast::Operation(Token::Dup),
+                Ast::Operation(Token::Mul),
+                Ast::Operation(Token::Emit),
+            ]
+        );
+        assert_eq!(
+            run_optimized(2, ": Square DUP * ; 3 Square ."),
+            Ok("9".to_string())
+        );
+    }
+
+    #[test]
+    fn optimizer_keeps_recursive_words() {
+        let input = ": Down DUP . DUP 0 > IF 1 - Down ELSE DROP THEN ; 3 Down";
+        assert_eq!(run_optimized(2, input), Ok("3210".to_string()));
+        let mut stacky = Stacky::new();
+        assert_eq!(optimized(&mut stacky, 2, input).len(), 3);
+    }
+
+    #[test]
+    fn optimizer_keeps_late_and_redefined_words() {
+        assert_eq!(
+            run_optimized(2, "Late : Late 1 ;"),
+            run_optimized(0, "Late : Late 1 ;")
+        );
+        assert_eq!(
+            run_optimized(2, ": A 1 ; : B A . ; : A 2 ; B"),
+            Ok("2".to_string())
+        );
+    }
+
+    #[test]
+    fn optimizer_level_zero() {
+        let input = ": Square DUP * ; 2 3 + Square .";
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        assert_eq!(stacky.optimize(ast.clone()), ast);
+    }
+
+    /// runs a program under the debugger with the commands and returns what the debugger wrote
+    fn debug(input: &str, breakpoints: &[&str], commands: &str) -> (String, Result<(), String>) {
+        let mut output: Vec<u8> = Vec::new();
+        let mut log: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        let mut debugger = Debugger::new(commands.as_bytes(), &mut log);
+        for breakpoint in breakpoints {
+            debugger.add_breakpoint(breakpoint);
+        }
+        let result = stacky
+            .try_run_with(&ast, &mut io::empty(), &mut output, &mut debugger)
+            .map_err(|e| e.to_string());
+        (String::from_utf8(log).unwrap(), result)
+    }
+
+    /// the lines the debugger stopped at
+    fn stops(log: &str) -> Vec<&str> {
+        log.lines()
+            .filter(|line| line.starts_with("<input>") || line.starts_with("(debug) <input>"))
+            .map(|line| line.trim_start_matches("(debug) "))
+            .collect()
+    }
+
+    #[test]
+    fn debugger_steps_into_words() {
+        let (log, result) = debug(": Square DUP * ; 3 Square .", &[], "n\nn\ns\ns\nc\n");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            stops(&log),
+            vec![
+                "<input>:1:1 : Square",
+                "<input>:1:18 3",
+                "<input>:1:20 Square",
+                "<input>:1:10 DUP in Square",
+                "<input>:1:14 * in Square",
+            ]
+        );
+        assert!(log.contains("  stack: 3 3\n"));
+    }
+
+    #[test]
+    fn debugger_steps_over_and_out_of_words() {
+        let input = ": Square DUP * ;\n3 Square\nSquare .";
+        let (log, _) = debug(input, &[], "n\nn\nn\n");
+        assert_eq!(
+            stops(&log),
+            vec![
+                "<input>:1:1 : Square",
+                "<input>:2:1 3",
+                "<input>:2:3 Square",
+                "<input>:3:1 Square",
+            ]
+        );
+
+        let (log, _) = debug(input, &["Square"], "c\ns\no\n");
+        assert_eq!(
+            stops(&log),
+            vec![
+                "<input>:1:1 : Square",
+                "<input>:2:3 Square",
+                "<input>:1:10 DUP in Square",
+                "<input>:3:1 Square",
+            ]
+        );
+    }
+
+    #[test]
+    fn debugger_breaks_at_lines() {
+        let input = "2 -> X\n3 0 DO\nI . LOOP";
+        let (log, result) = debug(input, &["3"], "c\nc\nd 3\nc\n");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            stops(&log),
+            vec!["<input>:1:1 2", "<input>:3:1 I", "<input>:3:1 I"]
+        );
+        assert!(log.contains("  loops: I=1/3\n  variables: X=2\n"));
+    }
+
+    #[test]
+    fn debugger_quits() {
+        let (_, result) = debug("1 2 + .", &[], "s\nq\n");
+        assert_eq!(
+            result,
+            Err("<input>:1:3: Stopped by the debugger".to_string())
+        );
+    }
+
+    #[test]
+    fn debugger_detaches_without_commands() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex("1 2 + .");
+        let ast = stacky.parse(&tokens);
+        let mut debugger = Debugger::new("".as_bytes(), io::sink());
+        stacky
+            .try_run_with(&ast, &mut io::empty(), &mut output, &mut debugger)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "3");
+    }
+
+    /// runs a program with a tracer for the words and returns the trace
+    fn trace(input: &str, words: &[&str]) -> (String, Result<(), String>) {
+        let mut output: Vec<u8> = Vec::new();
+        let mut log: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        let mut tracer = Tracer::new(&mut log);
+        for word in words {
+            tracer.add_word(*word);
+        }
+        let result = stacky
+            .try_run_with(&ast, &mut io::empty(), &mut output, &mut tracer)
+            .map_err(|e| e.to_string());
+        (String::from_utf8(log).unwrap(), result)
+    }
+
+    #[test]
+    fn trace_nodes() {
+        let (log, result) = trace(": Square DUP * ;\n3 Square .", &[]);
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            log,
+            "<input>:1:1 : Square ( -- )\n\
+             <input>:2:1 3 ( -- 3 )\n\
+             <input>:1:10   DUP ( 3 -- 3 3 )\n\
+             <input>:1:14   * ( 3 3 -- 9 )\n\
+             <input>:2:3 Square ( 3 -- 9 )\n\
+             <input>:2:10 . ( 9 -- )\n"
+        );
+    }
+
+    #[test]
+    fn trace_words() {
+        let (log, _) = trace(
+            ": Square DUP * ; : Cube DUP Square * ;\n2 Cube 3 Square 2DROP",
+            &["Square"],
+        );
+        assert_eq!(
+            log,
+            "<input>:1:10     DUP ( 2 2 -- 2 2 2 )\n\
+             <input>:1:14     * ( 2 2 2 -- 2 4 )\n\
+             <input>:1:29   Square ( 2 2 -- 2 4 )\n\
+             <input>:1:10   DUP ( 8 3 -- 8 3 3 )\n\
+             <input>:1:14   * ( 8 3 3 -- 8 9 )\n\
+             <input>:2:10 Square ( 8 3 -- 8 9 )\n"
+        );
+    }
+
+    #[test]
+    fn trace_errors() {
+        let (log, result) = trace("1 0 /", &[]);
+        assert_eq!(
+            result,
+            Err("<input>:1:5: Cannot divide by zero".to_string())
+        );
+        assert!(log.ends_with("<input>:1:5 / ( 1 0 -- ) Cannot divide by zero\n"));
+    }
+
+    /// runs a program with a profiler
+    /// records what an event shows through its public accessors
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl Hook for Recorder {
+        fn before(&mut self, event: &Event) -> Result<(), StackyError> {
+            if event.code() == "." {
+                let stack: Vec<String> = event.stack().iter().map(Value::to_string).collect();
+                self.events.push(format!(
+                    "{}:{} {:?} {:?} {:?} {:?} {}",
+                    event.span().line,
+                    event.span().column,
+                    event.calls(),
+                    stack,
+                    event.loops(),
+                    event.variables(),
+                    event.depth()
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hooks_see_events_through_accessors() {
+        let dir = source_dir(
+            "event",
+            &[("main.f", "5 -> x\n: Show 2 0 DO I . LOOP ;\nShow")],
+        );
+        let mut engine = Engine::new();
+        let program = engine.load_file(&dir.join("main.f")).unwrap();
+        let mut recorder = Recorder::default();
+        let mut output = vec![];
+        engine
+            .run_with(&program, &mut io::empty(), &mut output, &mut recorder)
+            .unwrap();
+        assert_eq!(
+            recorder.events,
+            [
+                "2:17 [\"Show\"] [\"0\"] [(0, 2)] [(\"x\", Number(5))] 1",
+                "2:17 [\"Show\"] [\"1\"] [(1, 2)] [(\"x\", Number(5))] 1",
+            ]
+        );
+    }
+
+    fn profile(input: &str) -> Profiler {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let tokens = stacky.lex(input);
+        let ast = stacky.parse(&tokens);
+        let mut profiler = Profiler::new();
+        stacky
+            .try_run_with(&ast, &mut io::empty(), &mut output, &mut profiler)
+            .unwrap();
+        profiler
+    }
+
+    #[test]
+    fn profile_counts_calls_and_lines() {
+        let profiler = profile(
+            ": Square DUP * ;\n: Cube DUP Square * ;\n3 Cube DROP\n4 0 DO I Square DROP LOOP",
+        );
+        assert_eq!(profiler.word("Square").unwrap().calls, 5);
+        assert_eq!(profiler.word("Cube").unwrap().calls, 1);
+        assert!(profiler.word("DROP").is_none());
+        assert_eq!(profiler.hits("<input>", 1), 6);
+        assert_eq!(profiler.hits("<input>", 3), 1);
+        assert_eq!(profiler.hits("<input>", 4), 4);
+
+        let cube = profiler.word("Cube").unwrap();
+        assert!(cube.exclusive <= cube.inclusive);
+    }
+
+    #[test]
+    fn profile_recursive_words() {
+        let profiler = profile(": Down DUP 0 > IF 1 - Down THEN ; 5 Down DROP");
+        let down = profiler.word("Down").unwrap();
+        assert_eq!(down.calls, 6);
+        assert!(down.exclusive <= down.inclusive);
+    }
+
+    #[test]
+    fn profile_report_and_collapsed_stacks() {
+        let profiler = profile(": Square DUP * ; : Cube DUP Square * ; 3 Cube 2 Square 2DROP");
+
+        let mut report: Vec<u8> = Vec::new();
+        profiler.report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].starts_with("word"));
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with("Square ")
+                    && line.split_whitespace().nth(1) == Some("2"))
+        );
+        assert!(report.contains("\nline "));
+
+        let mut collapsed: Vec<u8> = Vec::new();
+        profiler.collapsed(&mut collapsed).unwrap();
+        let stacks: Vec<String> = String::from_utf8(collapsed)
+            .unwrap()
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        assert_eq!(
+            stacks,
+            vec!["main", "main;Cube", "main;Cube;Square", "main;Square"]
+        );
+    }
+
+    /// runs a program with one limit set and returns the limit it exceeded
+    fn exceeded_limit(setting: &str, input: &str) -> Option<Limit> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let mut limits = Limits::default();
+        limits.set(setting).unwrap();
+        stacky.set_limits(limits);
+        match stacky.eval(input, &mut output) {
+            Err(error) => match error.root() {
+                StackyError::LimitExceeded { limit, .. } => Some(*limit),
+                error => panic!("Expected a limit error, got {}", error),
+            },
+            Ok(()) => None,
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let cases = [
+            ("steps=100", "1000 0 DO LOOP", Limit::Steps),
+            (
+                "stack=10",
+                ": Fill DUP 0 > IF DUP 1 - Fill THEN ; 20 Fill",
+                Limit::Stack,
+            ),
+            (
+                "loops=2",
+                "1 0 DO 1 0 DO 1 0 DO LOOP LOOP LOOP",
+                Limit::Loops,
+            ),
+            (
+                "calls=50",
+                ": Down DUP 0 > IF 1 - Down THEN ; 100 Down",
+                Limit::Calls,
+            ),
+            (
+                "item-size=100",
+                "\"ab\" 10 0 DO DUP + LOOP",
+                Limit::ItemSize,
+            ),
+            (
+                "item-size=100",
+                "1 2 3 4 5 6 7 8 9 10 11 12 13 13 >ARRAY",
+                Limit::ItemSize,
+            ),
+            (
+                "item-size=100",
+                "1 2 3 4 5 6 7 8 9 10 11 12 13 14 7 >MAP",
+                Limit::ItemSize,
+            ),
+            ("output=5", "10 0 DO I . LOOP", Limit::Output),
+        ];
+        for (setting, input, limit) in cases {
+            assert_eq!(exceeded_limit(setting, input), Some(limit), "{}", setting);
+        }
+
+        assert_eq!(exceeded_limit("steps=100", "10 0 DO LOOP"), None);
+        assert_eq!(exceeded_limit("loops=2", "1 0 DO 1 0 DO LOOP LOOP"), None);
+        assert_eq!(
+            exceeded_limit("item-size=100", "1 2 3 4 5 6 7 8 9 10 10 >ARRAY -> x"),
+            None
+        );
+        assert_eq!(
+            exceeded_limit("calls=50", ": Down DUP 0 > IF 1 - Down THEN ; 10 Down"),
+            None
+        );
+    }
+
+    #[test]
+    fn calls_are_always_limited() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_check(false);
+        let error = stacky.eval(": F F ; F", &mut output).unwrap_err();
+        assert_eq!(
+            error.root(),
+            &StackyError::LimitExceeded {
+                limit: Limit::Calls,
+                max: MAX_CALLS as u64
+            }
+        );
+
+        // a higher limit does not raise the built-in one
+        let mut limits = Limits::default();
+        limits.set(&format!("calls={}", MAX_CALLS * 10)).unwrap();
+        stacky.set_limits(limits);
+        let error = stacky.eval(": G 1 IF G THEN ; G", &mut output).unwrap_err();
+        assert_eq!(
+            error.root(),
+            &StackyError::LimitExceeded {
+                limit: Limit::Calls,
+                max: MAX_CALLS as u64
+            }
+        );
+    }
+
+    #[test]
+    fn stack_limit_counts_the_return_stack() {
+        assert_eq!(
+            exceeded_limit("stack=3", "1 >R 2 >R 3 >R 4 R> R> R> DROP DROP DROP DROP"),
+            Some(Limit::Stack)
+        );
+        assert_eq!(
+            exceeded_limit("stack=3", "1 >R 2 >R 3 R> R> DROP DROP DROP"),
+            None
+        );
+    }
+
+    #[test]
+    fn item_size_counts_items_below_the_top() {
+        let mut engine = Engine::new();
+        let mut limits = Limits::default();
+        limits.set("item-size=100").unwrap();
+        engine.set_limits(limits);
+        engine.register_fn("Big", 0, |stack| {
+            stack.push("x".repeat(1000));
+            stack.push(1);
+            Ok(())
+        });
+        let error = engine.eval("Big DROP -> x").unwrap_err();
+        assert!(
+            matches!(
+                error.root(),
+                StackyError::LimitExceeded {
+                    limit: Limit::ItemSize,
+                    ..
+                }
+            ),
+            "{}",
+            error
+        );
+        assert_eq!(engine.eval("Big DROP DROP 1 -> x x ."), Err(error));
+        assert_eq!(engine.eval("1 ."), Ok("1".to_string()));
+    }
+
+    #[test]
+    fn limit_errors() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let mut limits = Limits::default();
+        limits.set("output=5").unwrap();
+        stacky.set_limits(limits);
+        let error = stacky.eval("10 0 DO I . LOOP", &mut output).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "<input>:1:11: Output limit of 5 bytes exceeded"
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), "01234");
+
+        assert_eq!(
+            Limits::default().set("bogus=1"),
+            Err("Unknown limit bogus".to_string())
+        );
+        assert_eq!(
+            Limits::default().set("steps"),
+            Err("Expected name=value, got steps".to_string())
+        );
+    }
+
+    #[test]
+    fn cancel_from_another_thread() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        let handle = stacky.cancel_handle();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.cancel();
+        });
+        let error = stacky
+            .eval("9223372036854775807 0 DO LOOP", &mut output)
+            .unwrap_err();
+        canceller.join().unwrap();
+        assert_eq!(error.root(), &StackyError::Cancelled);
+
+        // the cancel is used up, the next run is not stopped
+        stacky.eval("1 .", &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "1");
+    }
+
+    #[test]
+    fn cancel_after_a_run_does_not_stop_the_next() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.eval("1 .", &mut output).unwrap();
+        stacky.cancel_handle().cancel();
+        stacky.eval("2 .", &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "12");
+    }
+
+    /// runs a program that reads the input and returns its output
+    fn with_input(program: &str, input: &str) -> String {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky
+            .eval_with_input(program, &mut input.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn key() {
+        assert_eq!(with_input("KEY . KEY . KEY . KEY .", "aé\n"), "9723310-1");
+    }
+
+    #[test]
+    fn accept() {
+        assert_eq!(
+            with_input(
+                "10 ACCEPT PUTS 3 ACCEPT PUTS 3 ACCEPT PUTS",
+                "hello\nworld\n"
+            ),
+            "hellowor"
+        );
+    }
+
+    #[test]
+    fn read_line() {
+        assert_eq!(
+            with_input(
+                "READ-LINE . PUTS READ-LINE . PUTS READ-LINE . PUTS",
+                "one\r\ntwo"
+            ),
+            "1one1two0"
+        );
+    }
+
+    #[test]
+    fn read_lines_as_a_filter() {
+        let program = ": Upper ( -- ) READ-LINE IF \"> \" PUTS PUTS Upper ELSE DROP THEN ; Upper";
+        assert_eq!(with_input(program, "a\nb\n"), "> a> b");
+    }
+
+    #[test]
+    fn number_in() {
+        assert_eq!(
+            with_input("NUMBER-IN . . NUMBER-IN . . NUMBER-IN . .", " 42 \nabc\n"),
+            "1420000"
+        );
+        assert_eq!(with_input("HEX NUMBER-IN . .", "ff\n"), "1FF");
+    }
+
+    #[test]
+    fn no_input() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.eval("KEY . READ-LINE . PUTS", &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "-10");
+    }
+
+    /// runs a program and returns its output or the error
+    fn run_files(program: &str, file_access: bool) -> Result<String, String> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut stacky = Stacky::new();
+        stacky.set_file_access(file_access);
+        stacky
+            .eval(program, &mut output)
+            .map_err(|e| e.to_string())?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn write_and_read_files() {
+        let dir = source_dir("files", &[]);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lines.txt").display().to_string();
+        let program = format!(
+            "\"{path}\" W/O CREATE-FILE . \
+             \"one\ntwo\n\" OVER WRITE-FILE . CLOSE-FILE . \
+             \"{path}\" R/O OPEN-FILE . \
+             DUP FILE-SIZE . . \
+             DUP READ-LINE . . PUTS \
+             DUP READ-LINE . . PUTS \
+             DUP READ-LINE . . PUTS \
+             CLOSE-FILE . \
+             \"{path}\" R/W OPEN-FILE . \
+             2 OVER READ-FILE . PUTS \"!\" OVER WRITE-FILE . CLOSE-FILE . \
+             \"{path}\" R/O OPEN-FILE . 100 OVER READ-FILE . PUTS CLOSE-FILE ."
+        );
+        assert_eq!(
+            run_files(&program, true).unwrap(),
+            "00000801one01two00000on0000on!\ntwo\n0"
+        );
+        let program = format!("\"{path}\" DELETE-FILE . \"{path}\" R/O OPEN-FILE . DROP");
+        assert_eq!(run_files(&program, true).unwrap(), "0-2");
+    }
+
+    #[test]
+    fn file_errors() {
+        let dir = source_dir("file_errors", &[("file.txt", "text")]);
+        let path = dir.join("file.txt").display().to_string();
+        let missing = dir.join("missing.txt").display().to_string();
+        assert_eq!(
+            run_files(&format!("\"{missing}\" R/O OPEN-FILE . DROP"), true).unwrap(),
+            "-2"
+        );
+        assert_eq!(
+            run_files(&format!("\"{missing}\" DELETE-FILE ."), true).unwrap(),
+            "-2"
+        );
+        assert_eq!(
+            run_files(&format!("\"{path}\" 7 OPEN-FILE . DROP"), true).unwrap(),
+            "-5"
+        );
+        let program = format!(
+            "\"{path}\" R/O OPEN-FILE DROP DUP CLOSE-FILE . DUP CLOSE-FILE . \
+             DUP FILE-SIZE . . 4 OVER READ-FILE . PUTS"
+        );
+        assert_eq!(run_files(&program, true).unwrap(), "0-4-40-4");
+    }
+
+    #[test]
+    fn file_access_disabled() {
+        let path = source_dir("file_access", &[("file.txt", "text")])
+            .join("file.txt")
+            .display()
+            .to_string();
+        assert!(run_files(&format!("\"{path}\" R/O OPEN-FILE"), false)
+            .unwrap_err()
+            .ends_with(": OPEN-FILE needs file access, which is disabled"));
+        assert!(run_files(&format!("\"{path}\" DELETE-FILE"), false)
+            .unwrap_err()
+            .ends_with(": DELETE-FILE needs file access, which is disabled"));
+        assert!(fs::metadata(&path).is_ok());
+    }
+
+    #[test]
+    fn include_needs_file_access() {
+        let path = source_dir("include_access", &[("square.f", ": Sq DUP * ;")]).join("square.f");
+        for word in ["INCLUDE", "REQUIRE"] {
+            let program = format!("{} \"{}\" 7 Sq .", word, path.display());
+            assert_eq!(
+                run_files(&program, false).unwrap_err(),
+                format!("<input>:1:1: {} needs file access, which is disabled", word)
+            );
+            assert_eq!(run_files(&program, true).unwrap(), "49");
+        }
+    }
+
+    #[test]
+    fn file_types_are_checked() {
+        assert_eq!(
+            run_files("1 R/O OPEN-FILE", true).unwrap_err(),
+            "<input>:1:7: OPEN-FILE expects a string, got a number"
+        );
+        assert_eq!(
+            run_files(
+                "\"x\" R/O OPEN-FILE DROP 1 OVER READ-FILE DROP WRITE-FILE",
+                true
+            )
+            .unwrap_err(),
+            "<input>:1:46: WRITE-FILE expects a file, got a string"
+        );
+    }
+
+    #[test]
+    fn engine_eval() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.eval("1 2 + .").unwrap(), "3");
+        engine.eval(": Twice DUP + ;").unwrap();
+        assert_eq!(engine.eval("21 Twice .").unwrap(), "42");
+        assert_eq!(
+            engine.eval("DROP").unwrap_err().to_string(),
+            "<input>:1:1: Stack underflow: DROP requires 1 items, 0 present"
+        );
+    }
+
+    #[test]
+    fn engine_push_and_pop() {
+        let mut engine = Engine::new();
+        engine.push(2);
+        engine.push(1.5);
+        engine.push("text");
+        assert_eq!(engine.depth(), 3);
+        assert_eq!(
+            engine.stack(),
+            vec![Value::Number(2), Value::Float(1.5), Value::from("text")]
+        );
+        assert_eq!(
+            engine.pop_number().unwrap_err().to_string(),
+            "Expected a number, got \"text\""
+        );
+        assert_eq!(engine.pop_string().unwrap(), "text");
+        assert_eq!(engine.pop_float().unwrap(), 1.5);
+        assert_eq!(engine.pop().unwrap(), Value::Number(2));
+        assert_eq!(
+            engine.pop().unwrap_err().to_string(),
+            "Stack underflow: pop requires 1 items, 0 present"
+        );
+
+        // file handles have no value
+        engine.eval("\"missing\" R/O OPEN-FILE DROP").unwrap();
+        assert!(engine.pop().is_err());
+        assert_eq!(engine.depth(), 1);
+    }
+
+    #[test]
+    fn engine_variables() {
+        let mut engine = Engine::new();
+        engine.set_variable("X", 5);
+        assert_eq!(engine.eval("@ X 1 + .").unwrap(), "6");
+        engine.eval("\"seven\" -> Y : Z ;").unwrap();
+        assert_eq!(engine.variable("Y"), Some(Value::from("seven")));
+        assert_eq!(engine.variable("Z"), None);
+        assert_eq!(engine.variable("Missing"), None);
+    }
+
+    #[test]
+    fn engine_calls_words() {
+        let mut engine = Engine::new();
+        engine
+            .eval(": Square ( n -- n ) DUP * ; : Hello \"hello\" PUTS ;")
+            .unwrap();
+        engine.push(7);
+        assert_eq!(engine.call("Square").unwrap(), "");
+        assert_eq!(engine.pop_number().unwrap(), 49);
+        assert_eq!(engine.call("Hello").unwrap(), "hello");
+        assert_eq!(
+            engine.call("Missing").unwrap_err().to_string(),
+            "<call>:1:1: Word not found"
+        );
+    }
+
+    #[test]
+    fn engine_reset() {
+        let mut engine = Engine::new();
+        engine.set_file_access(false);
+        engine.eval(": Answer 42 ; 1 -> X 1 2 3").unwrap();
+        engine.reset();
+        assert_eq!(engine.depth(), 0);
+        assert_eq!(engine.variable("X"), None);
+        assert!(engine.call("Answer").is_err());
+        assert_eq!(engine.eval("1 2 OVER . . .").unwrap(), "121");
+        assert!(engine
+            .eval("\"x\" DELETE-FILE")
+            .unwrap_err()
+            .to_string()
+            .ends_with("DELETE-FILE needs file access, which is disabled"));
+    }
+
+    /// counts up from a start, a native word with state
+    struct Counter(i64);
+
+    impl NativeWord for Counter {
+        fn arity(&self) -> usize {
+            0
+        }
+
+        fn call(&mut self, stack: &mut Stack) -> Result<(), StackyError> {
+            self.0 += 1;
+            stack.push(self.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn native_words() {
+        let mut engine = Engine::new();
+        engine.register_fn("Greeting", 1, |stack| {
+            let name = stack.pop_string()?;
+            stack.push(format!("Hello, {}!", name));
+            Ok(())
+        });
+        engine.register_fn("Hypot", 2, |stack| {
+            let b = stack.pop_float()?;
+            let a = stack.pop_float()?;
+            stack.push(a.hypot(b));
+            Ok(())
+        });
+        engine.register("Next", Counter(10));
+        assert_eq!(
+            engine.eval("\"stacky\" Greeting PUTS").unwrap(),
+            "Hello, stacky!"
+        );
+        assert_eq!(engine.eval("3.0 4.0 Hypot .").unwrap(), "5.0");
+        assert_eq!(engine.eval(": Two Next Next + ; Two .").unwrap(), "23");
+        engine.call("Next").unwrap();
+        assert_eq!(engine.pop_number().unwrap(), 13);
+    }
+
+    #[test]
+    fn native_word_errors() {
+        let mut engine = Engine::new();
+        engine.register_fn("Lookup", 1, |stack| {
+            let key = stack.pop_string()?;
+            match key.as_str() {
+                "answer" => stack.push(42),
+                _ => return Err(StackyError::runtime(format!("No entry for {}", key))),
+            }
+            Ok(())
+        });
+        assert_eq!(engine.eval("\"answer\" Lookup .").unwrap(), "42");
+        assert_eq!(
+            engine.eval("Lookup").unwrap_err().to_string(),
+            "<input>:1:1: Stack underflow: Lookup requires 1 items, 0 present"
+        );
+        assert_eq!(
+            engine.eval("\"question\" Lookup").unwrap_err().to_string(),
+            "<input>:1:12: No entry for question"
+        );
+        assert_eq!(
+            engine.eval("1 Lookup").unwrap_err().to_string(),
+            "<input>:1:3: Expected a string, got 1"
+        );
+        assert_eq!(engine.pop_number().unwrap(), 1);
+    }
+
+    #[test]
+    fn native_words_survive_a_reset() {
+        let mut engine = Engine::new();
+        engine.register("Next", Counter(0));
+        engine.eval(": Next 100 ;").unwrap();
+        assert_eq!(engine.eval("Next .").unwrap(), "100");
+        engine.reset();
+        assert_eq!(engine.eval("Next .").unwrap(), "1");
+    }
+
+    #[test]
+    fn arrays_and_maps_with_huge_counts() {
+        assert_eq!(
+            Engine::new()
+                .eval("1 9223372036854775807 >MAP")
+                .unwrap_err()
+                .to_string(),
+            "<input>:1:23: Stack underflow: >MAP requires 18446744073709551615 items, 2 present"
+        );
+        assert_eq!(
+            Engine::new()
+                .eval("1 100000000000 >ARRAY")
+                .unwrap_err()
+                .to_string(),
+            "<input>:1:16: Stack underflow: >ARRAY requires 100000000001 items, 2 present"
+        );
+    }
+
+    #[test]
+    fn arrays_and_maps() {
+        let mut engine = Engine::new();
+        assert_eq!(
+            engine
+                .eval("1 \"two\" 3.0 3 >ARRAY DUP . ARRAY> . . . .")
+                .unwrap(),
+            "[1, \"two\", 3.0]33.0two1"
+        );
+        assert_eq!(
+            engine
+                .eval("\"a\" 1 \"b\" 2 \"a\" 3 3 >MAP DUP . DUP \"a\" GET . MAP> . . . . .")
+                .unwrap(),
+            "{\"a\": 3, \"b\": 2}322b3a"
+        );
+        assert_eq!(engine.eval("0 >ARRAY 0 >MAP . .").unwrap(), "{}[]");
+        assert_eq!(engine.eval("5 6 2 >ARRAY 1 GET .").unwrap(), "6");
+        assert_eq!(
+            engine.eval("5 6 2 >ARRAY 2 GET").unwrap_err().to_string(),
+            "<input>:1:16: Index 2 is out of range for an array of 2 items"
+        );
+        assert_eq!(
+            engine.eval("0 >MAP \"x\" GET").unwrap_err().to_string(),
+            "<input>:1:12: No key \"x\" in the map"
+        );
+        assert_eq!(
+            engine.eval("1 2 >ARRAY").unwrap_err().to_string(),
+            "<input>:1:5: Stack underflow: >ARRAY requires 3 items, 2 present"
+        );
+        assert_eq!(
+            engine.eval("1 ARRAY>").unwrap_err().to_string(),
+            "<input>:1:3: ARRAY> expects an array, got a number"
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Status {
+        Open,
+        Held(String),
+        Moved { to: u32 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u64,
+        items: Vec<String>,
+        total: f64,
+        express: bool,
+        note: Option<String>,
+        statuses: Vec<Status>,
+        pair: (i8, char),
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let order = Order {
+            id: u64::MAX,
+            items: vec!["tea".to_string(), "cake".to_string()],
+            total: 7.5,
+            express: true,
+            note: None,
+            statuses: vec![
+                Status::Open,
+                Status::Held("payment".to_string()),
+                Status::Moved { to: 3 },
+            ],
+            pair: (-1, 'x'),
+        };
+        let mut engine = Engine::new();
+        engine.set_overflow_policy(OverflowPolicy::BigInt);
+        engine.push_serialized(&order).unwrap();
+        assert_eq!(engine.depth(), 1);
+        assert_eq!(engine.eval("DUP \"items\" GET 1 GET PUTS").unwrap(), "cake");
+        assert_eq!(engine.pop_deserialized::<Order>().unwrap(), order);
+    }
+
+    #[test]
+    fn serde_options_round_trip() {
+        fn round_trip<T>(value: T)
+        where
+            T: Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            let converted = from_value::<T>(to_value(&value).unwrap()).unwrap();
+            assert_eq!(converted, value);
+        }
+        round_trip(Some(Vec::<i64>::new()));
+        round_trip(None::<Vec<i64>>);
+        round_trip(Some(()));
+        round_trip(None::<()>);
+        round_trip(Some(Some(7)));
+        round_trip(Some(None::<i64>));
+        round_trip(None::<Option<i64>>);
+        assert_eq!(
+            to_value(&Some("tea")).unwrap(),
+            Value::Array(vec![Value::from("tea")])
+        );
+        assert!(from_value::<Option<i64>>(Value::Number(1)).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        host: String,
+        port: u16,
+        tags: Vec<String>,
+        debug: bool,
+    }
+
+    #[test]
+    fn serde_results_of_scripts() {
+        let mut engine = Engine::new();
+        engine
+            .eval(
+                "\"host\" \"localhost\" \"port\" 8000 80 + \"debug\" 1 2 < \
+                 \"tags\" \"a\" \"b\" 2 >ARRAY 4 >MAP",
+            )
+            .unwrap();
+        assert_eq!(
+            engine.pop_deserialized::<Config>().unwrap(),
+            Config {
+                host: "localhost".to_string(),
+                port: 8080,
+                tags: vec!["a".to_string(), "b".to_string()],
+                debug: true,
+            }
+        );
+
+        engine.eval("\"host\" \"x\" 1 >MAP").unwrap();
+        assert_eq!(
+            engine.pop_deserialized::<Config>().unwrap_err().to_string(),
+            "missing field `port`"
+        );
+        assert_eq!(engine.depth(), 1);
+        engine.eval("DROP 300").unwrap();
+        assert!(engine.pop_deserialized::<u8>().is_err());
+        assert_eq!(engine.pop_deserialized::<u16>().unwrap(), 300);
+    }
+
+    #[test]
+    fn native_words_take_rust_values() {
+        let mut engine = Engine::new();
+        engine.register_fn("Total", 1, |stack| {
+            let prices: Vec<f64> = stack.pop_deserialized()?;
+            stack.push_serialized(&prices.iter().sum::<f64>())
+        });
+        assert_eq!(engine.eval("1.5 2.5 2 >ARRAY Total .").unwrap(), "4.0");
+    }
+
+    #[test]
+    fn c_api_reports_panics_as_errors() {
+        let error = catch(|| -> Result<(), StackyError> { panic!("boom") }).unwrap_err();
+        assert_eq!(error.to_string(), "Internal error: boom");
+
+        let source = std::ffi::CString::new("1 9223372036854775807 PICK").unwrap();
+        unsafe {
+            let engine = stacky_engine_new();
+            assert_eq!(stacky_eval(engine, source.as_ptr()), -1);
+            let error = std::ffi::CStr::from_ptr(stacky_last_error(engine));
+            assert_eq!(
+                error.to_str().unwrap(),
+                "<input>:1:23: Stack underflow: PICK requires 9223372036854775809 items, 2 present"
+            );
+            stacky_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn plugin_needs_a_loadable_library() {
+        let mut engine = Engine::new();
+        assert!(engine
+            .eval("\"no/such/plugin.so\" PLUGIN")
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot load plugin no/such/plugin.so"));
+        assert!(engine
+            .eval("1 PLUGIN")
+            .unwrap_err()
+            .to_string()
+            .contains("PLUGIN expects a string, got a number"));
+    }
+
+    #[test]
+    fn lossless_lexing_keeps_trivia() {
+        let source = ": Sq ( n -- n ) DUP *  ; \\ square\n( note ) 3 Sq .\n";
+        let lexemes = Lexer::new(std::sync::Arc::from("<input>"))
+            .lex_lossless(source)
+            .unwrap();
+        let text: String = lexemes.iter().map(|lexeme| lexeme.text.as_str()).collect();
+        assert_eq!(text, source);
+
+        let kinds: Vec<&LexemeKind> = lexemes.iter().map(|lexeme| &lexeme.kind).collect();
+        assert_eq!(
+            kinds[4],
+            &LexemeKind::Token(Token::StackEffect(" n -- n ".into()))
+        );
+        let comments: Vec<(&str, usize, usize)> = lexemes
+            .iter()
+            .filter(|lexeme| !matches!(lexeme.kind, LexemeKind::Token(_) | LexemeKind::Whitespace))
+            .map(|lexeme| (lexeme.text.as_str(), lexeme.span.line, lexeme.span.column))
+            .collect();
+        assert_eq!(comments, vec![("\\ square", 1, 26), ("( note )", 2, 1)]);
+        // identifiers take the whitespace after them while lexing, it is trivia here
+        let sq = lexemes.iter().find(|lexeme| lexeme.text == "Sq").unwrap();
+        assert_eq!((sq.span.line, sq.span.column), (1, 3));
+    }
+
+    #[test]
+    fn format_indents_blocks_that_span_lines() {
+        let source = "\n\n\\ squares\n: sq   ( n -- n )  dup * ; : cube dup sq * ;   \\ cube it\n: Abs ( n -- n )\ndup 0 <   if\n-1 *\n   else ( keep it )\n THEN ;\n\n\n\n10 0 do i . loop\n: Count 5 0 DO\nI .\nLOOP ;   Count";
+        let formatted = "\\ squares\n: sq ( n -- n ) DUP * ;\n: cube DUP sq * ; \\ cube it\n: Abs ( n -- n )\n  DUP 0 < IF\n    -1 *\n  ELSE ( keep it )\n  THEN\n;\n\n10 0 DO I . LOOP\n: Count\n  5 0 DO\n    I .\n  LOOP\n;\nCount\n";
+        assert_eq!(format_source(source).unwrap(), formatted);
+        assert_eq!(format_source(formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn format_keeps_formatted_code() {
+        let prelude = include_str!("prelude.f");
+        assert_eq!(format_source(prelude).unwrap(), prelude);
+        assert_eq!(format_source("").unwrap(), "");
+    }
+
+    #[test]
+    fn format_upper_cases_keywords_that_are_not_defined() {
+        assert_eq!(
+            format_source(": dup 1 ; dup drop s>f")
+                .unwrap_err()
+                .to_string(),
+            "<input>:1:20: Expected whitespace"
+        );
+        assert_eq!(
+            format_source(": dup 1 ; dup drop 1 -> i i").unwrap(),
+            ": dup 1 ;\ndup DROP 1 -> i i\n"
+        );
+    }
+
+    /// the words sources of the syntax tree tests are made of
+    const WORDS: &[&str] = &[
+        ":",
+        ";",
+        "IF",
+        "ELSE",
+        "THEN",
+        "DO",
+        "LOOP",
+        "->",
+        "@",
+        "PRAGMA",
+        "OVERFLOW",
+        "WRAPPING",
+        "INCLUDE",
+        "\"lib.f\"",
+        "Sq",
+        "x",
+        "I",
+        "DUP",
+        "+",
+        ".",
+        "42",
+        "-7",
+        "\" a string\"",
+        "( note )",
+        "( n -- n )",
+        "\\ line\n",
+    ];
+
+    fn syntax_tree(source: &str) -> Option<Cst> {
+        let lexemes = Lexer::new(std::sync::Arc::from("<input>"))
+            .lex_lossless(source)
+            .ok()?;
+        Some(Cst::build(lexemes))
+    }
+
+    #[test]
+    fn only_programs_are_lowered() {
+        let cst = syntax_tree(": Sq DUP * ;").unwrap();
+        let Cst::Node { children, .. } = &cst else {
+            panic!("Expected a program, got {:?}", cst);
+        };
+        assert_eq!(
+            children[0].lower().unwrap_err().to_string(),
+            "Only a whole program that ends with EOF can be lowered"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn syntax_tree_keeps_any_source(source in any::<String>()) {
+            if let Some(cst) = syntax_tree(&source) {
+                prop_assert_eq!(cst.text(), source);
+            }
+        }
+
+        #[test]
+        fn syntax_tree_lowers_to_the_parsed_ast(
+            words in prop::collection::vec(
+                (prop::sample::select(WORDS), prop::sample::select(&[" ", "  ", "\n", "\t\n\n"][..])),
+                0..24,
+            )
+        ) {
+            let source: String = words.iter().map(|(word, space)| format!("{}{}", word, space)).collect();
+            let cst = syntax_tree(&source).unwrap();
+            prop_assert_eq!(cst.text(), source.as_str());
+
+            let tokens = Lexer::new(std::sync::Arc::from("<input>")).lex(&source).unwrap();
+            match (cst.lower(), parse(&tokens)) {
+                (Ok(lowered), Ok(parsed)) => prop_assert_eq!(lowered, parsed),
+                (lowered, parsed) => prop_assert_eq!(
+                    lowered.map_err(|e| e.to_string()),
+                    parsed.map_err(|e| e.to_string())
+                ),
+            }
+        }
+    }
+
+    fn lints(linter: &Linter, source: &str) -> Vec<String> {
+        linter
+            .lint("<input>", source)
+            .unwrap()
+            .iter()
+            .map(|lint| lint.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn lint_finds_suspicious_code() {
+        let source = ": Sq DUP * ;\n: Cube DUP Sq * ;\n: Sq DUP DUP * * ;\n@ x . 5 -> x @ x .\n: Show @ y . ; 3 -> y Show\n0 0 DO I . LOOP 10 0 DO I . LOOP\n1 IF ELSE 2 THEN 1 IF 2 ELSE THEN\n: dup 1 ; dup -> i @ i Cube\n";
+        assert_eq!(
+            lints(&Linter::new(), source),
+            vec![
+                "<input>:3:3: Sq is already defined at line 1 [redefined-word]",
+                "<input>:4:3: x is read before it is assigned [unassigned-variable]",
+                "<input>:6:5: DO from 0 to 0 never runs its body [empty-loop]",
+                "<input>:7:3: IF has an empty branch [empty-branch]",
+                "<input>:7:25: ELSE has an empty branch [empty-branch]",
+                "<input>:8:3: dup shadows the built-in word DUP [shadowing]",
+                "<input>:8:18: i shadows the loop variable I [shadowing]",
+            ]
+        );
+        assert_eq!(
+            lints(
+                &Linter::new(),
+                ": Sq DUP * ; : Unused 1 ; : Again Again ; 2 Sq ."
+            ),
+            vec![
+                "<input>:1:16: Unused is never used [unused-word]",
+                "<input>:1:29: Again is never used [unused-word]",
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_rules_can_be_turned_off() {
+        let source = ": Unused 1 ;\n\\ lint: allow empty-loop unused-word\n0 0 DO LOOP : Other 1 ;\n1 IF THEN ( lint: allow empty-branch )\n0 0 DO LOOP\n";
+        assert_eq!(
+            lints(&Linter::new(), source),
+            vec![
+                "<input>:1:3: Unused is never used [unused-word]",
+                "<input>:5:5: DO from 0 to 0 never runs its body [empty-loop]",
+            ]
+        );
+        let mut linter = Linter::new();
+        linter.set_rule(Rule::UnusedWord, false);
+        linter.set_rule(Rule::EmptyLoop, false);
+        assert!(lints(&linter, source).is_empty());
+
+        assert_eq!("empty-branch".parse::<Rule>(), Ok(Rule::EmptyBranch));
+        assert_eq!("typo".parse::<Rule>(), Err("Unknown rule typo".to_string()));
+        assert_eq!(
+            Linter::new()
+                .lint("<input>", ": Sq DUP")
+                .unwrap_err()
+                .to_string(),
+            "<input>:1:9: Unexpected end of input"
+        );
     }
 }
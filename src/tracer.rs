@@ -0,0 +1,74 @@
+use std::{collections::HashSet, io::Write};
+
+use crate::{
+    error::StackyError,
+    interpreter::{Event, Hook},
+    parser::Ast,
+    stack_machine::Entity,
+};
+
+/// writes a line for every node that ran, with its position, the number of running words and
+/// the stack before and after it
+///
+/// lines are written once a node is done, so the line of a word follows the lines of its body
+pub struct Tracer<W> {
+    output: W,
+    /// only nodes in these words and calls to them are traced, all nodes if it is empty
+    words: HashSet<String>,
+    /// the stack before each running node, if the node is traced
+    before: Vec<Option<Vec<Entity>>>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            words: HashSet::new(),
+            before: vec![],
+        }
+    }
+
+    pub fn add_word(&mut self, name: impl Into<String>) {
+        self.words.insert(name.into());
+    }
+
+    fn is_traced(&self, event: &Event) -> bool {
+        self.words.is_empty()
+            || event.calls.iter().any(|word| self.words.contains(word))
+            || matches!(&event.node.ast, Ast::FunctionCall(name) if self.words.contains(name))
+    }
+}
+
+/// the items of a stack, each followed by a space
+fn items(stack: &[Entity]) -> String {
+    stack.iter().map(|item| format!("{} ", item)).collect()
+}
+
+impl<W: Write> Hook for Tracer<W> {
+    fn before(&mut self, event: &Event) -> Result<(), StackyError> {
+        let before = self.is_traced(event).then(|| event.stack.to_vec());
+        self.before.push(before);
+        Ok(())
+    }
+
+    fn after(&mut self, event: &Event, error: Option<&StackyError>) -> Result<(), StackyError> {
+        let Some(Some(before)) = self.before.pop() else {
+            return Ok(());
+        };
+        write!(
+            self.output,
+            "{} {}{} ( {}-- ",
+            event.node.span,
+            "  ".repeat(event.calls.len()),
+            event.node.ast,
+            items(&before)
+        )?;
+        match error {
+            // the error is shown without the position, which is the position of the line
+            Some(StackyError::At { error, .. }) => writeln!(self.output, ") {}", error)?,
+            Some(error) => writeln!(self.output, ") {}", error)?,
+            None => writeln!(self.output, "{})", items(event.stack))?,
+        }
+        Ok(())
+    }
+}
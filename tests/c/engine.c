@@ -0,0 +1,60 @@
+/* Runs the C API of stacky, exits with 1 and names the failed check if one fails. */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "stacky.h"
+
+#define CHECK(condition)                                               \
+    do {                                                               \
+        if (!(condition)) {                                            \
+            fprintf(stderr, "%s:%d: %s\n", __FILE__, __LINE__, #condition); \
+            return 1;                                                  \
+        }                                                              \
+    } while (0)
+
+int main(void) {
+    StackyEngine *engine = stacky_engine_new();
+    CHECK(engine != NULL);
+
+    /* eval keeps what the code printed */
+    CHECK(stacky_eval(engine, "1 2 + .") == 0);
+    CHECK(strcmp(stacky_output(engine), "3") == 0);
+    CHECK(stacky_last_error(engine) == NULL);
+
+    /* words defined by eval can be used with pushed numbers */
+    CHECK(stacky_eval(engine, ": Square DUP * ;") == 0);
+    stacky_push_int(engine, 7);
+    CHECK(stacky_eval(engine, "Square") == 0);
+    int64_t number = 0;
+    CHECK(stacky_pop_int(engine, &number) == 0);
+    CHECK(number == 49);
+
+    /* strings go both ways and popped ones belong to the caller */
+    CHECK(stacky_push_string(engine, "hello") == 0);
+    CHECK(stacky_eval(engine, "\" world\" +") == 0);
+    char *text = stacky_pop_string(engine);
+    CHECK(text != NULL);
+    CHECK(strcmp(text, "hello world") == 0);
+    stacky_string_free(text);
+
+    /* errors are reported with a message */
+    CHECK(stacky_eval(engine, "1 0 /") == -1);
+    CHECK(stacky_last_error(engine) != NULL);
+    CHECK(strstr(stacky_last_error(engine), "Cannot divide by zero") != NULL);
+    CHECK(stacky_pop_int(engine, &number) == -1);
+    CHECK(strstr(stacky_last_error(engine), "Stack underflow") != NULL);
+
+    /* an item of the wrong kind stays on the stack */
+    CHECK(stacky_push_string(engine, "seven") == 0);
+    CHECK(stacky_pop_int(engine, &number) == -1);
+    CHECK(strstr(stacky_last_error(engine), "Expected a number") != NULL);
+    text = stacky_pop_string(engine);
+    CHECK(text != NULL);
+    CHECK(strcmp(text, "seven") == 0);
+    stacky_string_free(text);
+    CHECK(stacky_last_error(engine) == NULL);
+
+    stacky_engine_free(engine);
+    return 0;
+}
@@ -0,0 +1,60 @@
+/* A plugin with words that use every function of the plugin API, built with -DABI_VERSION=n
+   it claims another ABI version. */
+
+#include <stdlib.h>
+#include <string.h>
+
+#include "stacky.h"
+
+#ifndef ABI_VERSION
+#define ABI_VERSION STACKY_PLUGIN_ABI_VERSION
+#endif
+
+static const StackyPluginApi *stacky;
+
+/* ( a b -- a+b ) */
+static int add(StackyStack *stack) {
+    int64_t a, b;
+    if (stacky->pop_int(stack, &b) != 0 || stacky->pop_int(stack, &a) != 0) {
+        return 1;
+    }
+    stacky->push_int(stack, a + b);
+    return 0;
+}
+
+/* ( text -- length text ) */
+static int shout(StackyStack *stack) {
+    char *text = stacky->pop_string(stack);
+    if (text == NULL) {
+        return 1;
+    }
+    for (char *c = text; *c != '\0'; c++) {
+        if (*c >= 'a' && *c <= 'z') {
+            *c -= 'a' - 'A';
+        }
+    }
+    stacky->push_int(stack, (int64_t)strlen(text));
+    int status = stacky->push_string(stack, text);
+    stacky->string_free(text);
+    return status;
+}
+
+/* ( -- ) */
+static int broken(StackyStack *stack) {
+    stacky->fail(stack, "broken is broken");
+    return 1;
+}
+
+uint32_t stacky_plugin_abi_version(void) {
+    return ABI_VERSION;
+}
+
+int stacky_plugin_init(const StackyPluginApi *api, StackyRegistrar *registrar) {
+    stacky = api;
+    if (api->register_word(registrar, "Plus", 2, add) != 0 ||
+        api->register_word(registrar, "Shout", 1, shout) != 0 ||
+        api->register_word(registrar, "Broken", 0, broken) != 0) {
+        return 1;
+    }
+    return 0;
+}
@@ -0,0 +1,152 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use rust_stacky::{Engine, Value};
+
+/// the directory of the library, the test binary is in target/<profile>/deps next to it
+fn lib_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().parent().unwrap().to_path_buf()
+}
+
+/// the header in include/ is checked in, so it has to match the one generated by the build
+#[test]
+fn checked_in_header_is_current() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let generated = fs::read_to_string(Path::new(env!("OUT_DIR")).join("stacky.h")).unwrap();
+    let checked_in = fs::read_to_string(root.join("include/stacky.h")).unwrap();
+    assert!(
+        generated == checked_in,
+        "include/stacky.h is out of date, update it with STACKY_UPDATE_HEADER=1 cargo build"
+    );
+}
+
+/// compiles a C file in tests/c to `output`, none if there is no C compiler
+fn compile(source: &str, output: &Path, args: &[&str]) -> Option<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let output = lib_dir().join(output);
+    let compiled = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(root.join("tests/c").join(source))
+        .arg("-I")
+        .arg(env!("OUT_DIR"))
+        .args(args)
+        .arg("-o")
+        .arg(&output)
+        .output();
+    let compiled = match compiled {
+        Ok(compiled) => compiled,
+        Err(error) => {
+            eprintln!("skipped, no C compiler: {}", error);
+            return None;
+        }
+    };
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+    Some(output)
+}
+
+/// compiles tests/c/plugin.c as a shared library claiming the ABI version, every test uses its
+/// own name as the tests run at the same time
+fn plugin(name: &str, version: u32) -> Option<PathBuf> {
+    let define = format!("-DABI_VERSION={}", version);
+    let name = format!("libstacky_{}.so", name);
+    compile("plugin.c", Path::new(&name), &["-shared", "-fPIC", &define])
+}
+
+#[test]
+fn c_program_uses_the_engine() {
+    let lib_dir = lib_dir();
+    let rpath = format!("-Wl,-rpath,{}", lib_dir.display());
+    let lib_dir = format!("-L{}", lib_dir.display());
+    let args = [lib_dir.as_str(), &rpath, "-lrust_stacky"];
+    let Some(program) = compile("engine.c", Path::new("stacky_c_test"), &args) else {
+        return;
+    };
+
+    let ran = Command::new(&program).output().unwrap();
+    assert!(
+        ran.status.success(),
+        "{}",
+        String::from_utf8_lossy(&ran.stderr)
+    );
+}
+
+#[test]
+fn plugin_words_are_registered() {
+    let Some(path) = plugin("registered", rust_stacky::PLUGIN_ABI_VERSION) else {
+        return;
+    };
+    let mut engine = Engine::new();
+    engine.load_plugin(&path).unwrap();
+
+    assert_eq!(engine.eval("40 2 Plus .").unwrap(), "42");
+    engine.eval("\"plugin\" Shout").unwrap();
+    assert_eq!(
+        engine.stack(),
+        vec![Value::Number(6), Value::from("PLUGIN")]
+    );
+
+    let error = engine.eval("Broken").unwrap_err();
+    assert!(error.to_string().contains("broken is broken"), "{}", error);
+    let error = engine.eval("\"x\" 1 Plus").unwrap_err();
+    assert!(error.to_string().contains("Expected a number"), "{}", error);
+}
+
+#[test]
+fn plugin_is_loaded_from_code() {
+    let Some(path) = plugin("from_code", rust_stacky::PLUGIN_ABI_VERSION) else {
+        return;
+    };
+    let mut engine = Engine::new();
+    let source = format!("{:?} PLUGIN 1 2 Plus .", path.display().to_string());
+    assert_eq!(engine.eval(&source).unwrap(), "3");
+
+    // plugin words are native words, so they are kept by reset
+    engine.reset();
+    assert_eq!(engine.eval("3 4 Plus .").unwrap(), "7");
+
+    let mut engine = Engine::new();
+    engine.set_file_access(false);
+    let error = engine.eval(&source).unwrap_err();
+    assert!(
+        error.to_string().contains("PLUGIN needs file access"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn plugin_with_another_abi_version_is_rejected() {
+    let Some(path) = plugin("another_version", rust_stacky::PLUGIN_ABI_VERSION + 1) else {
+        return;
+    };
+    let mut engine = Engine::new();
+    let error = engine.load_plugin(&path).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("is built for ABI version 2, this stacky has version 1"),
+        "{}",
+        error
+    );
+    assert!(engine.eval("1 2 Plus").is_err());
+}
+
+#[test]
+fn missing_plugin_is_an_error() {
+    let mut engine = Engine::new();
+    let error = engine.load_plugin("no/such/plugin.so").unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("Cannot load plugin no/such/plugin.so"),
+        "{}",
+        error
+    );
+}